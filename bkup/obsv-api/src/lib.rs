@@ -1,41 +1,291 @@
 //! This crate provides a simple HTTP server for the API
 
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use obsv_core::db::DbClient;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default time range of the queries (from now)
+const DEFAULT_WINDOW: Duration = Duration::from_secs(3600);
 
 /// API server
 #[derive(Debug)]
 pub struct ApiServer {
     addr: SocketAddr,
+    db: Option<Arc<dyn DbClient>>,
 }
 
 impl ApiServer {
     /// Creates a new Server
     pub fn new(addr: SocketAddr) -> Self {
-        Self { addr }
+        Self { addr, db: None }
+    }
+
+    /// Sets the DB client serving the data
+    pub fn db(mut self, db: Arc<dyn DbClient>) -> Self {
+        self.db = Some(db);
+        self
     }
 
     /// Starts the server
     pub async fn start(self) {
-        let make_svc =
-            make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle_req)) });
+        let db = self.db;
+        let make_svc = make_service_fn(move |_conn| {
+            let db = db.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle_req(req, db.clone()))) }
+        });
         let server = hyper::Server::bind(&self.addr).serve(make_svc);
         server.await.unwrap();
     }
 }
 
 /// Handler
-async fn handle_req(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    match (req.method(), req.uri().path()) {
-        (&Method::GET, "/") => Ok(Response::builder()
+async fn handle_req(
+    req: Request<Body>,
+    db: Option<Arc<dyn DbClient>>,
+) -> Result<Response<Body>, Infallible> {
+    match (req.method(), req.uri().path(), db) {
+        (&Method::GET, "/", _) => Ok(Response::builder()
             .status(StatusCode::OK)
             .body(Body::empty())
             .unwrap()),
+        (&Method::GET, "/service-graph", Some(db)) => {
+            let query = match graph_query(&req, unix_now()) {
+                Ok(query) => query,
+                Err(err) => return Ok(error(StatusCode::BAD_REQUEST, &err)),
+            };
+            match db.get_service_graph(query.from, query.to).await {
+                // NB: the calls from or to the service are kept, including the other services
+                Ok(graph) => match &query.service {
+                    Some(service) => Ok(json(&graph.service(service))),
+                    None => Ok(json(&graph)),
+                },
+                Err(err) => Ok(error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string())),
+            }
+        }
+        (&Method::GET, path, Some(db)) if trace_logs_id(path).is_some() => {
+            let Some(Ok(trace_id)) = trace_logs_id(path).map(|id| u128::from_str_radix(id, 16))
+            else {
+                return Ok(error(StatusCode::BAD_REQUEST, "invalid trace ID"));
+            };
+            match db.get_trace_logs(trace_id).await {
+                Ok(logs) => Ok(json(&logs)),
+                Err(err) => Ok(error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string())),
+            }
+        }
         _ => Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Body::empty())
             .unwrap()),
     }
 }
+
+/// Returns the trace ID of a `/traces/{trace_id}/logs` path
+fn trace_logs_id(path: &str) -> Option<&str> {
+    path.strip_prefix("/traces/")?.strip_suffix("/logs")
+}
+
+/// Service graph query
+#[derive(Debug, PartialEq)]
+struct GraphQuery {
+    /// Service name
+    service: Option<String>,
+    /// Start of the time range (UNIX nanoseconds)
+    from: i128,
+    /// End of the time range (UNIX nanoseconds)
+    to: i128,
+}
+
+/// Parses a service graph query from the query parameters (`service`, `from` and `to`)
+///
+/// The time range is bounded, and defaults to the last hour.
+fn graph_query(req: &Request<Body>, now: i128) -> Result<GraphQuery, String> {
+    let params: HashMap<String, String> =
+        serde_urlencoded::from_str(req.uri().query().unwrap_or_default())
+            .map_err(|err| format!("invalid query: {err}"))?;
+    let timestamp = |key: &str| {
+        params
+            .get(key)
+            .map(|v| v.parse::<i128>())
+            .transpose()
+            .map_err(|_| format!("invalid '{key}' timestamp"))
+    };
+    let window = DEFAULT_WINDOW.as_nanos() as i128;
+    let (from, to) = match (timestamp("from")?, timestamp("to")?) {
+        (Some(from), Some(to)) => (from, to),
+        (Some(from), None) => (from, from.saturating_add(window)),
+        (None, Some(to)) => (to.saturating_sub(window), to),
+        (None, None) => (now - window, now),
+    };
+    if from >= to {
+        return Err("'from' must be before 'to'".to_string());
+    }
+    Ok(GraphQuery {
+        service: params.get("service").cloned(),
+        from,
+        to,
+    })
+}
+
+/// Returns the current time (UNIX nanoseconds)
+fn unix_now() -> i128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i128
+}
+
+/// Returns a JSON response
+fn json<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+    }
+}
+
+/// Returns an error response
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::{
+        data::{Log, LogData, Service, ServiceGraph, Span, TraceData},
+        db::memory::MemDbClient,
+    };
+
+    use super::*;
+
+    /// Returns a span
+    fn span(id: u64, parent_id: Option<u64>) -> Span {
+        Span {
+            id,
+            parent_id,
+            trace_id: 1,
+            name: "span".to_string(),
+            start: 0,
+            end: 10,
+            ..Default::default()
+        }
+    }
+
+    /// Sends a GET request
+    async fn get(db: &Arc<dyn DbClient>, uri: &str) -> (StatusCode, Vec<u8>) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = handle_req(req, Some(db.clone())).await.unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn api_service_graph() {
+        let db: Arc<dyn DbClient> = Arc::new(MemDbClient::new());
+        let mut data = TraceData::default();
+        for (service, span) in [
+            ("web", span(1, None)),
+            ("api", span(2, Some(1))),
+            ("db", span(3, Some(2))),
+        ] {
+            let service = Service {
+                name: service.to_string(),
+                attrs: HashMap::new(),
+            };
+            data.add_span(&service, None, span);
+        }
+        let graph = ServiceGraph::from_traces(&data);
+        db.insert_metrics(graph.to_metrics(0, 100)).await.unwrap();
+
+        let (status, body) = get(&db, "/service-graph?from=0&to=150").await;
+        assert_eq!(status, StatusCode::OK);
+        let graph: ServiceGraph = serde_json::from_slice(&body).unwrap();
+        assert_eq!(graph.services, vec!["api", "db", "web"]);
+        assert_eq!(graph.edges.len(), 2);
+
+        // NB: the edges of the service are kept, with the other services
+        let (_, body) = get(&db, "/service-graph?from=0&to=150&service=web").await;
+        let graph: ServiceGraph = serde_json::from_slice(&body).unwrap();
+        assert_eq!(graph.services, vec!["api", "web"]);
+        assert_eq!(graph.edges[0].client, "web");
+        assert_eq!(graph.edges[0].server, "api");
+
+        // NB: the default time range is the last hour
+        let (_, body) = get(&db, "/service-graph").await;
+        let graph: ServiceGraph = serde_json::from_slice(&body).unwrap();
+        assert!(graph.edges.is_empty());
+
+        let (status, _) = get(&db, "/service-graph?from=abc").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get(&db, "/service-graph?from=2&to=1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn api_trace_logs() {
+        let db: Arc<dyn DbClient> = Arc::new(MemDbClient::new());
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut logs = LogData::default();
+        for trace_id in [0xab, 0xcd] {
+            logs.add_log(
+                &service,
+                None,
+                Log {
+                    trace_id,
+                    span_id: 1,
+                    timestamp: 1,
+                    level: 9,
+                    message: format!("log {trace_id:x}"),
+                    attrs: HashMap::new(),
+                },
+            );
+        }
+        db.insert_logs(logs).await.unwrap();
+
+        let (status, body) = get(&db, &format!("/traces/{:032x}/logs", 0xab)).await;
+        assert_eq!(status, StatusCode::OK);
+        let logs: LogData = serde_json::from_slice(&body).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs.logs[0].logs[0].message, "log ab");
+
+        let (status, _) = get(&db, "/traces/xyz/logs").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn api_graph_query_window() {
+        let hour = DEFAULT_WINDOW.as_nanos() as i128;
+        let req = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        assert_eq!(
+            graph_query(&req("/service-graph"), hour * 2).unwrap(),
+            GraphQuery {
+                service: None,
+                from: hour,
+                to: hour * 2
+            }
+        );
+        assert_eq!(
+            graph_query(&req("/service-graph?from=10&service=api"), 0).unwrap(),
+            GraphQuery {
+                service: Some("api".to_string()),
+                from: 10,
+                to: 10 + hour
+            }
+        );
+    }
+}
@@ -26,6 +26,9 @@ async fn main() {
         .receiver(grpc_receiver)
         .exporter(stdout_exporter)
         .exporter(file_exporter)
+        .shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
         .start()
         .await;
 }
@@ -0,0 +1,102 @@
+//! DB exporter
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use obsv_core::{db::DbClient, error::Error};
+
+use crate::Data;
+
+use super::{ExportError, Exporter};
+
+/// DB exporter
+///
+/// The data is inserted in a DB (eg. Clickhouse).
+#[derive(Clone)]
+pub struct DbExporter {
+    /// DB client
+    db: Arc<dyn DbClient>,
+}
+
+impl DbExporter {
+    /// Creates a new [DbExporter]
+    pub fn new(db: impl DbClient + 'static) -> Self {
+        Self { db: Arc::new(db) }
+    }
+
+    /// Creates a new [DbExporter] for a Clickhouse DB
+    #[cfg(feature = "clickhouse")]
+    pub fn clickhouse(url: &str, db: &str) -> Result<Self, Error> {
+        use clickhouse_client::{intf::http::Http, HttpClient};
+        use obsv_core::db::clickhouse::ChClient;
+
+        let client = HttpClient {
+            db: None,
+            credentials: None,
+            interface: Http::new(url),
+        }
+        .database(db);
+        Ok(Self::new(ChClient::new(client)?))
+    }
+
+    /// Initializes the DB
+    pub async fn init(&self) -> Result<(), Error> {
+        self.db.init().await
+    }
+}
+
+#[async_trait]
+impl Exporter for DbExporter {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+        log::trace!("exporting to DB");
+        for d in data {
+            match d.clone() {
+                Data::Traces(traces) => self.db.insert_traces(traces).await?,
+                Data::Logs(logs) => self.db.insert_logs(logs).await?,
+                Data::Metrics(metrics) => self.db.insert_metrics(metrics).await?,
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::{
+        data::{Log, LogData, Service, ServiceLogs},
+        db::{memory::MemDbClient, LogQuery},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn db_export() {
+        let db = Arc::new(MemDbClient::new());
+        let exporter = DbExporter { db: db.clone() };
+        exporter.init().await.unwrap();
+        let logs = LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: vec![Log {
+                    trace_id: 0,
+                    span_id: 0,
+                    timestamp: 1,
+                    level: 9,
+                    message: "hello".to_string(),
+                    attrs: HashMap::new(),
+                }],
+            }],
+        };
+        exporter
+            .export(&[Data::Logs(logs.clone()), Data::Traces(Default::default())])
+            .await
+            .unwrap();
+        assert_eq!(db.search_logs(&LogQuery::new()).await.unwrap(), logs);
+    }
+}
@@ -10,9 +10,9 @@ use async_trait::async_trait;
 
 use crate::Data;
 
-use super::Exporter;
+use super::{format_data, ExportError, Exporter};
 
-/// Stdout exporter
+/// File exporter
 #[derive(Debug, Clone)]
 pub struct FileExporter {
     /// File path
@@ -30,23 +30,25 @@ impl FileExporter {
 
 #[async_trait]
 impl Exporter for FileExporter {
-    async fn export(&self, data: &Vec<Data>) {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
         log::trace!("exporting");
         let mut file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.path)
-            .unwrap();
-        let content = data
-            .into_iter()
-            .map(|d| format_data(d))
-            .collect::<Vec<_>>()
-            .join("\n");
-        file.write_all(content.as_bytes()).unwrap();
+            .map_err(|err| {
+                ExportError::retryable(format!("Cannot open file {}: {err}", self.path.display()))
+            })?;
+        let mut content = String::new();
+        for d in data {
+            content.push_str(&format_data(d));
+            content.push('\n');
+        }
+        file.write_all(content.as_bytes()).map_err(|err| {
+            ExportError::retryable(format!(
+                "Cannot write to file {}: {err}",
+                self.path.display()
+            ))
+        })
     }
 }
-
-/// Formats Data into a string
-fn format_data(data: &Data) -> String {
-    todo!("implement data formatting for stdout");
-}
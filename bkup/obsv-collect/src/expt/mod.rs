@@ -2,22 +2,81 @@
 //!
 //! The exporter is responsible for exporting the received data
 
+use std::fmt;
+
 use async_trait::async_trait;
 use dyn_clone::DynClone;
+use obsv_core::error::Error;
 
 use crate::Data;
 
+pub mod db;
 pub mod file;
+#[cfg(any(feature = "grpc", feature = "http"))]
+pub mod otlp;
+pub mod retry;
 pub mod stdout;
-
-#[cfg(feature = "clickhouse")]
-pub mod clickhouse;
+pub mod wal;
 
 /// Exporter
 #[async_trait]
 pub trait Exporter: Send + Sync + DynClone {
     /// Exports data
-    async fn export(&self, data: &Vec<Data>);
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError>;
+
+    /// Performs periodic work (eg. retrying failed exports)
+    ///
+    /// This is called periodically.
+    async fn tick(&self) {}
 }
 
 dyn_clone::clone_trait_object!(Exporter);
+
+/// Export error
+#[derive(Debug)]
+pub enum ExportError {
+    /// The export may succeed later (eg. the backend is unavailable)
+    Retryable(Error),
+    /// The export will never succeed (eg. the data is invalid)
+    Permanent(Error),
+}
+
+impl ExportError {
+    /// Creates a retryable error
+    pub fn retryable(message: String) -> Self {
+        Self::Retryable(Error::string(message))
+    }
+
+    /// Creates a permanent error
+    pub fn permanent(message: String) -> Self {
+        Self::Permanent(Error::string(message))
+    }
+
+    /// Checks if the export can be retried
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Retryable(_))
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Retryable(err) => write!(f, "{err} (retryable)"),
+            Self::Permanent(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+// NB: errors are retryable by default
+impl From<Error> for ExportError {
+    fn from(value: Error) -> Self {
+        Self::Retryable(value)
+    }
+}
+
+/// Formats Data into a string (JSON)
+fn format_data(data: &Data) -> String {
+    serde_json::to_string(data).unwrap_or_else(|err| format!("Cannot format data: {err}"))
+}
@@ -0,0 +1,487 @@
+//! OTLP exporter
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use obsv_core::error::Error;
+use obsv_otlp::proto::collector::{
+    logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+    trace::v1::ExportTraceServiceRequest,
+};
+
+use crate::Data;
+
+use super::{ExportError, Exporter};
+
+/// OTLP exporter
+///
+/// The data is forwarded to another OTLP endpoint, with gRPC or HTTP (protobuf or JSON).
+///
+/// When the endpoint partially accepts the data, the rejected items are not retried (as per the OTLP specs),
+/// and they are counted.
+///
+/// NB: each data is sent in its own request, so the retry of a failed export may send some data twice.
+#[derive(Clone)]
+pub struct OtlpExporter {
+    /// Endpoint (eg. http://localhost:4317)
+    endpoint: String,
+    /// Transport
+    transport: Transport,
+    /// Headers
+    headers: Vec<(String, String)>,
+    /// Is the request body compressed
+    gzip: bool,
+    /// Number of items rejected by the endpoint
+    rejected: Arc<AtomicU64>,
+}
+
+/// OTLP transport
+#[derive(Clone)]
+enum Transport {
+    /// gRPC
+    #[cfg(feature = "grpc")]
+    Grpc(tonic::transport::Channel),
+    /// HTTP
+    #[cfg(feature = "http")]
+    Http {
+        /// Client
+        client: hyper::Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
+        /// Content type (protobuf or JSON)
+        content_type: &'static str,
+    },
+}
+
+/// Items rejected by the endpoint (count, error message)
+type Rejected = Option<(i64, String)>;
+
+impl OtlpExporter {
+    /// Creates a new [OtlpExporter] for a gRPC endpoint
+    ///
+    /// The connection is established on the first export.
+    #[cfg(feature = "grpc")]
+    pub fn grpc(endpoint: &str) -> Result<Self, Error> {
+        use tonic::transport::{ClientTlsConfig, Endpoint};
+
+        let mut channel = Endpoint::from_shared(endpoint.to_string())
+            .map_err(|err| Error::string(format!("Invalid gRPC endpoint {endpoint}: {err}")))?;
+        if endpoint.starts_with("https://") {
+            channel = channel
+                .tls_config(ClientTlsConfig::new())
+                .map_err(|err| Error::string(format!("Invalid TLS config: {err}")))?;
+        }
+        Ok(Self::new(endpoint, Transport::Grpc(channel.connect_lazy())))
+    }
+
+    /// Creates a new [OtlpExporter] for an HTTP endpoint
+    ///
+    /// The content type is either protobuf or JSON.
+    #[cfg(feature = "http")]
+    pub fn http(endpoint: &str, content_type: &str) -> Result<Self, Error> {
+        use obsv_otlp::server::http::{APPLICATION_JSON, APPLICATION_PROTOBUF};
+
+        let content_type = match content_type {
+            APPLICATION_PROTOBUF => APPLICATION_PROTOBUF,
+            APPLICATION_JSON => APPLICATION_JSON,
+            _ => {
+                return Err(Error::string(format!(
+                    "Invalid content type: {content_type}"
+                )))
+            }
+        };
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client = hyper::Client::builder().build(connector);
+        Ok(Self::new(
+            endpoint.trim_end_matches('/'),
+            Transport::Http {
+                client,
+                content_type,
+            },
+        ))
+    }
+
+    /// Creates a new [OtlpExporter]
+    fn new(endpoint: &str, transport: Transport) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            transport,
+            headers: vec![],
+            gzip: false,
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Adds a header (eg. for authentication)
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_lowercase(), value.to_string()));
+        self
+    }
+
+    /// Compresses the requests with gzip
+    pub fn gzip(mut self) -> Self {
+        self.gzip = true;
+        self
+    }
+
+    /// Returns the number of items rejected by the endpoint
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl Exporter for OtlpExporter {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+        log::trace!("exporting to OTLP endpoint {}", self.endpoint);
+        for d in data.iter().filter(|d| !d.is_empty()) {
+            let rejected = match &self.transport {
+                #[cfg(feature = "grpc")]
+                Transport::Grpc(channel) => self.export_grpc(channel.clone(), d.clone()).await?,
+                #[cfg(feature = "http")]
+                Transport::Http {
+                    client,
+                    content_type,
+                } => self.export_http(client, content_type, d.clone()).await?,
+            };
+            if let Some((count, message)) = rejected {
+                log::warn!("OTLP endpoint rejected {count} items: {message}");
+                self.rejected
+                    .fetch_add(count.max(0) as u64, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the rejected items of a response
+macro_rules! rejected {
+    ($res:expr, $field:ident) => {
+        $res.partial_success
+            .map(|p| (p.$field, p.error_message))
+            .filter(|(count, message)| *count > 0 || !message.is_empty())
+    };
+}
+
+#[cfg(feature = "grpc")]
+impl OtlpExporter {
+    /// Exports to a gRPC endpoint
+    async fn export_grpc(
+        &self,
+        channel: tonic::transport::Channel,
+        data: Data,
+    ) -> Result<Rejected, ExportError> {
+        use obsv_otlp::proto::collector::{
+            logs::v1::logs_service_client::LogsServiceClient,
+            metrics::v1::metrics_service_client::MetricsServiceClient,
+            trace::v1::trace_service_client::TraceServiceClient,
+        };
+        use tonic::codec::CompressionEncoding;
+
+        let encoding = self.gzip.then_some(CompressionEncoding::Gzip);
+        Ok(match data {
+            Data::Traces(data) => {
+                let mut client = TraceServiceClient::new(channel);
+                if let Some(encoding) = encoding {
+                    client = client.send_compressed(encoding);
+                }
+                let req = self.grpc_request(ExportTraceServiceRequest::from(data))?;
+                let res = client.export(req).await.map_err(grpc_error)?;
+                rejected!(res.into_inner(), rejected_spans)
+            }
+            Data::Logs(data) => {
+                let mut client = LogsServiceClient::new(channel);
+                if let Some(encoding) = encoding {
+                    client = client.send_compressed(encoding);
+                }
+                let req = self.grpc_request(ExportLogsServiceRequest::from(data))?;
+                let res = client.export(req).await.map_err(grpc_error)?;
+                rejected!(res.into_inner(), rejected_log_records)
+            }
+            Data::Metrics(data) => {
+                let mut client = MetricsServiceClient::new(channel);
+                if let Some(encoding) = encoding {
+                    client = client.send_compressed(encoding);
+                }
+                let req = self.grpc_request(ExportMetricsServiceRequest::from(data))?;
+                let res = client.export(req).await.map_err(grpc_error)?;
+                rejected!(res.into_inner(), rejected_data_points)
+            }
+        })
+    }
+
+    /// Creates a gRPC request, with the headers as metadata
+    fn grpc_request<T>(&self, message: T) -> Result<tonic::Request<T>, ExportError> {
+        use tonic::metadata::{MetadataKey, MetadataValue};
+
+        let mut req = tonic::Request::new(message);
+        for (name, value) in &self.headers {
+            let key = MetadataKey::from_bytes(name.as_bytes())
+                .map_err(|err| ExportError::permanent(format!("Invalid header {name}: {err}")))?;
+            let value = MetadataValue::try_from(value.as_str())
+                .map_err(|err| ExportError::permanent(format!("Invalid header {name}: {err}")))?;
+            req.metadata_mut().insert(key, value);
+        }
+        Ok(req)
+    }
+}
+
+/// Converts a gRPC status to an export error
+///
+/// The retryable codes are defined in the OTLP specs.
+#[cfg(feature = "grpc")]
+fn grpc_error(status: tonic::Status) -> ExportError {
+    use tonic::Code;
+
+    let message = format!("gRPC error ({:?}): {}", status.code(), status.message());
+    match status.code() {
+        Code::Cancelled
+        | Code::DeadlineExceeded
+        | Code::ResourceExhausted
+        | Code::Aborted
+        | Code::OutOfRange
+        | Code::Unavailable
+        | Code::DataLoss => ExportError::retryable(message),
+        _ => ExportError::permanent(message),
+    }
+}
+
+#[cfg(feature = "http")]
+impl OtlpExporter {
+    /// Exports to an HTTP endpoint
+    async fn export_http(
+        &self,
+        client: &hyper::Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
+        content_type: &str,
+        data: Data,
+    ) -> Result<Rejected, ExportError> {
+        use obsv_otlp::{
+            proto::collector::{
+                logs::v1::ExportLogsServiceResponse, metrics::v1::ExportMetricsServiceResponse,
+                trace::v1::ExportTraceServiceResponse,
+            },
+            server::http::{ENDPOINT_LOGS, ENDPOINT_METRICS, ENDPOINT_TRACES},
+        };
+
+        Ok(match data {
+            Data::Traces(data) => {
+                let req = ExportTraceServiceRequest::from(data);
+                let res: ExportTraceServiceResponse = self
+                    .post(client, content_type, ENDPOINT_TRACES, req)
+                    .await?;
+                rejected!(res, rejected_spans)
+            }
+            Data::Logs(data) => {
+                let req = ExportLogsServiceRequest::from(data);
+                let res: ExportLogsServiceResponse =
+                    self.post(client, content_type, ENDPOINT_LOGS, req).await?;
+                rejected!(res, rejected_log_records)
+            }
+            Data::Metrics(data) => {
+                let req = ExportMetricsServiceRequest::from(data);
+                let res: ExportMetricsServiceResponse = self
+                    .post(client, content_type, ENDPOINT_METRICS, req)
+                    .await?;
+                rejected!(res, rejected_data_points)
+            }
+        })
+    }
+
+    /// Sends an OTLP request, and returns the response
+    async fn post<T, U>(
+        &self,
+        client: &hyper::Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
+        content_type: &str,
+        path: &str,
+        req: T,
+    ) -> Result<U, ExportError>
+    where
+        T: obsv_otlp::server::http::HttpConvert,
+        U: obsv_otlp::server::http::HttpConvert,
+    {
+        use std::io::Write;
+
+        use hyper::{
+            header::{CONTENT_ENCODING, CONTENT_TYPE},
+            Body, Method, Request,
+        };
+
+        let mut body = req
+            .into_http_body(content_type)
+            .map_err(ExportError::permanent)?;
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}{path}", self.endpoint))
+            .header(CONTENT_TYPE, content_type);
+        if self.gzip {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            body = encoder
+                .write_all(&body)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| ExportError::permanent(format!("Cannot compress body: {err}")))?;
+            builder = builder.header(CONTENT_ENCODING, "gzip");
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let req = builder
+            .body(Body::from(body))
+            .map_err(|err| ExportError::permanent(format!("Invalid HTTP request: {err}")))?;
+
+        let res = client
+            .request(req)
+            .await
+            .map_err(|err| ExportError::retryable(format!("HTTP request failed: {err}")))?;
+        let status = res.status();
+        let res_content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_string())
+            .unwrap_or_else(|| content_type.to_string());
+        let res_body = hyper::body::to_bytes(res.into_body())
+            .await
+            .map_err(|err| ExportError::retryable(format!("Cannot read HTTP response: {err}")))?;
+        if !status.is_success() {
+            return Err(http_error(status, &res_body));
+        }
+
+        // NB: the data has been accepted, even if the response cannot be decoded
+        if res_body.is_empty() {
+            return Ok(U::default());
+        }
+        Ok(
+            U::from_http_request(&res_content_type, &res_body).unwrap_or_else(|err| {
+                log::warn!("Invalid OTLP response: {err}");
+                U::default()
+            }),
+        )
+    }
+}
+
+/// Converts an HTTP error response to an export error
+///
+/// The retryable status codes are defined in the OTLP specs.
+#[cfg(feature = "http")]
+fn http_error(status: hyper::StatusCode, body: &[u8]) -> ExportError {
+    let message = format!("HTTP error ({status}): {}", String::from_utf8_lossy(body));
+    match status.as_u16() {
+        429 | 502 | 503 | 504 => ExportError::retryable(message),
+        _ => ExportError::permanent(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::TcpListener, sync::atomic::AtomicU64, time::Duration};
+
+    use obsv_core::data::{Log, LogData, Service, ServiceLogs};
+
+    use crate::{
+        queue::{queue, DataReceiver, QueueConfig},
+        recv::Receiver,
+    };
+
+    use super::*;
+
+    /// Returns logs
+    fn logs() -> LogData {
+        LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: vec![Log {
+                    trace_id: 0,
+                    span_id: 0,
+                    timestamp: 1,
+                    level: 9,
+                    message: "hello".to_string(),
+                    attrs: HashMap::new(),
+                }],
+            }],
+        }
+    }
+
+    /// Starts a receiver on a free port, and returns its address
+    fn start_receiver<R>(new: impl FnOnce(&str) -> R) -> (String, DataReceiver)
+    where
+        R: Receiver + 'static,
+    {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let receiver = new(&addr);
+        let (tx, rx) = queue(QueueConfig::default(), Arc::new(AtomicU64::new(0)));
+        tokio::spawn(async move { receiver.start(tx).await });
+        (addr, rx)
+    }
+
+    /// Exports data, while the receiver is starting
+    async fn export(exporter: &OtlpExporter, data: Data) {
+        for _ in 0..50 {
+            if exporter.export(std::slice::from_ref(&data)).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("cannot export to {}", exporter.endpoint);
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn otlp_http_export() {
+        use obsv_otlp::server::http::{APPLICATION_JSON, APPLICATION_PROTOBUF};
+
+        use crate::recv::http::HttpReceiver;
+
+        let (addr, mut rx) = start_receiver(HttpReceiver::new);
+        // NB: the service name is added to the resource attributes
+        let expected = LogData::try_from(ExportLogsServiceRequest::from(logs())).unwrap();
+        for content_type in [APPLICATION_PROTOBUF, APPLICATION_JSON] {
+            let exporter = OtlpExporter::http(&format!("http://{addr}/"), content_type)
+                .unwrap()
+                .header("Authorization", "Bearer token")
+                .gzip();
+            export(&exporter, Data::Logs(logs())).await;
+            assert_eq!(rx.recv().await, Some(Data::Logs(expected.clone())));
+            assert_eq!(exporter.rejected(), 0);
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn otlp_grpc_export() {
+        use crate::recv::grpc::GrpcReceiver;
+
+        let (addr, mut rx) = start_receiver(GrpcReceiver::new);
+        let expected = LogData::try_from(ExportLogsServiceRequest::from(logs())).unwrap();
+        let exporter = OtlpExporter::grpc(&format!("http://{addr}"))
+            .unwrap()
+            .header("Authorization", "Bearer token");
+        export(&exporter, Data::Logs(logs())).await;
+        assert_eq!(rx.recv().await, Some(Data::Logs(expected)));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn otlp_http_errors() {
+        use hyper::StatusCode;
+
+        assert!(http_error(StatusCode::SERVICE_UNAVAILABLE, b"").is_retryable());
+        assert!(http_error(StatusCode::TOO_MANY_REQUESTS, b"").is_retryable());
+        assert!(!http_error(StatusCode::BAD_REQUEST, b"invalid").is_retryable());
+        assert!(!http_error(StatusCode::INTERNAL_SERVER_ERROR, b"").is_retryable());
+    }
+}
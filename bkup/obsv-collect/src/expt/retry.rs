@@ -0,0 +1,393 @@
+//! Retry exporter
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::Data;
+
+use super::{ExportError, Exporter};
+
+/// Retry configuration
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Timeout of an export attempt
+    pub timeout: Duration,
+    /// Maximum number of retries
+    pub max_retries: usize,
+    /// Initial retry delay
+    pub initial_backoff: Duration,
+    /// Maximum retry delay
+    pub max_backoff: Duration,
+    /// Number of consecutive failed exports which opens the circuit
+    pub failure_threshold: usize,
+    /// Time after which an open circuit lets an export through
+    pub reset_timeout: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Creates a new retry configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the timeout of an export attempt
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of retries, and the initial and maximum retry delays
+    pub fn retries(mut self, max_retries: usize, initial: Duration, max: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets the circuit breaker failure threshold and reset timeout
+    pub fn circuit_breaker(mut self, failure_threshold: usize, reset_timeout: Duration) -> Self {
+        self.failure_threshold = failure_threshold;
+        self.reset_timeout = reset_timeout;
+        self
+    }
+}
+
+/// Retry exporter
+///
+/// The inner exporter is called with a timeout, and the retryable errors are retried
+/// with an exponential backoff (with jitter).
+///
+/// After a number of consecutive failed exports, the circuit breaker opens and the exports fail
+/// immediately, until the reset timeout where a single export is let through.
+#[derive(Clone)]
+pub struct RetryExporter {
+    /// Inner exporter
+    exporter: Box<dyn Exporter>,
+    /// Configuration
+    config: RetryConfig,
+    /// Circuit breaker
+    breaker: Arc<Mutex<Circuit>>,
+}
+
+/// Export let through by the circuit breaker
+///
+/// NB: if the export is cancelled while the circuit is half-open, the permit records a failure
+/// when dropped, otherwise the circuit would stay half-open and reject all the exports.
+struct Permit<'a> {
+    /// Exporter
+    exporter: &'a RetryExporter,
+    /// Is the result not recorded yet (half-open circuit)
+    half_open: bool,
+}
+
+impl Permit<'_> {
+    /// Records the result of the export
+    fn record(mut self, success: bool) {
+        self.half_open = false;
+        self.exporter.record(success);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.half_open {
+            log::warn!("half-open export was cancelled");
+            self.exporter.record(false);
+        }
+    }
+}
+
+/// Circuit breaker state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Circuit {
+    /// The exports are let through
+    Closed {
+        /// Number of consecutive failed exports
+        failures: usize,
+    },
+    /// The exports fail immediately
+    Open {
+        /// Time at which an export is let through
+        until: Instant,
+    },
+    /// A single export is let through
+    HalfOpen,
+}
+
+impl RetryExporter {
+    /// Creates a new [RetryExporter]
+    pub fn new(exporter: impl Exporter + 'static, config: RetryConfig) -> Self {
+        Self {
+            exporter: Box::new(exporter),
+            config,
+            breaker: Arc::new(Mutex::new(Circuit::Closed { failures: 0 })),
+        }
+    }
+
+    /// Checks if the circuit breaker is open
+    pub fn is_open(&self) -> bool {
+        matches!(*self.circuit(), Circuit::Open { .. })
+    }
+
+    /// Locks the circuit breaker
+    fn circuit(&self) -> std::sync::MutexGuard<'_, Circuit> {
+        // NB: the lock is never held across a panic
+        self.breaker.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Checks if an export is let through
+    fn acquire(&self) -> Option<Permit<'_>> {
+        let mut circuit = self.circuit();
+        let half_open = match *circuit {
+            Circuit::Closed { .. } => false,
+            Circuit::Open { until } if Instant::now() >= until => {
+                log::info!("circuit breaker is half-open");
+                *circuit = Circuit::HalfOpen;
+                true
+            }
+            Circuit::Open { .. } | Circuit::HalfOpen => return None,
+        };
+        Some(Permit {
+            exporter: self,
+            half_open,
+        })
+    }
+
+    /// Records the result of an export
+    fn record(&self, success: bool) {
+        let mut circuit = self.circuit();
+        *circuit = match (*circuit, success) {
+            (_, true) => Circuit::Closed { failures: 0 },
+            (Circuit::Closed { failures }, false)
+                if failures + 1 < self.config.failure_threshold =>
+            {
+                Circuit::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, false) => {
+                log::warn!("circuit breaker is open");
+                Circuit::Open {
+                    until: Instant::now() + self.config.reset_timeout,
+                }
+            }
+        };
+    }
+
+    /// Exports with a timeout
+    async fn try_export(&self, data: &[Data]) -> Result<(), ExportError> {
+        match tokio::time::timeout(self.config.timeout, self.exporter.export(data)).await {
+            Ok(res) => res,
+            Err(_) => Err(ExportError::retryable(format!(
+                "export timed out after {}s",
+                self.config.timeout.as_secs_f64()
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl Exporter for RetryExporter {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+        let Some(permit) = self.acquire() else {
+            return Err(ExportError::retryable(
+                "circuit breaker is open".to_string(),
+            ));
+        };
+
+        let mut backoff = self.config.initial_backoff;
+        let mut retries = 0;
+        loop {
+            match self.try_export(data).await {
+                Ok(()) => {
+                    permit.record(true);
+                    return Ok(());
+                }
+                // NB: a permanent error is caused by the data, not by the exporter
+                Err(err @ ExportError::Permanent(_)) => {
+                    permit.record(true);
+                    return Err(err);
+                }
+                Err(err) if retries >= self.config.max_retries => {
+                    permit.record(false);
+                    return Err(err);
+                }
+                Err(err) => {
+                    log::warn!("export failed, retrying: {err}");
+                    tokio::time::sleep(jitter(backoff)).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    async fn tick(&self) {
+        self.exporter.tick().await;
+    }
+}
+
+/// Returns a random delay between half and the full delay
+fn jitter(delay: Duration) -> Duration {
+    // NB: each RandomState has random keys, so the hash of nothing is random
+    let random = RandomState::new().build_hasher().finish();
+    let ratio = (random >> 11) as f64 / (1u64 << 53) as f64;
+    delay.mul_f64(0.5 + ratio / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// An exporter which fails a number of times
+    #[derive(Clone, Default)]
+    struct TestExporter {
+        /// Number of remaining failures
+        failures: Arc<AtomicUsize>,
+        /// Are the failures permanent
+        permanent: bool,
+        /// Export duration
+        delay: Duration,
+        /// Number of calls
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl TestExporter {
+        /// Creates an exporter which fails a number of times
+        fn failing(failures: usize) -> Self {
+            Self {
+                failures: Arc::new(AtomicUsize::new(failures)),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Exporter for TestExporter {
+        async fn export(&self, _data: &[Data]) -> Result<(), ExportError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(self.delay).await;
+            let failures = self.failures.load(Ordering::Relaxed);
+            if failures == 0 {
+                return Ok(());
+            }
+            self.failures.store(failures - 1, Ordering::Relaxed);
+            if self.permanent {
+                Err(ExportError::permanent("invalid data".to_string()))
+            } else {
+                Err(ExportError::retryable("unavailable".to_string()))
+            }
+        }
+    }
+
+    #[test]
+    fn retry_jitter() {
+        for _ in 0..100 {
+            let delay = jitter(Duration::from_secs(2));
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_until_success() {
+        let inner = TestExporter::failing(2);
+        let exporter = RetryExporter::new(inner.clone(), RetryConfig::new());
+        exporter.export(&[]).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 3);
+
+        // NB: a permanent error is not retried
+        let inner = TestExporter {
+            permanent: true,
+            ..TestExporter::failing(1)
+        };
+        let exporter = RetryExporter::new(inner.clone(), RetryConfig::new());
+        let err = exporter.export(&[]).await.unwrap_err();
+        assert!(!err.is_retryable());
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_timeout() {
+        let inner = TestExporter {
+            delay: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let config = RetryConfig::new().timeout(Duration::from_secs(1)).retries(
+            1,
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        );
+        let exporter = RetryExporter::new(inner.clone(), config);
+        let err = exporter.export(&[]).await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_circuit_breaker() {
+        let inner = TestExporter::failing(2);
+        let config = RetryConfig::new()
+            .retries(0, Duration::ZERO, Duration::ZERO)
+            .circuit_breaker(2, Duration::from_secs(10));
+        let exporter = RetryExporter::new(inner.clone(), config);
+        assert!(exporter.export(&[]).await.is_err());
+        assert!(!exporter.is_open());
+        assert!(exporter.export(&[]).await.is_err());
+        assert!(exporter.is_open());
+
+        // NB: the exports fail immediately while the circuit is open
+        assert!(exporter.export(&[]).await.is_err());
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 2);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        exporter.export(&[]).await.unwrap();
+        assert!(!exporter.is_open());
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_circuit_breaker_cancelled() {
+        let inner = TestExporter {
+            delay: Duration::from_secs(1),
+            ..TestExporter::failing(1)
+        };
+        let config = RetryConfig::new()
+            .retries(0, Duration::ZERO, Duration::ZERO)
+            .circuit_breaker(1, Duration::from_secs(10));
+        let exporter = RetryExporter::new(inner.clone(), config);
+        assert!(exporter.export(&[]).await.is_err());
+        assert!(exporter.is_open());
+
+        // NB: the half-open export is cancelled, which reopens the circuit
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let res = tokio::time::timeout(Duration::from_millis(100), exporter.export(&[])).await;
+        assert!(res.is_err());
+        assert!(exporter.is_open());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        exporter.export(&[]).await.unwrap();
+        assert!(!exporter.is_open());
+    }
+}
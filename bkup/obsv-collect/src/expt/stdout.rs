@@ -4,7 +4,7 @@ use async_trait::async_trait;
 
 use crate::Data;
 
-use super::Exporter;
+use super::{format_data, ExportError, Exporter};
 
 /// Stdout exporter
 #[derive(Debug, Default, Clone)]
@@ -19,15 +19,11 @@ impl StdoutExporter {
 
 #[async_trait]
 impl Exporter for StdoutExporter {
-    async fn export(&self, data: &Vec<Data>) {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
         log::trace!("exporting");
         for d in data {
             eprintln!("{}", format_data(d));
         }
+        Ok(())
     }
 }
-
-/// Formats Data into a string
-fn format_data(data: &Data) -> String {
-    todo!("implement data formatting for stdout");
-}
@@ -0,0 +1,478 @@
+//! Write-ahead log exporter
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use obsv_core::error::Error;
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::Data;
+
+use super::{ExportError, Exporter};
+
+/// WAL file extension
+const WAL_EXT: &str = "wal";
+
+/// Extension of the batches which are being written
+const TMP_EXT: &str = "tmp";
+
+/// Extension of the batches which cannot be read
+const CORRUPT_EXT: &str = "corrupt";
+
+/// Extension of the batches which failed with a permanent error
+const FAILED_EXT: &str = "failed";
+
+/// WAL configuration
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    /// Directory
+    pub dir: PathBuf,
+    /// Maximum disk usage (bytes)
+    pub max_size: u64,
+    /// Initial retry delay
+    pub initial_backoff: Duration,
+    /// Maximum retry delay
+    pub max_backoff: Duration,
+}
+
+impl WalConfig {
+    /// Creates a new WAL configuration
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            max_size: 1024 * 1024 * 1024,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets the maximum disk usage (bytes)
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets the initial and maximum retry delays
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+}
+
+/// WAL exporter
+///
+/// Each batch is persisted to disk before being exported by the inner exporter,
+/// and it is deleted once exported. When the export fails, it is retried with an exponential backoff,
+/// and the pending batches are replayed in order on startup.
+///
+/// NB: the delivery is at-least-once, a batch may be exported again if the collector stops
+/// between the export and the deletion of the batch.
+/// The batches which cannot be read or exported (permanent error) are never deleted,
+/// they are set aside with the `.corrupt` and `.failed` extensions, and still count against the maximum disk usage.
+#[derive(Clone)]
+pub struct WalExporter {
+    /// Inner exporter
+    exporter: Box<dyn Exporter>,
+    /// Configuration
+    config: WalConfig,
+    /// State
+    state: Arc<Mutex<WalState>>,
+}
+
+/// WAL state
+#[derive(Debug)]
+struct WalState {
+    /// Pending batches (sequence number -> size)
+    batches: BTreeMap<u64, u64>,
+    /// Size of the batches set aside (corrupt or failed)
+    set_aside: u64,
+    /// Next sequence number
+    next_seq: u64,
+    /// Current retry delay
+    backoff: Duration,
+    /// Time of the next retry
+    retry_at: Option<Instant>,
+}
+
+impl WalState {
+    /// Returns the disk usage
+    fn size(&self) -> u64 {
+        self.batches.values().sum::<u64>() + self.set_aside
+    }
+
+    /// Sets a batch aside
+    fn set_aside(&mut self, seq: u64) {
+        if let Some(size) = self.batches.remove(&seq) {
+            self.set_aside += size;
+        }
+    }
+}
+
+impl WalExporter {
+    /// Creates a new [WalExporter]
+    ///
+    /// The pending batches in the WAL directory are exported on the next tick.
+    pub fn new(exporter: impl Exporter + 'static, config: WalConfig) -> Result<Self, Error> {
+        fs::create_dir_all(&config.dir).map_err(|err| {
+            Error::string(format!(
+                "Cannot create WAL dir {}: {err}",
+                config.dir.display()
+            ))
+        })?;
+
+        let mut batches = BTreeMap::new();
+        let mut set_aside = 0;
+        let mut next_seq = 0;
+        let entries = fs::read_dir(&config.dir).map_err(|err| {
+            Error::string(format!(
+                "Cannot read WAL dir {}: {err}",
+                config.dir.display()
+            ))
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let ext = path.extension().and_then(|ext| ext.to_str());
+            match ext {
+                Some(WAL_EXT | CORRUPT_EXT | FAILED_EXT) => {}
+                Some(TMP_EXT) => {
+                    // NB: a temporary batch was never acknowledged, since the write did not complete
+                    log::warn!("Removing partial WAL batch {}", path.display());
+                    if let Err(err) = fs::remove_file(&path) {
+                        log::error!("Cannot remove WAL batch {}: {err}", path.display());
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+            let Some(seq) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let size = entry.metadata().map(|m| m.len()).unwrap_or_default();
+            // NB: the batches set aside keep their sequence number, so it is never reused
+            next_seq = next_seq.max(seq + 1);
+            if ext == Some(WAL_EXT) {
+                batches.insert(seq, size);
+            } else {
+                set_aside += size;
+            }
+        }
+        if !batches.is_empty() {
+            log::info!("WAL: {} pending batches", batches.len());
+        }
+
+        Ok(Self {
+            exporter: Box::new(exporter),
+            state: Arc::new(Mutex::new(WalState {
+                batches,
+                set_aside,
+                next_seq,
+                backoff: config.initial_backoff,
+                retry_at: None,
+            })),
+            config,
+        })
+    }
+
+    /// Returns the number of pending batches
+    pub async fn pending(&self) -> usize {
+        self.state.lock().await.batches.len()
+    }
+
+    /// Returns the path of a batch
+    fn batch_path(&self, seq: u64) -> PathBuf {
+        self.config.dir.join(format!("{seq:020}.{WAL_EXT}"))
+    }
+
+    /// Persists a batch
+    fn write(&self, state: &mut WalState, data: &[Data]) -> Result<(), ExportError> {
+        let content = serde_json::to_vec(data)
+            .map_err(|err| ExportError::permanent(format!("Cannot serialize WAL batch: {err}")))?;
+        let size = content.len() as u64;
+        if state.size() + size > self.config.max_size {
+            return Err(ExportError::retryable("WAL is full".to_string()));
+        }
+
+        // NB: the batch is written to a temporary file, so a partial write is never replayed
+        let seq = state.next_seq;
+        let path = self.batch_path(seq);
+        let tmp_path = path.with_extension(TMP_EXT);
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&content)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        };
+        write().map_err(|err| {
+            ExportError::retryable(format!("Cannot write WAL batch {}: {err}", path.display()))
+        })?;
+
+        state.next_seq += 1;
+        state.batches.insert(seq, size);
+        Ok(())
+    }
+
+    /// Exports the pending batches, in order
+    ///
+    /// The export stops at the first failure, and is retried after the backoff delay.
+    async fn replay(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        if state.retry_at.is_some_and(|t| Instant::now() < t) {
+            return Ok(());
+        }
+
+        while let Some((&seq, _)) = state.batches.first_key_value() {
+            let path = self.batch_path(seq);
+            let data = match fs::read(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|c| serde_json::from_slice::<Vec<Data>>(&c).map_err(|e| e.to_string()))
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    // NB: an unreadable batch is set aside, so it does not block the others
+                    log::error!("Invalid WAL batch {}: {err}", path.display());
+                    let _ = fs::rename(&path, path.with_extension(CORRUPT_EXT));
+                    state.set_aside(seq);
+                    continue;
+                }
+            };
+
+            match self.exporter.export(&data).await {
+                Ok(()) => {}
+                Err(ExportError::Permanent(err)) => {
+                    // NB: the batch will never be exported, so it is set aside (instead of being lost)
+                    log::error!("WAL batch {} cannot be exported: {err}", path.display());
+                    let failed_path = path.with_extension(FAILED_EXT);
+                    if let Err(err) = fs::rename(&path, &failed_path) {
+                        log::error!("Cannot move WAL batch {}: {err}", path.display());
+                    }
+                    state.set_aside(seq);
+                    continue;
+                }
+                Err(err) => {
+                    let backoff = state.backoff;
+                    state.retry_at = Some(Instant::now() + backoff);
+                    state.backoff = (backoff * 2).min(self.config.max_backoff);
+                    return Err(Error::string(format!(
+                        "WAL export failed, retrying in {}s: {err}",
+                        backoff.as_secs_f64()
+                    )));
+                }
+            }
+
+            if let Err(err) = fs::remove_file(&path) {
+                log::error!("Cannot remove WAL batch {}: {err}", path.display());
+            }
+            state.batches.remove(&seq);
+            state.backoff = self.config.initial_backoff;
+            state.retry_at = None;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Exporter for WalExporter {
+    async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+        log::trace!("exporting to WAL");
+        {
+            let mut state = self.state.lock().await;
+            self.write(&mut state, data)?;
+        }
+
+        // NB: the batch is persisted, so a failed export is retried later
+        if let Err(err) = self.replay().await {
+            log::warn!("{err}");
+        }
+        Ok(())
+    }
+
+    async fn tick(&self) {
+        if let Err(err) = self.replay().await {
+            log::warn!("{err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex as StdMutex,
+        },
+    };
+
+    use obsv_core::data::{Log, LogData, Service, ServiceLogs};
+
+    use super::*;
+
+    /// An exporter which always fails with a permanent error
+    #[derive(Clone)]
+    struct RejectingExporter;
+
+    #[async_trait]
+    impl Exporter for RejectingExporter {
+        async fn export(&self, _data: &[Data]) -> Result<(), ExportError> {
+            Err(ExportError::permanent("invalid data".to_string()))
+        }
+    }
+
+    /// An exporter which fails when it is down
+    #[derive(Clone, Default)]
+    struct TestExporter {
+        /// Is the exporter up
+        up: Arc<AtomicBool>,
+        /// Exported data
+        data: Arc<StdMutex<Vec<Data>>>,
+    }
+
+    #[async_trait]
+    impl Exporter for TestExporter {
+        async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+            if !self.up.load(Ordering::Relaxed) {
+                return Err(ExportError::retryable("exporter is down".to_string()));
+            }
+            self.data.lock().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    /// Returns a log
+    fn log(timestamp: i128) -> Data {
+        Data::Logs(LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: vec![Log {
+                    trace_id: 0,
+                    span_id: 0,
+                    timestamp,
+                    level: 9,
+                    message: "hello".to_string(),
+                    attrs: HashMap::new(),
+                }],
+            }],
+        })
+    }
+
+    /// Returns an empty WAL directory
+    fn wal_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obsv-wal-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn wal_replay_on_restart() {
+        let dir = wal_dir("replay");
+        let exporter = TestExporter::default();
+        let wal = WalExporter::new(exporter.clone(), WalConfig::new(&dir)).unwrap();
+        wal.export(&[log(1)]).await.unwrap();
+        wal.export(&[log(2), log(3)]).await.unwrap();
+        assert_eq!(wal.pending().await, 2);
+        drop(wal);
+
+        // NB: the pending batches are exported in order after a restart
+        exporter.up.store(true, Ordering::Relaxed);
+        let wal = WalExporter::new(exporter.clone(), WalConfig::new(&dir)).unwrap();
+        assert_eq!(wal.pending().await, 2);
+        wal.tick().await;
+        assert_eq!(wal.pending().await, 0);
+        assert_eq!(*exporter.data.lock().unwrap(), vec![log(1), log(2), log(3)]);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wal_backoff() {
+        let dir = wal_dir("backoff");
+        let exporter = TestExporter::default();
+        let config = WalConfig::new(&dir).backoff(Duration::from_secs(1), Duration::from_secs(2));
+        let wal = WalExporter::new(exporter.clone(), config).unwrap();
+        wal.export(&[log(1)]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        wal.tick().await;
+        assert_eq!(wal.state.lock().await.backoff, Duration::from_secs(2));
+
+        // NB: the export is not retried before the backoff delay
+        exporter.up.store(true, Ordering::Relaxed);
+        wal.tick().await;
+        assert_eq!(wal.pending().await, 1);
+        tokio::time::advance(Duration::from_secs(2)).await;
+        wal.tick().await;
+        assert_eq!(wal.pending().await, 0);
+        assert_eq!(wal.state.lock().await.backoff, Duration::from_secs(1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wal_max_size() {
+        let dir = wal_dir("max_size");
+        let size = serde_json::to_vec(&[log(1)]).unwrap().len() as u64;
+        let config = WalConfig::new(&dir).max_size(size);
+        let wal = WalExporter::new(TestExporter::default(), config).unwrap();
+        wal.export(&[log(1)]).await.unwrap();
+        assert!(wal.export(&[log(2)]).await.is_err());
+        assert_eq!(wal.pending().await, 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wal_keeps_failed_batches() {
+        let dir = wal_dir("failed");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{:020}.{TMP_EXT}", 7)), b"[{").unwrap();
+
+        // NB: the partial batch is removed on startup
+        let wal = WalExporter::new(RejectingExporter, WalConfig::new(&dir)).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        wal.export(&[log(1)]).await.unwrap();
+        assert_eq!(wal.pending().await, 0);
+        let failed = fs::read(dir.join(format!("{:020}.{FAILED_EXT}", 0))).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<Data>>(&failed).unwrap(),
+            vec![log(1)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wal_failed_batches_on_restart() {
+        let dir = wal_dir("failed_restart");
+        let size = serde_json::to_vec(&[log(1)]).unwrap().len() as u64;
+        let config = WalConfig::new(&dir).max_size(2 * size);
+        let wal = WalExporter::new(RejectingExporter, config.clone()).unwrap();
+        wal.export(&[log(1)]).await.unwrap();
+        drop(wal);
+
+        // NB: the failed batch is not overwritten after a restart, and it counts against the maximum size
+        let wal = WalExporter::new(RejectingExporter, config).unwrap();
+        wal.export(&[log(2)]).await.unwrap();
+        assert!(wal.export(&[log(3)]).await.is_err());
+        for (seq, timestamp) in [(0, 1), (1, 2)] {
+            let failed = fs::read(dir.join(format!("{seq:020}.{FAILED_EXT}"))).unwrap();
+            assert_eq!(
+                serde_json::from_slice::<Vec<Data>>(&failed).unwrap(),
+                vec![log(timestamp)]
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,13 +6,34 @@
 //!
 //! - **http**: HTTP server
 //! - **grpc**: GRPC server
+//! - **clickhouse**: Clickhouse exporter
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use expt::Exporter;
+use obsv_core::data::{LogData, MetricsData, TraceData};
+use obsv_otlp::proto::collector::{
+    logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+    trace::v1::ExportTraceServiceRequest,
+};
 use proc::Processor;
+use prost::Message;
+use queue::{queue, DataReceiver, DataSender, QueueConfig, SendError};
 use recv::Receiver;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
 
 pub mod expt;
 pub mod proc;
+pub mod queue;
 pub mod recv;
 
 // Collector service
@@ -24,8 +45,56 @@ pub struct CollService {
     processors: Vec<Box<dyn Processor>>,
     /// Exporters
     exporters: Vec<Box<dyn Exporter>>,
+    /// Shutdown signal
+    shutdown: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    /// Queue configuration of the receivers
+    recv_queue: QueueConfig,
+    /// Queue configuration of the exporters
+    export_queue: QueueConfig,
+    /// Counters
+    stats: CollStats,
+}
+
+/// Collector counters
+///
+/// The counters are shared with the service, and updated while it runs.
+#[derive(Debug, Clone, Default)]
+pub struct CollStats {
+    /// Number of received items
+    received: Arc<AtomicU64>,
+    /// Number of items dropped by the receivers queue
+    dropped: Arc<AtomicU64>,
+    /// Number of items dropped by the exporters queues
+    dropped_exports: Arc<AtomicU64>,
+    /// Number of items which failed to be exported
+    failed_exports: Arc<AtomicU64>,
 }
 
+impl CollStats {
+    /// Returns the number of received items (spans, logs or metrics)
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of items dropped by the receivers queue
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of items dropped by the exporters queues
+    pub fn dropped_exports(&self) -> u64 {
+        self.dropped_exports.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of items which failed to be exported
+    pub fn failed_exports(&self) -> u64 {
+        self.failed_exports.load(Ordering::Relaxed)
+    }
+}
+
+/// Interval at which the processors are ticked
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
 impl CollService {
     /// Instantiates a new server
     pub fn new() -> Self {
@@ -49,84 +118,413 @@ impl CollService {
         self.exporters.push(Box::new(exporter));
         self
     }
+
+    /// Sets the queue configuration of the receivers and the exporters
+    ///
+    /// The receivers share a queue, and each exporter has its own queue.
+    pub fn queue(mut self, config: QueueConfig) -> Self {
+        self.recv_queue = config.clone();
+        self.export_queue = config;
+        self
+    }
+
+    /// Sets the configuration of the queue shared by the receivers
+    pub fn receiver_queue(mut self, config: QueueConfig) -> Self {
+        self.recv_queue = config;
+        self
+    }
+
+    /// Sets the configuration of the queue of each exporter
+    pub fn exporter_queue(mut self, config: QueueConfig) -> Self {
+        self.export_queue = config;
+        self
+    }
+
+    /// Returns the counters
+    pub fn stats(&self) -> CollStats {
+        self.stats.clone()
+    }
+
+    /// Sets the shutdown signal
+    ///
+    /// When the signal is received, the buffered data is flushed and exported.
+    pub fn shutdown(mut self, signal: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+        self
+    }
 }
 
 /// A piece of collection data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Data {
-    ToDo,
+    /// Traces
+    Traces(TraceData),
+    /// Logs
+    Logs(LogData),
+    /// Metrics
+    Metrics(MetricsData),
+}
+
+impl Data {
+    /// Returns the number of items (spans, logs or metrics)
+    pub fn len(&self) -> usize {
+        match self {
+            Data::Traces(data) => data.len(),
+            Data::Logs(data) => data.len(),
+            Data::Metrics(data) => data.len(),
+        }
+    }
+
+    /// Checks if there are no items
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of OTLP items (spans, log records or metric data points)
+    ///
+    /// NB: the OTLP partial successes count the rejected metric data points, not the metrics.
+    pub fn otlp_len(&self) -> usize {
+        match self {
+            Data::Metrics(data) => data
+                .metrics
+                .iter()
+                .flat_map(|g| g.metrics.iter())
+                .map(|m| m.data.len())
+                .sum(),
+            data => data.len(),
+        }
+    }
+
+    /// Returns the size of the data, encoded as an OTLP protobuf request
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Data::Traces(data) => ExportTraceServiceRequest::from(data).encoded_len(),
+            Data::Logs(data) => ExportLogsServiceRequest::from(data).encoded_len(),
+            Data::Metrics(data) => ExportMetricsServiceRequest::from(data).encoded_len(),
+        }
+    }
 }
 
-impl Default for Data {
-    fn default() -> Self {
-        Self::ToDo
+impl From<TraceData> for Data {
+    fn from(value: TraceData) -> Self {
+        Self::Traces(value)
+    }
+}
+
+impl From<LogData> for Data {
+    fn from(value: LogData) -> Self {
+        Self::Logs(value)
+    }
+}
+
+impl From<MetricsData> for Data {
+    fn from(value: MetricsData) -> Self {
+        Self::Metrics(value)
     }
 }
 
 impl CollService {
     /// Starts the service
+    ///
+    /// The service stops when the shutdown signal is received, or when all the receivers have stopped.
     pub async fn start(mut self) {
-        let mut tasks = vec![];
-
-        // NB: each receiver runs in its own task, which sends each received data for processing
-        let (recv_tx, mut recv_rx) = tokio::sync::mpsc::unbounded_channel::<Data>();
+        // NB: each receiver runs in its own task, which sends each received data to the queue
+        let (recv_tx, mut recv_rx) = queue(self.recv_queue.clone(), self.stats.dropped.clone());
+        let mut receiver_tasks = JoinSet::new();
         for receiver in self.receivers {
-            tasks.push(tokio::spawn({
-                let tx = recv_tx.clone();
-                async move {
-                    receiver.start(tx).await;
+            let tx = recv_tx.clone();
+            receiver_tasks.spawn(async move {
+                receiver.start(tx).await;
+            });
+        }
+        drop(recv_tx);
+
+        // NB: each exporter runs in its own task, and reads from its own queue
+        let mut export_txs = vec![];
+        let mut export_tasks = JoinSet::new();
+        for exporter in self.exporters {
+            let (tx, rx) = queue(
+                self.export_queue.clone(),
+                self.stats.dropped_exports.clone(),
+            );
+            export_txs.push(tx);
+            export_tasks.spawn(run_exporter(
+                exporter,
+                rx,
+                self.stats.failed_exports.clone(),
+            ));
+        }
+
+        // NB: the data is processed sequentially
+        let mut shutdown = self
+            .shutdown
+            .take()
+            .unwrap_or_else(|| Box::pin(std::future::pending()));
+        let mut ticker = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            let data = tokio::select! {
+                data_recv = recv_rx.recv() => match data_recv {
+                    Some(d) => {
+                        log::trace!("received data");
+                        self.stats.received.fetch_add(d.len() as u64, Ordering::Relaxed);
+                        process(&mut self.processors, vec![d]).await
+                    }
+                    None => {
+                        log::info!("all receivers have stopped");
+                        break;
+                    }
+                },
+                _ = ticker.tick() => drain(&mut self.processors, false).await,
+                _ = &mut shutdown => {
+                    log::info!("shutting down");
+                    break;
                 }
-            }));
-        }
-
-        // NB: there is a unique task waiting to receive a signal
-        // once received, a task is spawned to process the data and then export it in its own thread
-        tasks.push(tokio::spawn({
-            async move {
-                // => we receive data from the receiver channel
-                loop {
-                    let data_recv = match recv_rx.recv().await {
-                        Some(d) => {
-                            log::trace!("received data");
-                            d
-                        }
-                        None => {
-                            log::error!("closed receiver channel");
-                            panic!("closed receiver channel")
-                        }
-                    };
-
-                    let mut processors = self.processors.clone();
-                    let exporters = self.exporters.clone();
-                    tokio::spawn(async move {
-                        let mut data = vec![data_recv];
-                        for processor in &mut processors {
-                            data = match processor.process(data).await {
-                                Some(d) => d,
-                                None => {
-                                    // NB: nothing is returned, so we stop the processing chain
-                                    return;
-                                }
-                            }
-                        }
-
-                        // NB: each exporter runs in its own task to export in parallel
-                        for exporter in exporters {
-                            tokio::spawn({
-                                let data = data.clone();
-                                async move {
-                                    exporter.export(&data).await;
-                                }
-                            });
-                        }
-                    });
+            };
+            export(&export_txs, data, &self.stats.dropped_exports).await;
+        }
+
+        // NB: the queued data has been acknowledged to the clients, so it is processed,
+        // and the buffered data is flushed before stopping
+        receiver_tasks.abort_all();
+        let mut queued = vec![];
+        while let Some(d) = recv_rx.try_recv() {
+            self.stats
+                .received
+                .fetch_add(d.len() as u64, Ordering::Relaxed);
+            queued.push(d);
+        }
+        let mut data = process(&mut self.processors, queued).await;
+        data.extend(drain(&mut self.processors, true).await);
+        export(&export_txs, data, &self.stats.dropped_exports).await;
+        drop(export_txs);
+        while let Some(res) = export_tasks.join_next().await {
+            if let Err(err) = res {
+                log::error!("export task failed: {err}");
+            }
+        }
+    }
+}
+
+/// Processes data through a chain of processors
+async fn process(processors: &mut [Box<dyn Processor>], mut data: Vec<Data>) -> Vec<Data> {
+    for processor in processors {
+        data = match processor.process(data).await {
+            Some(d) => d,
+            // NB: nothing is returned, so we stop the processing chain
+            None => return vec![],
+        }
+    }
+    data
+}
+
+/// Ticks (or flushes) the processors
+///
+/// The data released by a processor goes through the next processors.
+async fn drain(processors: &mut [Box<dyn Processor>], flush: bool) -> Vec<Data> {
+    let mut data = vec![];
+    for processor in processors {
+        if !data.is_empty() {
+            data = processor.process(data).await.unwrap_or_default();
+        }
+        if flush {
+            data.extend(processor.flush().await);
+        } else if let Some(d) = processor.tick().await {
+            data.extend(d);
+        }
+    }
+    data
+}
+
+/// Sends data to the exporters queues
+///
+/// NB: the data which is still rejected by a blocking queue is dropped, and added to the `dropped` counter.
+async fn export(txs: &[DataSender], data: Vec<Data>, dropped: &AtomicU64) {
+    for tx in txs {
+        for d in &data {
+            match tx.send(d.clone()).await {
+                Ok(()) => {}
+                Err(SendError::Closed(_)) => log::error!("closed exporter queue"),
+                Err(SendError::Full(d)) => {
+                    log::warn!("exporter queue is full, data dropped");
+                    dropped.fetch_add(d.len() as u64, Ordering::Relaxed);
                 }
+                Err(SendError::Dropped(_)) => log::warn!("exporter queue is full, data dropped"),
             }
-        }));
+        }
+    }
+}
 
-        // wait for all top-level tasks
-        for task in tasks {
-            task.await.unwrap();
+/// Runs an exporter, until its queue is closed
+///
+/// The queued data is exported in batches, and the failed items are added to the `failed` counter.
+async fn run_exporter(exporter: Box<dyn Exporter>, mut rx: DataReceiver, failed: Arc<AtomicU64>) {
+    let mut ticker = tokio::time::interval(TICK_INTERVAL);
+    loop {
+        tokio::select! {
+            d = rx.recv() => {
+                let Some(d) = d else {
+                    break;
+                };
+                let mut data = vec![d];
+                while let Some(d) = rx.try_recv() {
+                    data.push(d);
+                }
+                if let Err(err) = exporter.export(&data).await {
+                    log::error!("export failed: {err}");
+                    let n = data.iter().map(|d| d.len() as u64).sum::<u64>();
+                    failed.fetch_add(n, Ordering::Relaxed);
+                }
+            }
+            _ = ticker.tick() => exporter.tick().await,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use obsv_core::data::{Log, Metric, MetricData, NumberDataPoint, NumberValue, Service};
+
+    use crate::{expt::ExportError, proc::batch::BatchProcessor};
+
+    use super::*;
+
+    /// A receiver which sends logs and stops
+    struct TestReceiver(usize);
+
+    #[async_trait]
+    impl Receiver for TestReceiver {
+        async fn start(&self, tx: DataSender) {
+            for i in 0..self.0 {
+                let mut data = LogData::default();
+                data.add_log(
+                    &Service {
+                        name: "my_service".to_string(),
+                        attrs: HashMap::new(),
+                    },
+                    None,
+                    Log {
+                        trace_id: 0,
+                        span_id: 0,
+                        timestamp: i as i128,
+                        level: 9,
+                        message: "hello".to_string(),
+                        attrs: HashMap::new(),
+                    },
+                );
+                tx.send(data.into()).await.unwrap();
+            }
+        }
+    }
+
+    /// A receiver which sends logs and never stops
+    struct PendingReceiver(usize);
+
+    #[async_trait]
+    impl Receiver for PendingReceiver {
+        async fn start(&self, tx: DataSender) {
+            TestReceiver(self.0).start(tx.clone()).await;
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// A processor which takes some time
+    #[derive(Clone)]
+    struct SlowProcessor;
+
+    #[async_trait]
+    impl Processor for SlowProcessor {
+        async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Some(data)
+        }
+    }
+
+    /// An exporter which keeps the exported data
+    #[derive(Clone, Default)]
+    struct TestExporter(Arc<Mutex<Vec<Data>>>);
+
+    #[async_trait]
+    impl Exporter for TestExporter {
+        async fn export(&self, data: &[Data]) -> Result<(), ExportError> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn service_flushes_on_stop() {
+        let exporter = TestExporter::default();
+        let service = CollService::new();
+        let stats = service.stats();
+        service
+            .receiver(TestReceiver(5))
+            .processor(BatchProcessor::new(2).timeout(Duration::from_secs(60)))
+            .exporter(exporter.clone())
+            .start()
+            .await;
+
+        let exported = exporter.0.lock().unwrap();
+        assert_eq!(
+            exported.iter().map(|d| d.len()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(stats.received(), 5);
+        assert_eq!(stats.dropped(), 0);
+        assert_eq!(stats.failed_exports(), 0);
+    }
+
+    #[tokio::test]
+    async fn service_processes_queue_on_shutdown() {
+        let exporter = TestExporter::default();
+        let service = CollService::new();
+        let stats = service.stats();
+        service
+            .receiver(PendingReceiver(5))
+            .processor(SlowProcessor)
+            .exporter(exporter.clone())
+            .shutdown(tokio::time::sleep(Duration::from_millis(5)))
+            .start()
+            .await;
+
+        let exported = exporter.0.lock().unwrap();
+        assert_eq!(exported.iter().map(|d| d.len()).sum::<usize>(), 5);
+        assert_eq!(stats.received(), 5);
+    }
+
+    #[test]
+    fn data_otlp_len() {
+        let point = |timestamp| NumberDataPoint {
+            attrs: HashMap::new(),
+            start: 0,
+            timestamp,
+            value: NumberValue::Int(1),
+            exemplars: vec![],
+            flags: 0,
+        };
+        let mut metrics = MetricsData::default();
+        metrics.add_metric(
+            &Service {
+                name: "my_service".to_string(),
+                attrs: HashMap::new(),
+            },
+            None,
+            Metric {
+                name: "requests".to_string(),
+                descr: String::new(),
+                unit: String::new(),
+                data: MetricData::Gauge {
+                    points: vec![point(1), point(2), point(3)],
+                },
+            },
+        );
+        let data = Data::from(metrics);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.otlp_len(), 3);
+    }
+}
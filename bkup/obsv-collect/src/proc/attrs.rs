@@ -0,0 +1,581 @@
+//! Attributes processor
+
+use std::{collections::HashMap, fs, path::Path};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use obsv_core::{
+    data::{AttrValue, MetricData},
+    error::Error,
+};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::Data;
+
+use super::Processor;
+
+/// Attributes processor configuration
+///
+/// # Example
+///
+/// ```toml
+/// [[actions]]
+/// action = "upsert"
+/// key = "deployment.environment"
+/// value = "production"
+/// targets = ["resource"]
+///
+/// [[actions]]
+/// action = "hash"
+/// pattern = "^user\\."
+/// secret = "my_secret"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AttrsConfig {
+    /// Actions (applied in order)
+    #[serde(default)]
+    pub actions: Vec<AttrActionConfig>,
+}
+
+/// Attribute action configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttrActionConfig {
+    /// Action
+    #[serde(flatten)]
+    pub action: AttrAction,
+    /// Targets (spans, logs and metrics by default)
+    #[serde(default = "default_targets")]
+    pub targets: Vec<AttrTarget>,
+}
+
+/// Attribute action
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AttrAction {
+    /// Sets a value, if the key does not exist
+    Insert {
+        /// Key
+        key: String,
+        /// Value
+        value: toml::Value,
+    },
+    /// Sets a value, if the key exists
+    Update {
+        /// Key
+        key: String,
+        /// Value
+        value: toml::Value,
+    },
+    /// Sets a value
+    Upsert {
+        /// Key
+        key: String,
+        /// Value
+        value: toml::Value,
+    },
+    /// Copies the value of another key
+    Copy {
+        /// Key
+        key: String,
+        /// Source key
+        from: String,
+    },
+    /// Deletes the matching keys
+    Delete {
+        /// Key
+        key: Option<String>,
+        /// Key pattern (regex)
+        pattern: Option<String>,
+    },
+    /// Replaces the values of the matching keys with their SHA-256 hash
+    ///
+    /// NB: without a secret, low-entropy values (emails, IDs) can be recovered by brute force
+    Hash {
+        /// Key
+        key: Option<String>,
+        /// Key pattern (regex)
+        pattern: Option<String>,
+        /// Secret key (HMAC-SHA256)
+        secret: Option<String>,
+    },
+    /// Renames a key
+    Rename {
+        /// Key
+        key: String,
+        /// New key
+        to: String,
+    },
+}
+
+/// Attributes target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttrTarget {
+    /// Span attributes
+    Span,
+    /// Log attributes
+    Log,
+    /// Metric data points attributes
+    Metric,
+    /// Resource attributes (service)
+    Resource,
+    /// Instrumentation scope attributes
+    Scope,
+}
+
+/// Returns the default targets
+fn default_targets() -> Vec<AttrTarget> {
+    vec![AttrTarget::Span, AttrTarget::Log, AttrTarget::Metric]
+}
+
+impl AttrsConfig {
+    /// Loads the configuration from a TOML string
+    pub fn from_toml(content: &str) -> Result<Self, Error> {
+        toml::from_str(content)
+            .map_err(|err| Error::string(format!("Invalid attributes config: {err}")))
+    }
+
+    /// Loads the configuration from a TOML file
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| Error::string(format!("Cannot read {}: {err}", path.display())))?;
+        Self::from_toml(&content)
+    }
+}
+
+/// Attributes processor
+///
+/// The attributes of spans, logs, metrics, resources and scopes are modified by a list of actions.
+#[derive(Debug, Clone)]
+pub struct AttrsProcessor {
+    /// Actions
+    actions: Vec<(Action, Vec<AttrTarget>)>,
+}
+
+/// A compiled action
+#[derive(Debug, Clone)]
+enum Action {
+    /// Sets a value
+    Set {
+        /// Key
+        key: String,
+        /// Value
+        value: AttrValue,
+        /// Sets the value if the key does not exist
+        insert: bool,
+        /// Sets the value if the key exists
+        update: bool,
+    },
+    /// Copies a value
+    Copy {
+        /// Key
+        key: String,
+        /// Source key
+        from: String,
+    },
+    /// Deletes the matching keys
+    Delete(KeyMatcher),
+    /// Hashes the values of the matching keys
+    Hash {
+        /// Matcher
+        matcher: KeyMatcher,
+        /// Secret key
+        secret: Option<Vec<u8>>,
+    },
+    /// Renames a key
+    Rename {
+        /// Key
+        key: String,
+        /// New key
+        to: String,
+    },
+}
+
+/// Matches attribute keys
+#[derive(Debug, Clone)]
+enum KeyMatcher {
+    /// Exact key
+    Key(String),
+    /// Key pattern
+    Pattern(Regex),
+}
+
+impl KeyMatcher {
+    /// Creates a new matcher
+    fn new(key: Option<String>, pattern: Option<String>) -> Result<Self, Error> {
+        match (key, pattern) {
+            (Some(key), None) => Ok(Self::Key(key)),
+            (None, Some(pattern)) => Regex::new(&pattern)
+                .map(Self::Pattern)
+                .map_err(|err| Error::string(format!("Invalid pattern {pattern}: {err}"))),
+            _ => Err(Error::new("Either a key or a pattern must be set")),
+        }
+    }
+
+    /// Returns the matching keys
+    fn keys(&self, attrs: &HashMap<String, AttrValue>) -> Vec<String> {
+        match self {
+            Self::Key(key) if attrs.contains_key(key) => vec![key.clone()],
+            Self::Key(_) => vec![],
+            Self::Pattern(re) => attrs.keys().filter(|k| re.is_match(k)).cloned().collect(),
+        }
+    }
+}
+
+impl AttrsProcessor {
+    /// Creates a new attributes processor
+    pub fn new(config: AttrsConfig) -> Result<Self, Error> {
+        let actions = config
+            .actions
+            .into_iter()
+            .map(|cfg| Ok((Action::new(cfg.action)?, cfg.targets)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self { actions })
+    }
+
+    /// Applies the actions to a set of attributes
+    fn apply(&self, target: AttrTarget, attrs: &mut HashMap<String, AttrValue>) {
+        for (action, targets) in &self.actions {
+            if targets.contains(&target) {
+                action.apply(attrs);
+            }
+        }
+    }
+
+    /// Checks if a target has actions
+    fn has_target(&self, target: AttrTarget) -> bool {
+        self.actions.iter().any(|(_, t)| t.contains(&target))
+    }
+}
+
+impl Action {
+    /// Compiles an action
+    fn new(action: AttrAction) -> Result<Self, Error> {
+        Ok(match action {
+            AttrAction::Insert { key, value } => Self::Set {
+                key,
+                value: toml_to_attr(value),
+                insert: true,
+                update: false,
+            },
+            AttrAction::Update { key, value } => Self::Set {
+                key,
+                value: toml_to_attr(value),
+                insert: false,
+                update: true,
+            },
+            AttrAction::Upsert { key, value } => Self::Set {
+                key,
+                value: toml_to_attr(value),
+                insert: true,
+                update: true,
+            },
+            AttrAction::Copy { key, from } => Self::Copy { key, from },
+            AttrAction::Delete { key, pattern } => Self::Delete(KeyMatcher::new(key, pattern)?),
+            AttrAction::Hash {
+                key,
+                pattern,
+                secret,
+            } => Self::Hash {
+                matcher: KeyMatcher::new(key, pattern)?,
+                secret: secret.map(String::into_bytes),
+            },
+            AttrAction::Rename { key, to } => Self::Rename { key, to },
+        })
+    }
+
+    /// Applies the action
+    fn apply(&self, attrs: &mut HashMap<String, AttrValue>) {
+        match self {
+            Action::Set {
+                key,
+                value,
+                insert,
+                update,
+            } => {
+                let exists = attrs.contains_key(key);
+                if (exists && *update) || (!exists && *insert) {
+                    attrs.insert(key.clone(), value.clone());
+                }
+            }
+            Action::Copy { key, from } => {
+                if let Some(value) = attrs.get(from).cloned() {
+                    attrs.insert(key.clone(), value);
+                }
+            }
+            Action::Delete(matcher) => {
+                for key in matcher.keys(attrs) {
+                    attrs.remove(&key);
+                }
+            }
+            Action::Hash { matcher, secret } => {
+                for key in matcher.keys(attrs) {
+                    if let Some(value) = attrs.get_mut(&key) {
+                        let hash = hash(secret.as_deref(), value.to_string().as_bytes());
+                        *value = AttrValue::String(hash);
+                    }
+                }
+            }
+            Action::Rename { key, to } => {
+                if let Some(value) = attrs.remove(key) {
+                    attrs.insert(to.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+/// Hashes a value (hex), with HMAC-SHA256 if a secret is set, or SHA-256
+pub(crate) fn hash(secret: Option<&[u8]>, value: &[u8]) -> String {
+    match secret {
+        Some(secret) => {
+            // NB: HMAC accepts keys of any size
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("invalid HMAC key");
+            mac.update(value);
+            format!("{:x}", mac.finalize().into_bytes())
+        }
+        None => format!("{:x}", Sha256::digest(value)),
+    }
+}
+
+/// Converts a TOML value to an attribute value
+fn toml_to_attr(value: toml::Value) -> AttrValue {
+    match value {
+        toml::Value::String(s) => AttrValue::String(s),
+        toml::Value::Integer(i) => AttrValue::Int(i),
+        toml::Value::Float(f) => AttrValue::Float(f),
+        toml::Value::Boolean(b) => AttrValue::Bool(b),
+        toml::Value::Datetime(dt) => AttrValue::String(dt.to_string()),
+        toml::Value::Array(values) => {
+            AttrValue::Array(values.into_iter().map(toml_to_attr).collect())
+        }
+        toml::Value::Table(table) => AttrValue::Map(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_attr(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Returns the attributes of the metric data points
+fn points_attrs(data: &mut MetricData) -> Vec<&mut HashMap<String, AttrValue>> {
+    match data {
+        MetricData::Gauge { points } | MetricData::Sum { points, .. } => {
+            points.iter_mut().map(|p| &mut p.attrs).collect()
+        }
+        MetricData::Histogram { points, .. } => points.iter_mut().map(|p| &mut p.attrs).collect(),
+        MetricData::ExpHistogram { points, .. } => {
+            points.iter_mut().map(|p| &mut p.attrs).collect()
+        }
+        MetricData::Summary { points } => points.iter_mut().map(|p| &mut p.attrs).collect(),
+    }
+}
+
+#[async_trait]
+impl Processor for AttrsProcessor {
+    async fn process(&mut self, mut data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("attributes processing");
+        let resource = self.has_target(AttrTarget::Resource);
+        let scope = self.has_target(AttrTarget::Scope);
+        for d in &mut data {
+            // NB: the resource and scope are shared by the items of a group
+            macro_rules! apply_group {
+                ($group:expr) => {
+                    if resource {
+                        self.apply(AttrTarget::Resource, &mut $group.service.attrs);
+                    }
+                    if let (true, Some(s)) = (scope, $group.scope.as_mut()) {
+                        self.apply(AttrTarget::Scope, &mut s.attrs);
+                    }
+                };
+            }
+
+            match d {
+                Data::Traces(traces) => {
+                    for group in &mut traces.spans {
+                        apply_group!(group);
+                        for span in &mut group.spans {
+                            self.apply(AttrTarget::Span, &mut span.attrs);
+                        }
+                    }
+                }
+                Data::Logs(logs) => {
+                    for group in &mut logs.logs {
+                        apply_group!(group);
+                        for log in &mut group.logs {
+                            self.apply(AttrTarget::Log, &mut log.attrs);
+                        }
+                    }
+                }
+                Data::Metrics(metrics) => {
+                    for group in &mut metrics.metrics {
+                        apply_group!(group);
+                        for metric in &mut group.metrics {
+                            for attrs in points_attrs(&mut metric.data) {
+                                self.apply(AttrTarget::Metric, attrs);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::data::{Log, LogData, Service, ServiceLogs};
+
+    use super::*;
+
+    /// Returns logs with attributes
+    fn logs(attrs: &[(&str, &str)]) -> Data {
+        let attrs = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), AttrValue::String(v.to_string())))
+            .collect::<HashMap<_, _>>();
+        Data::Logs(LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: attrs.clone(),
+                },
+                scope: None,
+                logs: vec![Log {
+                    trace_id: 0,
+                    span_id: 0,
+                    timestamp: 1,
+                    level: 9,
+                    message: "hello".to_string(),
+                    attrs,
+                }],
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn attrs_actions() {
+        let config = AttrsConfig::from_toml(
+            r#"
+            [[actions]]
+            action = "insert"
+            key = "env"
+            value = "prod"
+
+            [[actions]]
+            action = "update"
+            key = "missing"
+            value = 1
+
+            [[actions]]
+            action = "upsert"
+            key = "region"
+            value = "eu"
+            targets = ["resource"]
+
+            [[actions]]
+            action = "copy"
+            key = "host.copy"
+            from = "host"
+
+            [[actions]]
+            action = "rename"
+            key = "host"
+            to = "host.name"
+
+            [[actions]]
+            action = "hash"
+            key = "user.email"
+
+            [[actions]]
+            action = "delete"
+            pattern = "^secret\\."
+            "#,
+        )
+        .unwrap();
+        let mut processor = AttrsProcessor::new(config).unwrap();
+        let data = logs(&[
+            ("host", "my_host"),
+            ("user.email", "me@example.com"),
+            ("secret.token", "abc"),
+            ("secret.key", "def"),
+        ]);
+        let data = processor.process(vec![data]).await.unwrap();
+
+        let Data::Logs(logs) = &data[0] else {
+            panic!("invalid data");
+        };
+        let group = &logs.logs[0];
+        let mut keys = group.logs[0].attrs.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["env", "host.copy", "host.name", "user.email"]);
+        assert_eq!(
+            group.logs[0].attrs["user.email"],
+            AttrValue::String(format!("{:x}", Sha256::digest(b"me@example.com")))
+        );
+        // NB: only the upsert targets the resource
+        assert_eq!(group.service.attrs.len(), 5);
+        assert_eq!(
+            group.service.attrs["region"],
+            AttrValue::String("eu".to_string())
+        );
+    }
+
+    #[test]
+    fn attrs_invalid_config() {
+        let config = AttrsConfig::from_toml(
+            r#"
+            [[actions]]
+            action = "delete"
+            pattern = "("
+            "#,
+        )
+        .unwrap();
+        assert!(AttrsProcessor::new(config).is_err());
+
+        let config = AttrsConfig::from_toml(
+            r#"
+            [[actions]]
+            action = "hash"
+            "#,
+        )
+        .unwrap();
+        assert!(AttrsProcessor::new(config).is_err());
+        assert!(AttrsConfig::from_toml("[[actions]]\naction = \"unknown\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn attrs_hash_secret() {
+        let config = AttrsConfig::from_toml(
+            r#"
+            [[actions]]
+            action = "hash"
+            key = "user.email"
+            secret = "my_secret"
+            "#,
+        )
+        .unwrap();
+        let mut processor = AttrsProcessor::new(config).unwrap();
+        let data = logs(&[("user.email", "me@example.com")]);
+        let data = processor.process(vec![data]).await.unwrap();
+
+        let Data::Logs(logs) = &data[0] else {
+            panic!("invalid data");
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"my_secret").unwrap();
+        mac.update(b"me@example.com");
+        let expected = format!("{:x}", mac.finalize().into_bytes());
+        assert_eq!(
+            logs.logs[0].logs[0].attrs["user.email"],
+            AttrValue::String(expected)
+        );
+        assert_ne!(
+            logs.logs[0].logs[0].attrs["user.email"],
+            AttrValue::String(format!("{:x}", Sha256::digest(b"me@example.com")))
+        );
+    }
+}
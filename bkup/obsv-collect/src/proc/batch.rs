@@ -1,8 +1,9 @@
 //! Batch processor
 
+use std::time::Duration;
+
 use async_trait::async_trait;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 use crate::Data;
 
@@ -10,22 +11,33 @@ use super::Processor;
 
 /// Batch processor
 ///
-/// `N` is the buffer capacity
-#[derive(Debug)]
+/// The data is accumulated per signal (traces, logs, metrics), and a batch is released when:
+/// - it reaches the maximum number of items (spans, logs or metrics),
+/// - it reaches the maximum encoded size (OTLP protobuf),
+/// - its first item is older than the timeout.
+///
+/// NB: the data is not split, so a batch may exceed the limits.
+#[derive(Debug, Clone)]
 pub struct BatchProcessor {
-    /// Buffer capacity
+    /// Maximum number of items
     capacity: usize,
-    /// Buffer
-    buffer: Arc<Mutex<Data>>,
+    /// Maximum encoded size (bytes)
+    max_bytes: Option<usize>,
+    /// Timeout
+    timeout: Duration,
+    /// Batches (traces, logs, metrics)
+    batches: [Option<Batch>; 3],
 }
 
-impl Clone for BatchProcessor {
-    fn clone(&self) -> Self {
-        Self {
-            capacity: self.capacity,
-            buffer: self.buffer.clone(),
-        }
-    }
+/// A batch of data
+#[derive(Debug, Clone)]
+struct Batch {
+    /// Data
+    data: Data,
+    /// Encoded size (bytes)
+    size: usize,
+    /// Creation time
+    created: Instant,
 }
 
 impl BatchProcessor {
@@ -33,22 +45,202 @@ impl BatchProcessor {
     pub fn new(capacity: usize) -> Self {
         Self {
             capacity,
-            buffer: Arc::new(Mutex::new(Data::default())),
+            max_bytes: None,
+            timeout: Duration::from_millis(200),
+            batches: [None, None, None],
+        }
+    }
+
+    /// Sets the maximum encoded size of a batch (bytes)
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Sets the timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds data to its batch, and returns the batch if it is full
+    fn add(&mut self, data: Data) -> Option<Data> {
+        // NB: the encoded size is only computed when it is bounded
+        let size = if self.max_bytes.is_some() {
+            data.encoded_len()
+        } else {
+            0
+        };
+        let slot = &mut self.batches[signal_index(&data)];
+        match slot {
+            Some(batch) => {
+                merge(&mut batch.data, data);
+                batch.size += size;
+            }
+            None => {
+                *slot = Some(Batch {
+                    data,
+                    size,
+                    created: Instant::now(),
+                });
+            }
+        }
+
+        let batch = slot.as_ref()?;
+        if batch.data.len() >= self.capacity || self.max_bytes.is_some_and(|m| batch.size >= m) {
+            slot.take().map(|b| b.data)
+        } else {
+            None
         }
     }
 }
 
 #[async_trait]
 impl Processor for BatchProcessor {
-    async fn process(&mut self, mut data: Vec<Data>) -> Option<Vec<Data>> {
+    async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
         log::trace!("batch processing");
-        todo!("implement batch processing");
-        // let mut buffer = self.buffer.lock().await;
-        // *buffer.append(&mut data);
-        // if *buffer.len() <= self.capacity {
-        //     return None;
-        // }
-        // let overflown = self.buffer.drain(self.capacity..).collect::<Vec<_>>();
-        // return Some(overflown);
+        let batches = data
+            .into_iter()
+            .filter(|d| !d.is_empty())
+            .filter_map(|d| self.add(d))
+            .collect::<Vec<_>>();
+        if batches.is_empty() {
+            None
+        } else {
+            Some(batches)
+        }
+    }
+
+    async fn tick(&mut self) -> Option<Vec<Data>> {
+        let batches = self
+            .batches
+            .iter_mut()
+            .filter(|b| {
+                b.as_ref()
+                    .is_some_and(|b| b.created.elapsed() >= self.timeout)
+            })
+            .filter_map(|b| b.take().map(|b| b.data))
+            .collect::<Vec<_>>();
+        if batches.is_empty() {
+            None
+        } else {
+            Some(batches)
+        }
+    }
+
+    async fn flush(&mut self) -> Vec<Data> {
+        self.batches
+            .iter_mut()
+            .filter_map(|b| b.take().map(|b| b.data))
+            .collect()
+    }
+}
+
+/// Returns the index of the batch of a data
+fn signal_index(data: &Data) -> usize {
+    match data {
+        Data::Traces(_) => 0,
+        Data::Logs(_) => 1,
+        Data::Metrics(_) => 2,
+    }
+}
+
+/// Merges data of the same signal
+fn merge(batch: &mut Data, data: Data) {
+    match (batch, data) {
+        (Data::Traces(batch), Data::Traces(data)) => {
+            for service_spans in data.spans {
+                for span in service_spans.spans {
+                    batch.add_span(&service_spans.service, service_spans.scope.as_ref(), span);
+                }
+            }
+        }
+        (Data::Logs(batch), Data::Logs(data)) => {
+            for service_logs in data.logs {
+                for log in service_logs.logs {
+                    batch.add_log(&service_logs.service, service_logs.scope.as_ref(), log);
+                }
+            }
+        }
+        (Data::Metrics(batch), Data::Metrics(data)) => {
+            for service_metrics in data.metrics {
+                for metric in service_metrics.metrics {
+                    batch.add_metric(
+                        &service_metrics.service,
+                        service_metrics.scope.as_ref(),
+                        metric,
+                    );
+                }
+            }
+        }
+        _ => unreachable!("data of different signals"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::data::{Log, LogData, Service, ServiceLogs, TraceData};
+
+    use super::*;
+
+    /// Returns logs
+    fn logs(n: usize) -> Data {
+        Data::Logs(LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: (0..n)
+                    .map(|i| Log {
+                        trace_id: 0,
+                        span_id: 0,
+                        timestamp: i as i128,
+                        level: 9,
+                        message: "hello".to_string(),
+                        attrs: HashMap::new(),
+                    })
+                    .collect(),
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn batch_max_items() {
+        let mut processor = BatchProcessor::new(3);
+        assert_eq!(processor.process(vec![logs(1), logs(1)]).await, None);
+        let batches = processor.process(vec![logs(2)]).await.unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 4);
+        // NB: the logs are merged in the same service
+        assert!(matches!(&batches[0], Data::Logs(data) if data.logs.len() == 1));
+        assert!(processor.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_max_bytes() {
+        let size = logs(1).encoded_len();
+        let mut processor = BatchProcessor::new(100).max_bytes(2 * size);
+        assert_eq!(processor.process(vec![logs(1)]).await, None);
+        assert_eq!(processor.process(vec![logs(1)]).await.unwrap()[0].len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_timeout_and_flush() {
+        let mut processor = BatchProcessor::new(100).timeout(Duration::from_secs(1));
+        processor.process(vec![logs(1)]).await;
+        tokio::time::advance(Duration::from_millis(500)).await;
+        processor
+            .process(vec![Data::Traces(TraceData::default()), logs(1)])
+            .await;
+        assert_eq!(processor.tick().await, None);
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(processor.tick().await.unwrap()[0].len(), 2);
+
+        processor.process(vec![logs(1)]).await;
+        assert_eq!(processor.flush().await, vec![logs(1)]);
     }
 }
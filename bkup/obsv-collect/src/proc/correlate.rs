@@ -0,0 +1,213 @@
+//! Log correlation processor
+
+use async_trait::async_trait;
+use obsv_core::data::{AttrValue, LogIndex, SpanEvent};
+
+use crate::Data;
+
+use super::Processor;
+
+/// Name of the span events holding the correlated logs
+pub const LOG_EVENT_NAME: &str = "log";
+
+/// Log correlation processor
+///
+/// The recent logs are indexed by trace (with a bounded number of traces and logs per trace).
+///
+/// The span enrichment is opt-in, since it duplicates the logs into the spans: when enabled,
+/// the spans are enriched with their logs (by trace and span IDs), which are added as `log` span events
+/// (with the `log.message` and `log.level` attributes), up to a maximum number of events per span.
+///
+/// NB: a span is usually exported after its logs, so only the logs received before the span are added.
+#[derive(Debug, Clone)]
+pub struct LogCorrelationProcessor {
+    /// Recent logs
+    index: LogIndex,
+    /// Maximum number of log events added to a span (no enrichment if not set)
+    max_span_events: Option<usize>,
+}
+
+impl Default for LogCorrelationProcessor {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+impl LogCorrelationProcessor {
+    /// Creates a new log correlation processor, keeping the logs of a maximum number of traces
+    pub fn new(max_traces: usize) -> Self {
+        Self {
+            index: LogIndex::new().max_traces(max_traces).max_trace_logs(1000),
+            max_span_events: None,
+        }
+    }
+
+    /// Sets the maximum number of logs kept per trace
+    pub fn max_trace_logs(mut self, max_trace_logs: usize) -> Self {
+        self.index = self.index.max_trace_logs(max_trace_logs);
+        self
+    }
+
+    /// Enriches the spans with their logs, adding a maximum number of log events per span
+    pub fn enrich_spans(mut self, max_span_events: usize) -> Self {
+        self.max_span_events = Some(max_span_events);
+        self
+    }
+
+    /// Returns the indexed logs
+    pub fn index(&self) -> &LogIndex {
+        &self.index
+    }
+}
+
+#[async_trait]
+impl Processor for LogCorrelationProcessor {
+    async fn process(&mut self, mut data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("log correlation processing");
+        // NB: the logs are indexed first, for the spans of the same batch
+        for d in &data {
+            if let Data::Logs(logs) = d {
+                self.index.add_logs(logs);
+            }
+        }
+        let Some(max_span_events) = self.max_span_events else {
+            return Some(data);
+        };
+        for d in &mut data {
+            let Data::Traces(traces) = d else {
+                continue;
+            };
+            for span in traces.spans.iter_mut().flat_map(|g| g.spans.iter_mut()) {
+                let logs = self.index.span_logs(span.trace_id, span.id);
+                let dropped = logs.len().saturating_sub(max_span_events);
+                for log in logs.into_iter().take(max_span_events) {
+                    let mut attrs = log.attrs.clone();
+                    attrs.insert(
+                        "log.message".to_string(),
+                        AttrValue::String(log.message.clone()),
+                    );
+                    attrs.insert("log.level".to_string(), AttrValue::Int(log.level.into()));
+                    span.events.push(SpanEvent {
+                        timestamp: log.timestamp,
+                        name: LOG_EVENT_NAME.to_string(),
+                        attrs,
+                        dropped_attrs: 0,
+                    });
+                }
+                span.dropped_events = span.dropped_events.saturating_add(dropped as u32);
+            }
+        }
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::data::{Log, LogData, Service, Span, TraceData};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn log_correlation() {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut logs = LogData::default();
+        for (span_id, message) in [(2, "request failed"), (3, "other span")] {
+            logs.add_log(
+                &service,
+                None,
+                Log {
+                    trace_id: 1,
+                    span_id,
+                    timestamp: 10,
+                    level: 17,
+                    message: message.to_string(),
+                    attrs: HashMap::new(),
+                },
+            );
+        }
+        let mut traces = TraceData::default();
+        traces.add_span(
+            &service,
+            None,
+            Span {
+                id: 2,
+                trace_id: 1,
+                name: "GET".to_string(),
+                start: 0,
+                end: 20,
+                ..Default::default()
+            },
+        );
+
+        let mut processor = LogCorrelationProcessor::new(100).enrich_spans(10);
+        let data = processor
+            .process(vec![Data::Traces(traces), Data::Logs(logs.clone())])
+            .await
+            .unwrap();
+        assert_eq!(data[1], Data::Logs(logs));
+        let Data::Traces(traces) = &data[0] else {
+            panic!("invalid data");
+        };
+        let events = &traces.spans[0].spans[0].events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, LOG_EVENT_NAME);
+        assert_eq!(
+            events[0].attrs.get("log.message"),
+            Some(&AttrValue::String("request failed".to_string()))
+        );
+        assert_eq!(events[0].attrs.get("log.level"), Some(&AttrValue::Int(17)));
+        assert_eq!(processor.index().trace_logs(1).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn log_correlation_max_span_events() {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut logs = LogData::default();
+        for timestamp in 0..3 {
+            logs.add_log(
+                &service,
+                None,
+                Log {
+                    trace_id: 1,
+                    span_id: 2,
+                    timestamp,
+                    level: 9,
+                    message: "message".to_string(),
+                    attrs: HashMap::new(),
+                },
+            );
+        }
+        let mut traces = TraceData::default();
+        traces.add_span(
+            &service,
+            None,
+            Span {
+                id: 2,
+                trace_id: 1,
+                ..Default::default()
+            },
+        );
+        let data = vec![Data::Logs(logs), Data::Traces(traces)];
+
+        // NB: the spans are not enriched by default
+        let mut processor = LogCorrelationProcessor::new(100);
+        assert_eq!(processor.process(data.clone()).await, Some(data.clone()));
+
+        let mut processor = LogCorrelationProcessor::new(100).enrich_spans(2);
+        let data = processor.process(data).await.unwrap();
+        let Data::Traces(traces) = &data[1] else {
+            panic!("invalid data");
+        };
+        let span = &traces.spans[0].spans[0];
+        assert_eq!(span.events.len(), 2);
+        assert_eq!(span.dropped_events, 1);
+    }
+}
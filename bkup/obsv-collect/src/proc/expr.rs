@@ -0,0 +1,791 @@
+//! Filter expressions
+//!
+//! An expression is a predicate over a span, a log or a metric data point, for instance:
+//!
+//! ```text
+//! service.name == "api" and span.duration > 500ms and attrs["http.status_code"] >= 500
+//! ```
+//!
+//! - fields: `service.name`, `resource["key"]`, `scope.name`, `scope.version`, `attrs["key"]`,
+//!   `span.name`, `span.kind`, `span.status`, `span.duration`, `span.trace_id`,
+//!   `log.level`, `log.message`, `log.trace_id`, `metric.name`, `metric.unit`
+//! - literals: strings (`"api"`), numbers (`500`, `0.5`), durations (`500ms`, `ns`, `us`, `ms`, `s`, `m`, `h`),
+//!   booleans (`true`, `false`) and `null`
+//! - operators: `==`, `!=`, `<`, `<=`, `>`, `>=`, `=~` and `!~` (regex), `and` (`&&`), `or` (`||`), `not` (`!`)
+//!
+//! A field which is not a boolean is true if it is set (eg. `attrs["error"]`).
+
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use obsv_core::{
+    data::{AttrValue, Log, Metric, Scope, Service, Span},
+    error::Error,
+};
+use regex::Regex;
+
+/// Signal of an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Spans
+    Spans,
+    /// Logs
+    Logs,
+    /// Metric data points
+    Metrics,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signal::Spans => write!(f, "spans"),
+            Signal::Logs => write!(f, "logs"),
+            Signal::Metrics => write!(f, "metrics"),
+        }
+    }
+}
+
+/// Filter expression
+#[derive(Debug, Clone)]
+pub struct Expr {
+    /// Root node
+    root: Node,
+}
+
+/// Item evaluated by an expression
+#[derive(Debug, Clone, Copy)]
+pub struct ExprItem<'a> {
+    /// Service
+    pub service: &'a Service,
+    /// Scope
+    pub scope: Option<&'a Scope>,
+    /// Span, log or metric data point
+    pub item: Item<'a>,
+}
+
+/// Span, log or metric data point
+#[derive(Debug, Clone, Copy)]
+pub enum Item<'a> {
+    /// Span
+    Span(&'a Span),
+    /// Log
+    Log(&'a Log),
+    /// Metric data point
+    Point {
+        /// Metric
+        metric: &'a Metric,
+        /// Data point attributes
+        attrs: &'a HashMap<String, AttrValue>,
+    },
+}
+
+impl Expr {
+    /// Parses an expression for a signal
+    pub fn parse(input: &str, signal: Signal) -> Result<Self, Error> {
+        let tokens = tokenize(input).map_err(|err| err.into_error(input))?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.len(),
+            signal,
+        };
+        let root = parser.parse().map_err(|err| err.into_error(input))?;
+        Ok(Self { root })
+    }
+
+    /// Evaluates the expression
+    pub fn eval(&self, item: &ExprItem) -> bool {
+        self.root.eval(item)
+    }
+}
+
+/// Parse error
+#[derive(Debug)]
+struct ParseError {
+    /// Position (byte offset)
+    pos: usize,
+    /// Message
+    message: String,
+}
+
+impl ParseError {
+    /// Creates a new parse error
+    fn new(pos: usize, message: impl Into<String>) -> Self {
+        Self {
+            pos,
+            message: message.into(),
+        }
+    }
+
+    /// Converts to an [Error], with the position in the input
+    fn into_error(self, input: &str) -> Error {
+        Error::string(format!(
+            "Invalid expression at position {}: {}\n  {input}\n  {}^",
+            self.pos,
+            self.message,
+            " ".repeat(input[..self.pos.min(input.len())].chars().count())
+        ))
+    }
+}
+
+/// Token
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Identifier
+    Ident(String),
+    /// String literal
+    Str(String),
+    /// Number literal
+    Number(f64),
+    /// Integer literal
+    Int(i64),
+    /// Duration literal (nanoseconds)
+    Duration(i128),
+    /// Comparison operator
+    Cmp(CmpOp),
+    /// `and`
+    And,
+    /// `or`
+    Or,
+    /// `not`
+    Not,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `[`
+    LBracket,
+    /// `]`
+    RBracket,
+    /// `.`
+    Dot,
+}
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+    /// `=~`
+    Match,
+    /// `!~`
+    NotMatch,
+}
+
+/// Splits the input into tokens (with their position)
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | '[' | ']' | '.' => {
+                chars.next();
+                match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    _ => Token::Dot,
+                }
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|(_, c)| *c);
+                let (token, double) = match (c, next) {
+                    ('=', Some('=')) => (Token::Cmp(CmpOp::Eq), true),
+                    ('=', Some('~')) => (Token::Cmp(CmpOp::Match), true),
+                    ('!', Some('=')) => (Token::Cmp(CmpOp::Ne), true),
+                    ('!', Some('~')) => (Token::Cmp(CmpOp::NotMatch), true),
+                    ('!', _) => (Token::Not, false),
+                    ('<', Some('=')) => (Token::Cmp(CmpOp::Le), true),
+                    ('<', _) => (Token::Cmp(CmpOp::Lt), false),
+                    ('>', Some('=')) => (Token::Cmp(CmpOp::Ge), true),
+                    ('>', _) => (Token::Cmp(CmpOp::Gt), false),
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    _ => return Err(ParseError::new(pos, format!("unexpected '{c}'"))),
+                };
+                if double {
+                    chars.next();
+                }
+                token
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, q)) if q == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, e)) if e == c || e == '\\' => s.push(e),
+                            // NB: other escapes are kept (eg. for regexes)
+                            Some((_, e)) => {
+                                s.push('\\');
+                                s.push(e);
+                            }
+                            None => return Err(ParseError::new(pos, "unterminated string")),
+                        },
+                        Some((_, ch)) => s.push(ch),
+                        None => return Err(ParseError::new(pos, "unterminated string")),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = pos;
+                let mut number = String::new();
+                while let Some(&(i, ch)) = chars.peek() {
+                    if ch.is_ascii_digit() || ch == '.' || (ch == '-' && i == pos) {
+                        number.push(ch);
+                        end = i + ch.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let mut unit = String::new();
+                while let Some(&(i, ch)) = chars.peek() {
+                    if ch.is_ascii_alphabetic() {
+                        unit.push(ch);
+                        end = i + 1;
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                number_token(&number, &unit).ok_or_else(|| {
+                    ParseError::new(pos, format!("invalid number '{}'", &input[pos..end]))
+                })?
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_alphanumeric() || ch == '_' {
+                        ident.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                match ident.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(ident),
+                }
+            }
+            _ => return Err(ParseError::new(pos, format!("unexpected '{c}'"))),
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+/// Returns the token of a number (with an optional duration unit)
+fn number_token(number: &str, unit: &str) -> Option<Token> {
+    let factor: i128 = match unit {
+        "" => {
+            return match number.parse::<i64>() {
+                Ok(i) => Some(Token::Int(i)),
+                Err(_) => number.parse::<f64>().ok().map(Token::Number),
+            }
+        }
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        _ => return None,
+    };
+    let value = number.parse::<f64>().ok()?;
+    Some(Token::Duration((value * factor as f64) as i128))
+}
+
+/// Expression node
+#[derive(Debug, Clone)]
+enum Node {
+    /// Logical and
+    And(Box<Node>, Box<Node>),
+    /// Logical or
+    Or(Box<Node>, Box<Node>),
+    /// Logical not
+    Not(Box<Node>),
+    /// Comparison
+    Cmp(Operand, CmpOp, Operand),
+    /// Regex match
+    Match(Operand, Regex, bool),
+    /// Operand (true if it is set)
+    Operand(Operand),
+}
+
+/// Operand
+#[derive(Debug, Clone)]
+enum Operand {
+    /// Field
+    Field(Field),
+    /// Constant
+    Const(Value),
+}
+
+/// Field
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    /// Service name
+    ServiceName,
+    /// Resource attribute
+    Resource(String),
+    /// Scope name
+    ScopeName,
+    /// Scope version
+    ScopeVersion,
+    /// Item attribute
+    Attr(String),
+    /// Span name
+    SpanName,
+    /// Span kind
+    SpanKind,
+    /// Span status
+    SpanStatus,
+    /// Span duration
+    SpanDuration,
+    /// Trace ID (span or log)
+    TraceId,
+    /// Log level
+    LogLevel,
+    /// Log message
+    LogMessage,
+    /// Metric name
+    MetricName,
+    /// Metric unit
+    MetricUnit,
+}
+
+impl Field {
+    /// Returns a field from its path, and the signal it is restricted to
+    fn from_path(path: &str, key: Option<String>) -> Option<(Self, Option<Signal>)> {
+        Some(match (path, key) {
+            ("service.name", None) => (Self::ServiceName, None),
+            ("resource", Some(key)) => (Self::Resource(key), None),
+            ("scope.name", None) => (Self::ScopeName, None),
+            ("scope.version", None) => (Self::ScopeVersion, None),
+            ("attrs", Some(key)) => (Self::Attr(key), None),
+            ("span.name", None) => (Self::SpanName, Some(Signal::Spans)),
+            ("span.kind", None) => (Self::SpanKind, Some(Signal::Spans)),
+            ("span.status", None) => (Self::SpanStatus, Some(Signal::Spans)),
+            ("span.duration", None) => (Self::SpanDuration, Some(Signal::Spans)),
+            ("span.trace_id", None) => (Self::TraceId, Some(Signal::Spans)),
+            ("log.level", None) => (Self::LogLevel, Some(Signal::Logs)),
+            ("log.message", None) => (Self::LogMessage, Some(Signal::Logs)),
+            ("log.trace_id", None) => (Self::TraceId, Some(Signal::Logs)),
+            ("metric.name", None) => (Self::MetricName, Some(Signal::Metrics)),
+            ("metric.unit", None) => (Self::MetricUnit, Some(Signal::Metrics)),
+            _ => return None,
+        })
+    }
+
+    /// Returns the value of the field
+    fn value(&self, item: &ExprItem) -> Value {
+        let attrs = match item.item {
+            Item::Span(span) => &span.attrs,
+            Item::Log(log) => &log.attrs,
+            Item::Point { attrs, .. } => attrs,
+        };
+        match (self, item.item) {
+            (Field::ServiceName, _) => Value::Str(item.service.name.clone()),
+            (Field::Resource(key), _) => item.service.attrs.get(key).into(),
+            (Field::ScopeName, _) => item
+                .scope
+                .map(|s| Value::Str(s.name.clone()))
+                .unwrap_or(Value::Null),
+            (Field::ScopeVersion, _) => item
+                .scope
+                .and_then(|s| s.version.clone())
+                .map(Value::Str)
+                .unwrap_or(Value::Null),
+            (Field::Attr(key), _) => attrs.get(key).into(),
+            (Field::SpanName, Item::Span(span)) => Value::Str(span.name.clone()),
+            (Field::SpanKind, Item::Span(span)) => Value::Str(span.kind.as_str().to_string()),
+            (Field::SpanStatus, Item::Span(span)) => {
+                Value::Str(span.status.code.as_str().to_string())
+            }
+            (Field::SpanDuration, Item::Span(span)) => Value::Duration(span.end - span.start),
+            (Field::TraceId, Item::Span(span)) => Value::Str(format!("{:032x}", span.trace_id)),
+            (Field::TraceId, Item::Log(log)) => Value::Str(format!("{:032x}", log.trace_id)),
+            (Field::LogLevel, Item::Log(log)) => Value::Int(log.level.into()),
+            (Field::LogMessage, Item::Log(log)) => Value::Str(log.message.clone()),
+            (Field::MetricName, Item::Point { metric, .. }) => Value::Str(metric.name.clone()),
+            (Field::MetricUnit, Item::Point { metric, .. }) => Value::Str(metric.unit.clone()),
+            _ => Value::Null,
+        }
+    }
+}
+
+/// Value
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// No value
+    Null,
+    /// Boolean
+    Bool(bool),
+    /// Integer
+    Int(i64),
+    /// Float
+    Float(f64),
+    /// String
+    Str(String),
+    /// Duration (nanoseconds)
+    Duration(i128),
+}
+
+impl From<Option<&AttrValue>> for Value {
+    fn from(value: Option<&AttrValue>) -> Self {
+        match value {
+            None | Some(AttrValue::None) => Value::Null,
+            Some(AttrValue::Bool(b)) => Value::Bool(*b),
+            Some(AttrValue::Int(i)) => Value::Int(*i),
+            Some(AttrValue::Uint(u)) => i64::try_from(*u)
+                .map(Value::Int)
+                .unwrap_or(Value::Float(*u as f64)),
+            Some(AttrValue::Float(f)) => Value::Float(*f),
+            Some(AttrValue::String(s)) => Value::Str(s.clone()),
+            Some(value) => Value::Str(value.to_string()),
+        }
+    }
+}
+
+impl Value {
+    /// Compares 2 values, if they are comparable
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+            (Value::Duration(a), Value::Duration(b)) => Some(a.cmp(b)),
+            // NB: a number is a duration in nanoseconds
+            (Value::Duration(a), Value::Int(b)) => Some(a.cmp(&(*b as i128))),
+            (Value::Int(a), Value::Duration(b)) => Some((*a as i128).cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Checks if the value is true
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            _ => true,
+        }
+    }
+}
+
+impl Operand {
+    /// Returns the value of the operand
+    fn value(&self, item: &ExprItem) -> Value {
+        match self {
+            Operand::Field(field) => field.value(item),
+            Operand::Const(value) => value.clone(),
+        }
+    }
+}
+
+impl Node {
+    /// Evaluates the node
+    fn eval(&self, item: &ExprItem) -> bool {
+        match self {
+            Node::And(a, b) => a.eval(item) && b.eval(item),
+            Node::Or(a, b) => a.eval(item) || b.eval(item),
+            Node::Not(a) => !a.eval(item),
+            Node::Cmp(a, op, b) => {
+                let ord = a.value(item).compare(&b.value(item));
+                match op {
+                    CmpOp::Eq => ord == Some(Ordering::Equal),
+                    CmpOp::Ne => ord != Some(Ordering::Equal),
+                    CmpOp::Lt => ord == Some(Ordering::Less),
+                    CmpOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    CmpOp::Gt => ord == Some(Ordering::Greater),
+                    CmpOp::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                    CmpOp::Match | CmpOp::NotMatch => unreachable!("regex match"),
+                }
+            }
+            Node::Match(a, re, negate) => {
+                let matched = match a.value(item) {
+                    Value::Null => false,
+                    Value::Str(s) => re.is_match(&s),
+                    Value::Bool(b) => re.is_match(&b.to_string()),
+                    Value::Int(i) => re.is_match(&i.to_string()),
+                    Value::Float(f) => re.is_match(&f.to_string()),
+                    Value::Duration(d) => re.is_match(&d.to_string()),
+                };
+                matched != *negate
+            }
+            Node::Operand(a) => a.value(item).is_true(),
+        }
+    }
+}
+
+/// Recursive descent parser
+struct Parser {
+    /// Tokens
+    tokens: Vec<(usize, Token)>,
+    /// Current token
+    pos: usize,
+    /// End of the input
+    end: usize,
+    /// Signal
+    signal: Signal,
+}
+
+impl Parser {
+    /// Returns the current token
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    /// Returns the position of the current token
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(pos, _)| *pos)
+            .unwrap_or(self.end)
+    }
+
+    /// Consumes the current token
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes an expected token
+    fn expect(&mut self, expected: Token, descr: &str) -> Result<(), ParseError> {
+        let pos = self.offset();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(ParseError::new(pos, format!("expected {descr}"))),
+        }
+    }
+
+    /// Parses the whole input
+    fn parse(&mut self) -> Result<Node, ParseError> {
+        let node = self.parse_or()?;
+        if self.pos < self.tokens.len() {
+            return Err(ParseError::new(self.offset(), "unexpected token"));
+        }
+        Ok(node)
+    }
+
+    /// Parses `a or b`
+    fn parse_or(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    /// Parses `a and b`
+    fn parse_and(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            node = Node::And(Box::new(node), Box::new(self.parse_not()?));
+        }
+        Ok(node)
+    }
+
+    /// Parses `not a`
+    fn parse_not(&mut self) -> Result<Node, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_cmp()
+    }
+
+    /// Parses a comparison
+    fn parse_cmp(&mut self) -> Result<Node, ParseError> {
+        if self.peek() == Some(&Token::LParen) {
+            self.next();
+            let node = self.parse_or()?;
+            self.expect(Token::RParen, "')'")?;
+            return Ok(node);
+        }
+
+        let left = self.parse_operand()?;
+        let Some(Token::Cmp(op)) = self.peek().cloned() else {
+            return Ok(Node::Operand(left));
+        };
+        self.next();
+        let pos = self.offset();
+        let right = self.parse_operand()?;
+        match op {
+            CmpOp::Match | CmpOp::NotMatch => match right {
+                Operand::Const(Value::Str(pattern)) => {
+                    let re = Regex::new(&pattern)
+                        .map_err(|err| ParseError::new(pos, format!("invalid regex: {err}")))?;
+                    Ok(Node::Match(left, re, op == CmpOp::NotMatch))
+                }
+                _ => Err(ParseError::new(pos, "expected a regex string")),
+            },
+            _ => Ok(Node::Cmp(left, op, right)),
+        }
+    }
+
+    /// Parses a field or a literal
+    fn parse_operand(&mut self) -> Result<Operand, ParseError> {
+        let pos = self.offset();
+        let value = match self.next() {
+            Some(Token::Str(s)) => Value::Str(s),
+            Some(Token::Int(i)) => Value::Int(i),
+            Some(Token::Number(f)) => Value::Float(f),
+            Some(Token::Duration(d)) => Value::Duration(d),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                _ => return self.parse_field(pos, ident).map(Operand::Field),
+            },
+            _ => return Err(ParseError::new(pos, "expected a field or a value")),
+        };
+        Ok(Operand::Const(value))
+    }
+
+    /// Parses a field (eg. `span.name` or `attrs["key"]`)
+    fn parse_field(&mut self, pos: usize, ident: String) -> Result<Field, ParseError> {
+        let mut path = ident;
+        while self.peek() == Some(&Token::Dot) {
+            self.next();
+            match self.next() {
+                Some(Token::Ident(ident)) => {
+                    path.push('.');
+                    path.push_str(&ident);
+                }
+                _ => return Err(ParseError::new(self.offset(), "expected a field name")),
+            }
+        }
+        let key = if self.peek() == Some(&Token::LBracket) {
+            self.next();
+            let key_pos = self.offset();
+            let Some(Token::Str(key)) = self.next() else {
+                return Err(ParseError::new(key_pos, "expected a key string"));
+            };
+            self.expect(Token::RBracket, "']'")?;
+            Some(key)
+        } else {
+            None
+        };
+
+        match Field::from_path(&path, key) {
+            Some((field, None)) => Ok(field),
+            Some((field, Some(signal))) if signal == self.signal => Ok(field),
+            Some(_) => Err(ParseError::new(
+                pos,
+                format!("field '{path}' is not available for {}", self.signal),
+            )),
+            None => Err(ParseError::new(pos, format!("unknown field '{path}'"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::data::{SpanKind, SpanStatus};
+
+    use super::*;
+
+    /// Returns a span
+    fn span(name: &str, duration_ms: i128, status: u64) -> Span {
+        Span {
+            id: 1,
+            trace_id: 1,
+            name: name.to_string(),
+            start: 0,
+            end: duration_ms * 1_000_000,
+            kind: SpanKind::Server,
+            status: SpanStatus::default(),
+            attrs: HashMap::from([("http.status_code".to_string(), AttrValue::Uint(status))]),
+            ..Default::default()
+        }
+    }
+
+    /// Evaluates an expression on a span
+    fn eval(expr: &str, span: &Span) -> bool {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let expr = Expr::parse(expr, Signal::Spans).unwrap();
+        expr.eval(&ExprItem {
+            service: &service,
+            scope: None,
+            item: Item::Span(span),
+        })
+    }
+
+    #[test]
+    fn expr_eval() {
+        let slow = span("GET /users", 800, 503);
+        let fast = span("GET /health", 10, 200);
+        let expr = r#"service.name == "api" and span.duration > 500ms and attrs["http.status_code"] >= 500"#;
+        assert!(eval(expr, &slow));
+        assert!(!eval(expr, &fast));
+
+        assert!(eval(r#"span.name =~ "^GET /health$""#, &fast));
+        assert!(eval(r#"span.name =~ "^\w+ /\w+$""#, &fast));
+        assert!(eval(
+            r#"not (span.name =~ "health") && span.kind == "server""#,
+            &slow
+        ));
+        assert!(eval(
+            r#"span.name !~ "health" || span.duration < 0.5s"#,
+            &fast
+        ));
+        assert!(eval(
+            r#"attrs["missing"] == null and !attrs["missing"]"#,
+            &fast
+        ));
+        assert!(eval(r#"attrs["http.status_code"]"#, &fast));
+        assert!(eval(
+            r#"span.status != "error" and scope.name == null"#,
+            &fast
+        ));
+        // NB: values of different types are not equal
+        assert!(!eval(r#"attrs["http.status_code"] == "200""#, &fast));
+    }
+
+    #[test]
+    fn expr_parse_errors() {
+        let err = |expr: &str, signal| Expr::parse(expr, signal).unwrap_err().message;
+        assert!(err(r#"span.name == "a" and"#, Signal::Spans)
+            .starts_with("Invalid expression at position 20: expected a field or a value"));
+        assert!(err(r#"span.name == "a"#, Signal::Spans).contains("unterminated string"));
+        assert!(err("span.duration > 5 parsecs", Signal::Spans).contains("unexpected token"));
+        assert!(err("span.duration > 5x", Signal::Spans).contains("invalid number '5x'"));
+        assert!(err("log.level > 9", Signal::Spans)
+            .contains("field 'log.level' is not available for spans"));
+        assert!(err("span.foo == 1", Signal::Spans).contains("unknown field 'span.foo'"));
+        assert!(err(r#"span.name =~ "(""#, Signal::Spans).contains("invalid regex"));
+        assert!(err("(span.name == 1", Signal::Spans).contains("expected ')'"));
+    }
+}
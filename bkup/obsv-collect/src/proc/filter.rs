@@ -1,10 +1,18 @@
 //! Filter processor
 
 use async_trait::async_trait;
+use obsv_core::{
+    data::{MetricData, Scope, Service},
+    error::Error,
+};
+use serde::Deserialize;
 
 use crate::Data;
 
-use super::Processor;
+use super::{
+    expr::{Expr, ExprItem, Item, Signal},
+    Processor,
+};
 
 /// Filter processor
 #[derive(Debug, Clone)]
@@ -20,7 +28,7 @@ impl<F> FilterProcessor<F>
 where
     F: Fn(&Data) -> bool + Send + Sync + Clone,
 {
-    /// Creates a new batch processor
+    /// Creates a new filter processor
     pub fn new(rule: F) -> Self {
         Self { filter: rule }
     }
@@ -39,6 +47,388 @@ where
                 filtered.push(d);
             }
         }
-        return Some(filtered);
+        Some(filtered)
+    }
+}
+
+/// Expression filter configuration
+///
+/// # Example
+///
+/// ```toml
+/// action = "drop"
+/// spans = 'span.name == "GET /health"'
+/// logs = 'log.level < 9'
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExprFilterConfig {
+    /// Action on the matching items
+    #[serde(default)]
+    pub action: FilterAction,
+    /// Spans expression
+    pub spans: Option<String>,
+    /// Logs expression
+    pub logs: Option<String>,
+    /// Metric data points expression
+    pub metrics: Option<String>,
+}
+
+/// Action of a filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// The matching items are dropped
+    #[default]
+    Drop,
+    /// Only the matching items are kept
+    Keep,
+}
+
+impl ExprFilterConfig {
+    /// Loads the configuration from a TOML string
+    pub fn from_toml(content: &str) -> Result<Self, Error> {
+        toml::from_str(content)
+            .map_err(|err| Error::string(format!("Invalid filter config: {err}")))
+    }
+}
+
+/// Expression filter processor
+///
+/// The spans, logs and metric data points are filtered by an expression (see [Expr]).
+/// A signal without an expression is not filtered.
+#[derive(Debug, Clone)]
+pub struct ExprFilterProcessor {
+    /// Action
+    action: FilterAction,
+    /// Spans expression
+    spans: Option<Expr>,
+    /// Logs expression
+    logs: Option<Expr>,
+    /// Metric data points expression
+    metrics: Option<Expr>,
+}
+
+impl ExprFilterProcessor {
+    /// Creates a new expression filter processor
+    pub fn new(config: ExprFilterConfig) -> Result<Self, Error> {
+        let parse =
+            |expr: Option<String>, signal| expr.map(|e| Expr::parse(&e, signal)).transpose();
+        Ok(Self {
+            action: config.action,
+            spans: parse(config.spans, Signal::Spans)?,
+            logs: parse(config.logs, Signal::Logs)?,
+            metrics: parse(config.metrics, Signal::Metrics)?,
+        })
+    }
+
+    /// Checks if an item is kept
+    fn keep(&self, expr: &Expr, service: &Service, scope: Option<&Scope>, item: Item) -> bool {
+        let item = ExprItem {
+            service,
+            scope,
+            item,
+        };
+        expr.eval(&item) == (self.action == FilterAction::Keep)
+    }
+}
+
+/// Evaluates an expression with the data points of a metric
+macro_rules! with_points {
+    ($data:expr, $points:ident => $e:expr) => {
+        match $data {
+            MetricData::Gauge { $points } | MetricData::Sum { $points, .. } => $e,
+            MetricData::Histogram { $points, .. } => $e,
+            MetricData::ExpHistogram { $points, .. } => $e,
+            MetricData::Summary { $points } => $e,
+        }
+    };
+}
+
+#[async_trait]
+impl Processor for ExprFilterProcessor {
+    async fn process(&mut self, mut data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("expression filter processing");
+        for d in &mut data {
+            match d {
+                Data::Traces(traces) => {
+                    let Some(expr) = &self.spans else {
+                        continue;
+                    };
+                    for group in &mut traces.spans {
+                        let (service, scope) = (&group.service, group.scope.as_ref());
+                        group
+                            .spans
+                            .retain(|span| self.keep(expr, service, scope, Item::Span(span)));
+                    }
+                    traces.spans.retain(|group| !group.spans.is_empty());
+                }
+                Data::Logs(logs) => {
+                    let Some(expr) = &self.logs else {
+                        continue;
+                    };
+                    for group in &mut logs.logs {
+                        let (service, scope) = (&group.service, group.scope.as_ref());
+                        group
+                            .logs
+                            .retain(|log| self.keep(expr, service, scope, Item::Log(log)));
+                    }
+                    logs.logs.retain(|group| !group.logs.is_empty());
+                }
+                Data::Metrics(metrics) => {
+                    let Some(expr) = &self.metrics else {
+                        continue;
+                    };
+                    for group in &mut metrics.metrics {
+                        let (service, scope) = (&group.service, group.scope.as_ref());
+                        for metric in &mut group.metrics {
+                            let flags: Vec<bool> = with_points!(&metric.data, points => {
+                                points
+                                    .iter()
+                                    .map(|p| {
+                                        let item = Item::Point {
+                                            metric,
+                                            attrs: &p.attrs,
+                                        };
+                                        self.keep(expr, service, scope, item)
+                                    })
+                                    .collect()
+                            });
+                            let mut flags = flags.into_iter();
+                            with_points!(&mut metric.data, points => {
+                                points.retain(|_| flags.next().unwrap_or(true))
+                            });
+                        }
+                        group.metrics.retain(|metric| !metric.data.is_empty());
+                    }
+                    metrics.metrics.retain(|group| !group.metrics.is_empty());
+                }
+            }
+        }
+        data.retain(|d| !d.is_empty());
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::data::{
+        AggregationTemporality, AttrValue, HistogramDataPoint, Log, LogData, Metric, MetricsData,
+        NumberDataPoint, NumberValue, ServiceLogs, ServiceMetrics, Span, TraceData,
+    };
+
+    use super::*;
+
+    /// Returns a service
+    fn service(name: &str) -> Service {
+        Service {
+            name: name.to_string(),
+            attrs: HashMap::new(),
+        }
+    }
+
+    /// Returns logs with levels
+    fn logs(levels: &[i16]) -> Data {
+        Data::Logs(LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: levels
+                    .iter()
+                    .map(|level| Log {
+                        trace_id: 0,
+                        span_id: 0,
+                        timestamp: 1,
+                        level: *level,
+                        message: "hello".to_string(),
+                        attrs: HashMap::new(),
+                    })
+                    .collect(),
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn expr_filter_logs() {
+        let config = ExprFilterConfig::from_toml("logs = 'log.level < 9'").unwrap();
+        let mut processor = ExprFilterProcessor::new(config).unwrap();
+        let data = processor.process(vec![logs(&[5, 9, 13])]).await.unwrap();
+        assert_eq!(data, vec![logs(&[9, 13])]);
+        // NB: empty data is removed
+        assert_eq!(processor.process(vec![logs(&[1])]).await.unwrap(), vec![]);
+
+        let config = ExprFilterConfig::from_toml(
+            r#"
+            action = "keep"
+            logs = 'service.name == "my_service" and log.level >= 13'
+            "#,
+        )
+        .unwrap();
+        let mut processor = ExprFilterProcessor::new(config).unwrap();
+        let data = processor.process(vec![logs(&[5, 9, 13])]).await.unwrap();
+        assert_eq!(data, vec![logs(&[13])]);
+
+        let config = ExprFilterConfig::from_toml("spans = 'log.level < 9'").unwrap();
+        assert!(ExprFilterProcessor::new(config).is_err());
+    }
+
+    /// Returns spans with names, by service
+    fn traces(spans: &[(&str, &str)]) -> Data {
+        let mut data = TraceData::default();
+        for (i, (service_name, name)) in spans.iter().enumerate() {
+            let span = Span {
+                id: i as u64,
+                trace_id: 1,
+                name: name.to_string(),
+                ..Default::default()
+            };
+            data.add_span(&service(service_name), None, span);
+        }
+        Data::Traces(data)
+    }
+
+    #[tokio::test]
+    async fn expr_filter_spans() {
+        let config =
+            ExprFilterConfig::from_toml(r#"spans = 'span.name == "GET /health"'"#).unwrap();
+        let mut processor = ExprFilterProcessor::new(config).unwrap();
+        let data = processor
+            .process(vec![traces(&[
+                ("web", "GET /health"),
+                ("web", "GET /users"),
+                ("api", "GET /health"),
+            ])])
+            .await
+            .unwrap();
+        // NB: the service without spans is removed
+        let Data::Traces(data) = &data[0] else {
+            panic!("no traces");
+        };
+        assert_eq!(data.spans.len(), 1);
+        assert_eq!(data.spans[0].service.name, "web");
+        assert_eq!(data.spans[0].spans.len(), 1);
+        assert_eq!(data.spans[0].spans[0].name, "GET /users");
+
+        let data = vec![traces(&[("api", "GET /health")]), logs(&[1])];
+        assert_eq!(processor.process(data).await.unwrap(), vec![logs(&[1])]);
+    }
+
+    /// Returns a data point attribute
+    fn host(name: &str) -> HashMap<String, AttrValue> {
+        HashMap::from([("host".to_string(), AttrValue::String(name.to_string()))])
+    }
+
+    /// Returns a gauge with 1 data point per host
+    fn gauge(name: &str, hosts: &[&str]) -> Metric {
+        let points = hosts
+            .iter()
+            .map(|h| NumberDataPoint {
+                attrs: host(h),
+                start: 0,
+                timestamp: 1,
+                value: NumberValue::Int(1),
+                exemplars: vec![],
+                flags: 0,
+            })
+            .collect();
+        Metric {
+            name: name.to_string(),
+            descr: String::new(),
+            unit: String::new(),
+            data: MetricData::Gauge { points },
+        }
+    }
+
+    /// Returns a histogram with 1 data point per host
+    fn histogram(name: &str, hosts: &[&str]) -> Metric {
+        let points = hosts
+            .iter()
+            .map(|h| HistogramDataPoint {
+                attrs: host(h),
+                start: 0,
+                timestamp: 1,
+                count: 1,
+                sum: Some(1.0),
+                bucket_counts: vec![1, 0],
+                bounds: vec![10.0],
+                min: None,
+                max: None,
+                exemplars: vec![],
+                flags: 0,
+            })
+            .collect();
+        Metric {
+            name: name.to_string(),
+            descr: String::new(),
+            unit: String::new(),
+            data: MetricData::Histogram {
+                points,
+                temporality: AggregationTemporality::Delta,
+            },
+        }
+    }
+
+    /// Returns metrics by service
+    fn metrics(groups: Vec<(&str, Vec<Metric>)>) -> Data {
+        Data::Metrics(MetricsData {
+            metrics: groups
+                .into_iter()
+                .map(|(name, metrics)| ServiceMetrics {
+                    service: service(name),
+                    scope: None,
+                    metrics,
+                })
+                .collect(),
+        })
+    }
+
+    #[tokio::test]
+    async fn expr_filter_metrics() {
+        let config = ExprFilterConfig::from_toml(
+            r#"metrics = 'metric.name != "cpu" or attrs["host"] == "a"'"#,
+        )
+        .unwrap();
+        let mut processor = ExprFilterProcessor::new(config).unwrap();
+        let data = processor
+            .process(vec![metrics(vec![
+                (
+                    "web",
+                    vec![gauge("cpu", &["a", "b"]), histogram("latency", &["a"])],
+                ),
+                ("api", vec![histogram("latency", &["b"])]),
+            ])])
+            .await
+            .unwrap();
+        // NB: the data points are filtered, then the empty metrics and services are removed
+        assert_eq!(
+            data,
+            vec![metrics(vec![("web", vec![gauge("cpu", &["b"])])])]
+        );
+
+        let config = ExprFilterConfig::from_toml(
+            r#"
+            action = "keep"
+            metrics = 'attrs["host"] == "b"'
+            "#,
+        )
+        .unwrap();
+        let mut processor = ExprFilterProcessor::new(config).unwrap();
+        let data = processor
+            .process(vec![metrics(vec![(
+                "web",
+                vec![histogram("latency", &["a", "b", "a"])],
+            )])])
+            .await
+            .unwrap();
+        assert_eq!(
+            data,
+            vec![metrics(vec![("web", vec![histogram("latency", &["b"])])])]
+        );
+        let data = vec![metrics(vec![("web", vec![gauge("cpu", &["a"])])])];
+        assert_eq!(processor.process(data).await.unwrap(), vec![]);
     }
 }
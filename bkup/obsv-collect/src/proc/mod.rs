@@ -7,9 +7,17 @@ use dyn_clone::DynClone;
 
 use crate::Data;
 
+pub mod attrs;
 pub mod batch;
+pub mod correlate;
+pub mod expr;
 pub mod filter;
 pub mod id;
+pub mod redact;
+pub mod resource;
+pub mod sampling;
+pub mod servicegraph;
+pub mod spanmetrics;
 
 /// Processor
 #[async_trait]
@@ -18,6 +26,20 @@ pub trait Processor: Send + Sync + DynClone {
     ///
     /// The data is returned once processed, or None if the data processed latter
     async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>>;
+
+    /// Returns the data which is ready to be released (eg. after a timeout)
+    ///
+    /// This is called periodically.
+    async fn tick(&mut self) -> Option<Vec<Data>> {
+        None
+    }
+
+    /// Returns all the buffered data
+    ///
+    /// This is called when the service is shut down.
+    async fn flush(&mut self) -> Vec<Data> {
+        vec![]
+    }
 }
 
 dyn_clone::clone_trait_object!(Processor);
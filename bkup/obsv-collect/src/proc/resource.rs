@@ -0,0 +1,372 @@
+//! Resource detection processor
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use obsv_core::data::{AttrValue, Service};
+use obsv_otlp::conv::SemConv;
+
+use crate::Data;
+
+use super::Processor;
+
+/// Environment variables mapped to resource attributes
+const ENV_ATTRS: [(&str, &str); 9] = [
+    ("K8S_CLUSTER_NAME", SemConv::K8S_CLUSTER_NAME),
+    ("K8S_NODE_NAME", SemConv::K8S_NODE_NAME),
+    ("K8S_NAMESPACE_NAME", SemConv::K8S_NAMESPACE_NAME),
+    ("K8S_POD_NAME", SemConv::K8S_POD_NAME),
+    ("K8S_POD_UID", SemConv::K8S_POD_UID),
+    ("CLOUD_PROVIDER", SemConv::CLOUD_ID),
+    ("CLOUD_REGION", SemConv::CLOUD_REGION),
+    ("CLOUD_AVAILABILITY_ZONE", SemConv::CLOUD_AVAILABILITY_ZONE),
+    ("CLOUD_ACCOUNT_ID", SemConv::CLOUD_ACCOUNT_ID),
+];
+
+/// Resource detector
+///
+/// NB: the attributes describe the collector host and process, so the host and OS attributes only
+/// describe the services when the collector runs on the same host (agent), not as a gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceDetector {
+    /// Host name and architecture
+    Host,
+    /// Operating system (from `/etc/os-release`)
+    Os,
+    /// Current process (the collector)
+    ///
+    /// The command arguments are not detected, since they may contain secrets.
+    Process,
+    /// Container ID (from the cgroup files)
+    Container,
+    /// Environment variables
+    ///
+    /// The attributes are read from `OTEL_RESOURCE_ATTRIBUTES` (`key1=value1,key2=value2`),
+    /// and from variables such as `K8S_POD_NAME` or `CLOUD_REGION` (eg. set with the k8s downward API).
+    Env,
+}
+
+impl ResourceDetector {
+    /// Detects the resource attributes
+    pub fn detect(&self) -> HashMap<String, AttrValue> {
+        let mut attrs = HashMap::new();
+        let mut insert = |key: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                attrs.insert(key.to_string(), AttrValue::String(value));
+            }
+        };
+        match self {
+            ResourceDetector::Host => {
+                let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .ok()
+                    .or_else(|| std::env::var("HOSTNAME").ok());
+                insert(SemConv::HOST_NAME, name.map(|n| n.trim().to_string()));
+                insert(SemConv::HOST_ARCH, Some(host_arch().to_string()));
+            }
+            ResourceDetector::Os => {
+                insert(SemConv::OS_TYPE, Some(std::env::consts::OS.to_string()));
+                if let Ok(release) = std::fs::read_to_string("/etc/os-release") {
+                    let release = os_release(&release);
+                    insert(SemConv::OS_NAME, release.get("NAME").cloned());
+                    insert(SemConv::OS_VERSION, release.get("VERSION_ID").cloned());
+                    insert(SemConv::OS_DESCRIPTION, release.get("PRETTY_NAME").cloned());
+                }
+            }
+            ResourceDetector::Process => {
+                let exe = std::env::current_exe().ok();
+                insert(
+                    SemConv::PROCESS_EXECUTABLE_NAME,
+                    exe.as_ref()
+                        .and_then(|p| p.file_name())
+                        .map(|n| n.to_string_lossy().to_string()),
+                );
+                insert(
+                    SemConv::PROCESS_EXECUTABLE_PATH,
+                    exe.map(|p| p.to_string_lossy().to_string()),
+                );
+                insert(SemConv::PROCESS_OWNER, std::env::var("USER").ok());
+                attrs.insert(
+                    SemConv::PROCESS_PID.to_string(),
+                    AttrValue::Int(std::process::id().into()),
+                );
+            }
+            ResourceDetector::Container => {
+                // NB: cgroup v1 has the ID in the cgroup paths, cgroup v2 in the mount points
+                let id = ["/proc/self/cgroup", "/proc/self/mountinfo"]
+                    .iter()
+                    .filter_map(|path| std::fs::read_to_string(path).ok())
+                    .find_map(|content| container_id(&content));
+                insert(SemConv::CONTAINER_ID, id);
+            }
+            ResourceDetector::Env => {
+                attrs = env_attrs(std::env::vars());
+            }
+        }
+        attrs
+    }
+}
+
+/// Resource detection processor
+///
+/// The detected attributes are merged into the service attributes.
+/// By default, the attributes already set by the service are kept.
+///
+/// NB: the host and OS detectors must only be used when the collector runs on the same host
+/// as the services (agent). In a gateway, they would stamp the gateway host on all the services,
+/// in particular with [ResourceProcessor::overwrite].
+/// The process detector is ignored, since the collector process is not the service process.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceProcessor {
+    /// Attributes
+    attrs: HashMap<String, AttrValue>,
+    /// Are the existing attributes overridden
+    overwrite: bool,
+}
+
+impl ResourceProcessor {
+    /// Creates a new resource processor, without attributes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the attributes of a detector
+    ///
+    /// The attributes of the latter detectors take precedence.
+    pub fn detect(mut self, detector: ResourceDetector) -> Self {
+        if detector == ResourceDetector::Process {
+            log::warn!("the process detector describes the collector, it is ignored");
+            return self;
+        }
+        self.attrs.extend(detector.detect());
+        self
+    }
+
+    /// Adds an attribute
+    pub fn attr(mut self, key: &str, value: AttrValue) -> Self {
+        self.attrs.insert(key.to_string(), value);
+        self
+    }
+
+    /// Overrides the existing service attributes
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Returns the attributes
+    pub fn attrs(&self) -> &HashMap<String, AttrValue> {
+        &self.attrs
+    }
+
+    /// Merges the attributes into a service
+    fn enrich(&self, service: &mut Service) {
+        for (key, value) in &self.attrs {
+            if self.overwrite {
+                service.attrs.insert(key.clone(), value.clone());
+            } else {
+                service
+                    .attrs
+                    .entry(key.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for ResourceProcessor {
+    async fn process(&mut self, mut data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("resource processing");
+        for d in &mut data {
+            match d {
+                Data::Traces(traces) => traces
+                    .spans
+                    .iter_mut()
+                    .for_each(|g| self.enrich(&mut g.service)),
+                Data::Logs(logs) => logs
+                    .logs
+                    .iter_mut()
+                    .for_each(|g| self.enrich(&mut g.service)),
+                Data::Metrics(metrics) => metrics
+                    .metrics
+                    .iter_mut()
+                    .for_each(|g| self.enrich(&mut g.service)),
+            }
+        }
+        Some(data)
+    }
+}
+
+/// Returns the host architecture (semantic convention value)
+fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        "arm" => "arm32",
+        "powerpc" => "ppc32",
+        "powerpc64" => "ppc64",
+        "s390x" => "s390x",
+        arch => arch,
+    }
+}
+
+/// Parses the content of `/etc/os-release`
+fn os_release(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Extracts a container ID (64 hex characters) from the content of a cgroup file
+fn container_id(content: &str) -> Option<String> {
+    content
+        .lines()
+        .flat_map(|line| line.split(['/', ' ']))
+        .map(|segment| {
+            // NB: systemd uses scopes such as `docker-<id>.scope`
+            let segment = segment.trim_end_matches(".scope");
+            segment.rsplit_once('-').map_or(segment, |(_, id)| id)
+        })
+        .find(|id| id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|id| id.to_string())
+}
+
+/// Returns the resource attributes of environment variables
+fn env_attrs(vars: impl Iterator<Item = (String, String)>) -> HashMap<String, AttrValue> {
+    let vars = vars.collect::<HashMap<_, _>>();
+    let mut attrs = HashMap::new();
+    for (var, key) in ENV_ATTRS {
+        if let Some(value) = vars.get(var).filter(|v| !v.is_empty()) {
+            attrs.insert(key.to_string(), AttrValue::String(value.clone()));
+        }
+    }
+    // NB: the OTEL variable takes precedence
+    if let Some(resource) = vars.get("OTEL_RESOURCE_ATTRIBUTES") {
+        for (key, value) in resource.split(',').filter_map(|kv| kv.split_once('=')) {
+            attrs.insert(
+                key.trim().to_string(),
+                AttrValue::String(value.trim().to_string()),
+            );
+        }
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::data::{LogData, ServiceLogs};
+
+    use super::*;
+
+    #[test]
+    fn resource_detectors() {
+        let id = "a3f6c2b1d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f";
+        let cgroup_v1 = format!("12:pids:/docker/{id}\n11:cpu:/docker/{id}\n");
+        assert_eq!(container_id(&cgroup_v1).as_deref(), Some(id));
+        let systemd = format!("0::/system.slice/docker-{id}.scope\n");
+        assert_eq!(container_id(&systemd).as_deref(), Some(id));
+        let mountinfo = format!(
+            "736 712 0:95 /docker/containers/{id}/hostname /etc/hostname rw - ext4 /dev/sda1 rw\n"
+        );
+        assert_eq!(container_id(&mountinfo).as_deref(), Some(id));
+        assert_eq!(container_id("0::/\n"), None);
+
+        let release = os_release("NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\n");
+        assert_eq!(release.get("NAME").map(String::as_str), Some("Ubuntu"));
+        assert_eq!(release.get("VERSION_ID").map(String::as_str), Some("22.04"));
+
+        let attrs = env_attrs(
+            [
+                ("K8S_POD_NAME", "api-7d9f"),
+                ("CLOUD_REGION", "eu-west-1"),
+                (
+                    "OTEL_RESOURCE_ATTRIBUTES",
+                    "cloud.region=us-east-1,team=core",
+                ),
+                ("PATH", "/usr/bin"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        assert_eq!(
+            attrs,
+            HashMap::from([
+                (
+                    "k8s.pod.name".to_string(),
+                    AttrValue::String("api-7d9f".to_string())
+                ),
+                (
+                    "cloud.region".to_string(),
+                    AttrValue::String("us-east-1".to_string())
+                ),
+                ("team".to_string(), AttrValue::String("core".to_string())),
+            ])
+        );
+
+        let attrs = ResourceDetector::Process.detect();
+        assert_eq!(
+            attrs.get(SemConv::PROCESS_PID),
+            Some(&AttrValue::Int(std::process::id().into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn resource_processor() {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::from([(
+                SemConv::HOST_NAME.to_string(),
+                AttrValue::String("service-host".to_string()),
+            )]),
+        };
+        let data = vec![Data::Logs(LogData {
+            logs: vec![ServiceLogs {
+                service,
+                scope: None,
+                logs: vec![],
+            }],
+        })];
+        let service_attrs = |data: Vec<Data>| match &data[0] {
+            Data::Logs(logs) => logs.logs[0].service.attrs.clone(),
+            _ => panic!("invalid data"),
+        };
+
+        let mut processor = ResourceProcessor::new()
+            .attr(SemConv::HOST_NAME, AttrValue::String("node-1".to_string()))
+            .attr(SemConv::CLOUD_REGION, AttrValue::String("eu".to_string()));
+        let attrs = service_attrs(processor.process(data.clone()).await.unwrap());
+        assert_eq!(
+            attrs.get(SemConv::HOST_NAME),
+            Some(&AttrValue::String("service-host".to_string()))
+        );
+        assert_eq!(
+            attrs.get(SemConv::CLOUD_REGION),
+            Some(&AttrValue::String("eu".to_string()))
+        );
+
+        let mut processor = processor.overwrite(true);
+        let attrs = service_attrs(processor.process(data).await.unwrap());
+        assert_eq!(
+            attrs.get(SemConv::HOST_NAME),
+            Some(&AttrValue::String("node-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn resource_process_detector() {
+        let attrs = ResourceDetector::Process.detect();
+        assert!(!attrs.contains_key(SemConv::PROCESS_COMMAND_ARGS));
+
+        // NB: the collector process attributes are not merged into the services
+        let processor = ResourceProcessor::new().detect(ResourceDetector::Process);
+        assert!(processor.attrs().is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! Head sampling processor
+
+use async_trait::async_trait;
+use obsv_core::{
+    data::{AttrValue, Context},
+    http::TraceParentHeader,
+};
+
+use crate::{proc::Processor, Data};
+
+use super::is_sampled;
+
+/// Span attribute holding the effective sampling probability
+///
+/// A sampled span stands for `1 / probability` spans.
+pub const SAMPLING_PROBABILITY_ATTR: &str = "sampling.probability";
+
+/// Head sampling processor
+///
+/// A fraction of the traces is kept, based on their trace ID, so all the collectors keep the same traces.
+/// It is stateless, and the spans of a trace are kept or dropped without buffering.
+///
+/// The effective sampling probability is recorded on the kept spans.
+///
+/// NB: the received spans do not carry the W3C trace flags, so the pipeline cannot honour
+/// the upstream sampled flag. The spans which are not sampled upstream are usually not exported
+/// by the SDKs; [HeadSamplingProcessor::sample_parent] and [HeadSamplingProcessor::sample_context]
+/// apply the same decision with the flag, where the `traceparent` header or context is available.
+#[derive(Debug, Clone)]
+pub struct HeadSamplingProcessor {
+    /// Sampling probability (between 0 and 1)
+    probability: f64,
+}
+
+impl HeadSamplingProcessor {
+    /// Creates a new head sampling processor, with a sampling probability (between 0 and 1)
+    pub fn new(probability: f64) -> Self {
+        Self {
+            probability: probability.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Checks if a trace is sampled
+    pub fn sample(&self, trace_id: u128) -> bool {
+        is_sampled(trace_id, self.probability)
+    }
+
+    /// Checks if a trace is sampled, given its `traceparent` header
+    ///
+    /// A trace which is not sampled upstream (W3C sampled flag) is never sampled.
+    pub fn sample_parent(&self, header: &TraceParentHeader) -> bool {
+        header.is_sampled() && self.sample(header.trace_id)
+    }
+
+    /// Checks if a trace is sampled, given its context
+    ///
+    /// A trace which is not sampled upstream (W3C sampled flag) is never sampled.
+    pub fn sample_context(&self, ctx: &Context) -> bool {
+        ctx.is_sampled() && self.sample(ctx.trace_id)
+    }
+
+    /// Returns the effective sampling probability of a span
+    ///
+    /// The sampling is consistent, so the effective probability after
+    /// being sampled upstream is the lowest probability.
+    fn effective_probability(&self, upstream: Option<&AttrValue>) -> f64 {
+        match upstream {
+            Some(AttrValue::Float(p)) => p.min(self.probability),
+            _ => self.probability,
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for HeadSamplingProcessor {
+    async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("head sampling processing");
+        let data = data
+            .into_iter()
+            .filter_map(|d| match d {
+                Data::Traces(mut traces) => {
+                    for group in &mut traces.spans {
+                        group.spans.retain(|span| self.sample(span.trace_id));
+                        for span in &mut group.spans {
+                            let probability = self
+                                .effective_probability(span.attrs.get(SAMPLING_PROBABILITY_ATTR));
+                            span.attrs.insert(
+                                SAMPLING_PROBABILITY_ATTR.to_string(),
+                                AttrValue::Float(probability),
+                            );
+                        }
+                    }
+                    traces.spans.retain(|g| !g.spans.is_empty());
+                    if traces.spans.is_empty() {
+                        None
+                    } else {
+                        Some(Data::Traces(traces))
+                    }
+                }
+                // NB: the other signals are not sampled
+                d => Some(d),
+            })
+            .collect::<Vec<_>>();
+        if data.is_empty() {
+            None
+        } else {
+            Some(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::data::{Service, Span, TraceData};
+
+    use super::*;
+
+    /// Returns a root span
+    fn span(trace_id: u128) -> Span {
+        Span {
+            id: 1,
+            trace_id,
+            name: "span".to_string(),
+            start: 0,
+            end: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn head_sampling() {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut data = TraceData::default();
+        let step = (u64::MAX / 1000) as u128;
+        for i in 0..1000u128 {
+            // NB: the trace IDs are spread over the u64 range
+            data.add_span(&service, None, span(i * step + step / 2));
+        }
+
+        let mut processor = HeadSamplingProcessor::new(0.25);
+        let sampled = processor
+            .process(vec![Data::Traces(data.clone())])
+            .await
+            .unwrap();
+        let Data::Traces(traces) = &sampled[0] else {
+            panic!("invalid data");
+        };
+        assert_eq!(traces.len(), 250);
+        for span in traces.spans.iter().flat_map(|g| g.spans.iter()) {
+            assert!(processor.sample(span.trace_id));
+            assert_eq!(
+                span.attrs.get(SAMPLING_PROBABILITY_ATTR),
+                Some(&AttrValue::Float(0.25))
+            );
+        }
+
+        // NB: the sampling is consistent, so a higher probability keeps the same traces and probability
+        let mut processor = HeadSamplingProcessor::new(0.5);
+        let resampled = processor.process(sampled.clone()).await.unwrap();
+        assert_eq!(resampled, sampled);
+        assert_eq!(HeadSamplingProcessor::new(0.0).process(sampled).await, None);
+    }
+
+    #[test]
+    fn head_sampling_flags() {
+        let processor = HeadSamplingProcessor::new(1.0);
+        let mut header = TraceParentHeader {
+            version: 0,
+            trace_id: 1,
+            parent_id: 1,
+            flags: TraceParentHeader::SAMPLED,
+        };
+        assert!(processor.sample_parent(&header));
+        header.flags = 0;
+        assert!(!processor.sample_parent(&header));
+
+        let ctx = Context {
+            trace_id: 1,
+            span_id: 1,
+            flags: 0,
+            state: HashMap::new(),
+        };
+        assert!(!processor.sample_context(&ctx));
+    }
+}
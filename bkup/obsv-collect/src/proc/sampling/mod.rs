@@ -0,0 +1,17 @@
+//! Sampling processors
+
+pub mod head;
+pub mod tail;
+
+/// Returns the position of a trace in [0, 1), for probabilistic sampling
+///
+/// The trace ID is random (W3C trace context), so its lower 64 bits are used.
+/// The position is the same for all the spans of a trace, and across collectors.
+pub fn trace_ratio(trace_id: u128) -> f64 {
+    (trace_id as u64 >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Checks if a trace is sampled with a probability
+pub fn is_sampled(trace_id: u128, probability: f64) -> bool {
+    trace_ratio(trace_id) < probability
+}
@@ -0,0 +1,425 @@
+//! Tail sampling processor
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use obsv_core::{
+    data::{Span, TraceData},
+    error::Error,
+};
+use tokio::time::Instant;
+
+use crate::{
+    proc::{
+        expr::{Expr, ExprItem, Item, Signal},
+        Processor,
+    },
+    Data,
+};
+
+use super::is_sampled;
+
+/// Tail sampling policy
+///
+/// A trace is sampled if any policy samples it.
+#[derive(Debug, Clone)]
+pub enum TailPolicy {
+    /// Samples the traces with a span in error
+    StatusError,
+    /// Samples the traces whose root span is longer than a threshold
+    ///
+    /// Without a root span, the duration of the whole trace is used.
+    Latency(Duration),
+    /// Samples the traces with a span matching an expression (see [Expr])
+    Expr(Expr),
+    /// Samples a fraction of the traces (between 0 and 1)
+    Probabilistic(f64),
+    /// Samples a maximum number of traces per second, per service (of the root span)
+    RateLimit(u32),
+}
+
+impl TailPolicy {
+    /// Creates a policy which samples the traces with a span matching an expression
+    pub fn expr(expr: &str) -> Result<Self, Error> {
+        Ok(Self::Expr(Expr::parse(expr, Signal::Spans)?))
+    }
+}
+
+/// Tail sampling processor
+///
+/// The spans are buffered per trace, and a trace is sampled or dropped as a whole
+/// when its decision window has elapsed since its first span.
+///
+/// The number of buffered traces is bounded: when the limit is reached, the decision is made for the oldest trace.
+/// The decisions are kept for a while, so the late spans of a trace follow its decision.
+#[derive(Debug, Clone)]
+pub struct TailSamplingProcessor {
+    /// Decision window
+    decision_wait: Duration,
+    /// Maximum number of buffered traces
+    max_traces: usize,
+    /// Policies
+    policies: Vec<TailPolicy>,
+    /// Buffered traces
+    traces: HashMap<u128, PendingTrace>,
+    /// Buffered traces, in order of arrival
+    pending: VecDeque<u128>,
+    /// Decided traces (trace ID -> sampled)
+    decisions: HashMap<u128, bool>,
+    /// Decided traces, in order of decision
+    decided: VecDeque<u128>,
+    /// Rate limits per service (window start, number of sampled traces)
+    rates: HashMap<String, (Instant, u32)>,
+}
+
+/// A buffered trace
+#[derive(Debug, Clone)]
+struct PendingTrace {
+    /// Time of the first span
+    created: Instant,
+    /// Spans
+    data: TraceData,
+}
+
+impl Default for TailSamplingProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TailSamplingProcessor {
+    /// Creates a new tail sampling processor
+    ///
+    /// Without policies, all the traces are dropped.
+    pub fn new() -> Self {
+        Self {
+            decision_wait: Duration::from_secs(10),
+            max_traces: 50_000,
+            policies: vec![],
+            traces: HashMap::new(),
+            pending: VecDeque::new(),
+            decisions: HashMap::new(),
+            decided: VecDeque::new(),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Sets the decision window
+    pub fn decision_wait(mut self, wait: Duration) -> Self {
+        self.decision_wait = wait;
+        self
+    }
+
+    /// Sets the maximum number of buffered traces
+    pub fn max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = max_traces.max(1);
+        self
+    }
+
+    /// Adds a policy
+    pub fn policy(mut self, policy: TailPolicy) -> Self {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Buffers the spans, and returns the spans of the already sampled traces
+    fn add(&mut self, data: TraceData, sampled: &mut TraceData) {
+        for group in data.spans {
+            for span in group.spans {
+                let trace_id = span.trace_id;
+                match self.decisions.get(&trace_id) {
+                    Some(true) => sampled.add_span(&group.service, group.scope.as_ref(), span),
+                    Some(false) => {}
+                    None => {
+                        if !self.traces.contains_key(&trace_id) {
+                            if self.traces.len() >= self.max_traces {
+                                log::warn!("too many buffered traces, sampling the oldest trace");
+                                self.decide_oldest(sampled);
+                            }
+                            self.pending.push_back(trace_id);
+                        }
+                        self.traces
+                            .entry(trace_id)
+                            .or_insert_with(|| PendingTrace {
+                                created: Instant::now(),
+                                data: TraceData::default(),
+                            })
+                            .data
+                            .add_span(&group.service, group.scope.as_ref(), span);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes the decision for the oldest trace
+    fn decide_oldest(&mut self, sampled: &mut TraceData) -> bool {
+        let Some(trace_id) = self.pending.pop_front() else {
+            return false;
+        };
+        let Some(trace) = self.traces.remove(&trace_id) else {
+            return true;
+        };
+
+        let keep = self.sample(trace_id, &trace.data);
+        if keep {
+            for group in trace.data.spans {
+                for span in group.spans {
+                    sampled.add_span(&group.service, group.scope.as_ref(), span);
+                }
+            }
+        }
+
+        // NB: the decisions are bounded like the traces
+        self.decisions.insert(trace_id, keep);
+        self.decided.push_back(trace_id);
+        while self.decided.len() > self.max_traces {
+            if let Some(id) = self.decided.pop_front() {
+                self.decisions.remove(&id);
+            }
+        }
+        true
+    }
+
+    /// Applies the policies to a trace
+    fn sample(&mut self, trace_id: u128, data: &TraceData) -> bool {
+        let spans = || data.spans.iter().flat_map(|g| g.spans.iter());
+        let root = data.spans.iter().find_map(|g| {
+            g.spans
+                .iter()
+                .find(|s| s.parent_id.is_none())
+                .map(|s| (g, s))
+        });
+
+        for policy in &self.policies {
+            let sampled = match policy {
+                TailPolicy::StatusError => spans().any(|s| s.status.is_error()),
+                TailPolicy::Latency(threshold) => {
+                    let duration = match root {
+                        Some((_, span)) => span.end - span.start,
+                        None => {
+                            spans().map(|s| s.end).max().unwrap_or_default()
+                                - spans().map(|s| s.start).min().unwrap_or_default()
+                        }
+                    };
+                    duration >= threshold.as_nanos() as i128
+                }
+                TailPolicy::Expr(expr) => data.spans.iter().any(|g| {
+                    g.spans.iter().any(|span: &Span| {
+                        expr.eval(&ExprItem {
+                            service: &g.service,
+                            scope: g.scope.as_ref(),
+                            item: Item::Span(span),
+                        })
+                    })
+                }),
+                TailPolicy::Probabilistic(probability) => is_sampled(trace_id, *probability),
+                TailPolicy::RateLimit(limit) => {
+                    let service = root
+                        .map(|(g, _)| &g.service)
+                        .or(data.spans.first().map(|g| &g.service))
+                        .map(|s| s.name.clone())
+                        .unwrap_or_default();
+                    let now = Instant::now();
+                    let (start, count) = self.rates.entry(service).or_insert((now, 0));
+                    if now.duration_since(*start) >= Duration::from_secs(1) {
+                        *start = now;
+                        *count = 0;
+                    }
+                    if *count < *limit {
+                        *count += 1;
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+            if sampled {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the sampled traces, if any
+    fn output(sampled: TraceData) -> Option<Vec<Data>> {
+        if sampled.is_empty() {
+            None
+        } else {
+            Some(vec![Data::Traces(sampled)])
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for TailSamplingProcessor {
+    async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("tail sampling processing");
+        let mut output = vec![];
+        let mut sampled = TraceData::default();
+        for d in data {
+            match d {
+                Data::Traces(traces) => self.add(traces, &mut sampled),
+                // NB: the other signals are not sampled
+                d => output.push(d),
+            }
+        }
+        if !sampled.is_empty() {
+            output.push(Data::Traces(sampled));
+        }
+        if output.is_empty() {
+            None
+        } else {
+            Some(output)
+        }
+    }
+
+    async fn tick(&mut self) -> Option<Vec<Data>> {
+        let mut sampled = TraceData::default();
+        while let Some(trace_id) = self.pending.front() {
+            let expired = self
+                .traces
+                .get(trace_id)
+                .map_or(true, |t| t.created.elapsed() >= self.decision_wait);
+            if !expired {
+                break;
+            }
+            self.decide_oldest(&mut sampled);
+        }
+
+        // NB: the rate limits of idle services are removed
+        self.rates
+            .retain(|_, (start, _)| start.elapsed() < Duration::from_secs(1));
+        Self::output(sampled)
+    }
+
+    async fn flush(&mut self) -> Vec<Data> {
+        let mut sampled = TraceData::default();
+        while self.decide_oldest(&mut sampled) {}
+        Self::output(sampled).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use obsv_core::data::{AttrValue, Service, SpanStatus, StatusCode};
+
+    use super::*;
+
+    /// Returns the trace IDs of data
+    fn trace_ids(data: &[Data]) -> HashSet<u128> {
+        data.iter()
+            .filter_map(|d| match d {
+                Data::Traces(traces) => Some(traces),
+                _ => None,
+            })
+            .flat_map(|t| {
+                t.spans
+                    .iter()
+                    .flat_map(|g| g.spans.iter().map(|s| s.trace_id))
+            })
+            .collect()
+    }
+
+    /// Returns a span
+    fn span(trace_id: u128, id: u64, parent_id: Option<u64>, duration_ms: i128) -> Span {
+        Span {
+            id,
+            parent_id,
+            trace_id,
+            name: "span".to_string(),
+            start: 0,
+            end: duration_ms * 1_000_000,
+            status: SpanStatus::default(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns traces
+    fn traces(service: &str, spans: Vec<Span>) -> Data {
+        let service = Service {
+            name: service.to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut data = TraceData::default();
+        for span in spans {
+            data.add_span(&service, None, span);
+        }
+        Data::Traces(data)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tail_sampling_policies() {
+        let mut processor = TailSamplingProcessor::new()
+            .decision_wait(Duration::from_secs(1))
+            .policy(TailPolicy::StatusError)
+            .policy(TailPolicy::Latency(Duration::from_millis(500)))
+            .policy(TailPolicy::expr(r#"attrs["debug"] == true"#).unwrap())
+            .policy(TailPolicy::Probabilistic(0.0));
+
+        let mut error = span(1, 2, Some(1), 10);
+        error.status.code = StatusCode::Error;
+        let mut debug = span(4, 1, None, 10);
+        debug
+            .attrs
+            .insert("debug".to_string(), AttrValue::Bool(true));
+        let data = traces(
+            "api",
+            vec![
+                span(1, 1, None, 10),
+                error,
+                span(2, 1, None, 800),
+                span(3, 1, None, 10),
+                debug,
+            ],
+        );
+        assert_eq!(processor.process(vec![data]).await, None);
+        assert_eq!(processor.tick().await, None);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let sampled = processor.tick().await.unwrap();
+        assert_eq!(trace_ids(&sampled), HashSet::from([1, 2, 4]));
+        assert_eq!(sampled[0].len(), 3 + 1);
+
+        // NB: the late spans follow the decision of their trace
+        let late = traces(
+            "api",
+            vec![span(1, 3, Some(1), 10), span(3, 2, Some(1), 10)],
+        );
+        let sampled = processor.process(vec![late]).await.unwrap();
+        assert_eq!(trace_ids(&sampled), HashSet::from([1]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tail_sampling_max_traces() {
+        let mut processor = TailSamplingProcessor::new()
+            .max_traces(2)
+            .policy(TailPolicy::Probabilistic(1.0));
+        let data = traces("api", (1..=3).map(|id| span(id, 1, None, 10)).collect());
+        let sampled = processor.process(vec![data]).await.unwrap();
+        assert_eq!(trace_ids(&sampled), HashSet::from([1]));
+        assert_eq!(trace_ids(&processor.flush().await), HashSet::from([2, 3]));
+        assert!(processor.flush().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tail_sampling_rate_limit() {
+        let mut processor = TailSamplingProcessor::new().policy(TailPolicy::RateLimit(1));
+        let data = vec![
+            traces("api", vec![span(1, 1, None, 10), span(2, 1, None, 10)]),
+            traces("web", vec![span(3, 1, None, 10)]),
+        ];
+        processor.process(data).await;
+        assert_eq!(trace_ids(&processor.flush().await), HashSet::from([1, 3]));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let data = traces("api", vec![span(4, 1, None, 10)]);
+        processor.process(vec![data]).await;
+        assert_eq!(trace_ids(&processor.flush().await), HashSet::from([4]));
+    }
+}
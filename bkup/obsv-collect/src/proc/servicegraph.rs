@@ -0,0 +1,192 @@
+//! Service graph processor
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use obsv_core::data::ServiceGraphBuilder;
+use tokio::time::Instant;
+
+use crate::Data;
+
+use super::Processor;
+
+/// Service graph processor
+///
+/// The calls between services (a span whose parent span is in another service) are aggregated
+/// into edges with their numbers of requests and errors, and latency percentiles.
+///
+/// On each interval, the edges are emitted as metrics (see [obsv_core::data::ServiceGraph::to_metrics]),
+/// so they are stored with the other metrics and the graph can be queried with
+/// [obsv_core::db::DbClient::get_service_graph]. The spans are passed through.
+///
+/// The spans are buffered to pair the parent and child spans of the calls, until they expire,
+/// and the buffer is bounded.
+#[derive(Debug, Clone)]
+pub struct ServiceGraphProcessor {
+    /// Emission interval
+    interval: Duration,
+    /// Time during which a span waits for its parent or child spans
+    wait: Duration,
+    /// Maximum number of buffered spans
+    max_spans: usize,
+    /// Graph builder
+    builder: ServiceGraphBuilder,
+    /// Time of the last emission
+    emitted: Instant,
+    /// Start of the current interval (UNIX nanoseconds)
+    start: i128,
+}
+
+impl Default for ServiceGraphProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceGraphProcessor {
+    /// Creates a new service graph processor
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            wait: Duration::from_secs(10),
+            max_spans: 100_000,
+            builder: ServiceGraphBuilder::new(),
+            emitted: Instant::now(),
+            start: unix_now(),
+        }
+    }
+
+    /// Sets the emission interval
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the time during which a span waits for its parent or child spans
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Sets the maximum number of buffered spans
+    pub fn max_spans(mut self, max_spans: usize) -> Self {
+        self.max_spans = max_spans;
+        self
+    }
+
+    /// Returns the edges of the interval as metrics
+    fn emit(&mut self) -> Option<Data> {
+        let start = self.start;
+        let timestamp = unix_now();
+        self.start = timestamp;
+        self.emitted = Instant::now();
+
+        self.builder
+            .expire_before(timestamp - self.wait.as_nanos() as i128);
+        let graph = self.builder.take();
+        if graph.edges.is_empty() {
+            None
+        } else {
+            Some(Data::Metrics(graph.to_metrics(start, timestamp)))
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for ServiceGraphProcessor {
+    async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("service graph processing");
+        for d in &data {
+            if let Data::Traces(traces) = d {
+                self.builder.add_traces(traces);
+            }
+        }
+        if self.builder.len() > self.max_spans {
+            // NB: the aggregated edges are kept, only the pending calls of the oldest spans are lost
+            log::warn!("service graph buffer is full, dropping the oldest spans");
+            self.builder.truncate(self.max_spans);
+        }
+        Some(data)
+    }
+
+    async fn tick(&mut self) -> Option<Vec<Data>> {
+        if self.emitted.elapsed() < self.interval {
+            return None;
+        }
+        self.emit().map(|d| vec![d])
+    }
+
+    async fn flush(&mut self) -> Vec<Data> {
+        self.emit().into_iter().collect()
+    }
+}
+
+/// Returns the current time (UNIX nanoseconds)
+fn unix_now() -> i128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use obsv_core::data::{Service, ServiceGraph, Span, TraceData};
+
+    use super::*;
+
+    /// Returns spans of a service
+    fn traces(service: &str, id: u64, parent_id: Option<u64>) -> Data {
+        let service = Service {
+            name: service.to_string(),
+            attrs: HashMap::new(),
+        };
+        let span = Span {
+            id,
+            parent_id,
+            trace_id: 1,
+            name: "span".to_string(),
+            start: unix_now(),
+            end: unix_now() + 1_000_000,
+            ..Default::default()
+        };
+        let mut data = TraceData::default();
+        data.add_span(&service, None, span);
+        Data::Traces(data)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn service_graph_processor() {
+        let mut processor = ServiceGraphProcessor::new().interval(Duration::from_secs(10));
+        let data = vec![traces("api", 2, Some(1))];
+        assert_eq!(processor.process(data.clone()).await, Some(data));
+        processor.process(vec![traces("web", 1, None)]).await;
+        assert_eq!(processor.tick().await, None);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let Some(Data::Metrics(metrics)) =
+            processor.tick().await.and_then(|d| d.into_iter().next())
+        else {
+            panic!("no metrics");
+        };
+        let graph = ServiceGraph::from_metrics(&metrics);
+        assert_eq!(graph.services, vec!["api", "web"]);
+        assert_eq!(graph.edges[0].client, "web");
+        assert_eq!(graph.edges[0].requests, 1);
+
+        // NB: the edges are reset after each emission
+        assert!(processor.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn service_graph_processor_max_spans() {
+        let mut processor = ServiceGraphProcessor::new().max_spans(1);
+        processor.process(vec![traces("api", 2, Some(1))]).await;
+        // NB: the oldest span is dropped when the buffer is full
+        processor.process(vec![traces("db", 3, None)]).await;
+        processor.process(vec![traces("web", 1, None)]).await;
+        assert!(processor.flush().await.is_empty());
+    }
+}
@@ -0,0 +1,508 @@
+//! Span metrics processor
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use obsv_core::data::{
+    AggregationTemporality, AttrValue, HistogramDataPoint, Metric, MetricData, MetricsData,
+    NumberDataPoint, NumberValue, Scope, Service, Span, SpanKind, StatusCode, TraceData,
+};
+use tokio::time::Instant;
+
+use crate::Data;
+
+use super::Processor;
+
+/// Default histogram bounds (milliseconds)
+const DEFAULT_BOUNDS: [f64; 16] = [
+    2.0, 4.0, 6.0, 8.0, 10.0, 50.0, 100.0, 200.0, 400.0, 800.0, 1000.0, 1400.0, 2000.0, 5000.0,
+    10_000.0, 15_000.0,
+];
+
+/// Name of the calls metric
+pub const CALLS_METRIC: &str = "traces.span.metrics.calls";
+
+/// Name of the duration metric
+pub const DURATION_METRIC: &str = "traces.span.metrics.duration";
+
+/// Attribute of the overflow series
+pub const OVERFLOW_ATTR: &str = "otel.metric.overflow";
+
+/// Default maximum number of series
+const DEFAULT_MAX_SERIES: usize = 1000;
+
+/// Span metrics processor
+///
+/// The spans are aggregated into RED metrics (rate, errors, duration) per service,
+/// span name, span kind, status code and configured attributes:
+/// - `traces.span.metrics.calls`: number of spans (sum),
+/// - `traces.span.metrics.duration`: span durations in milliseconds (histogram).
+///
+/// The number of series of an interval is capped, and the spans of the new series beyond the cap
+/// are aggregated into a single overflow series per service (with the `otel.metric.overflow` attribute).
+///
+/// The metrics are emitted with a delta temporality on each interval, and the spans are passed through.
+#[derive(Debug, Clone)]
+pub struct SpanMetricsProcessor {
+    /// Emission interval
+    interval: Duration,
+    /// Attributes added to the series
+    dimensions: Vec<String>,
+    /// Histogram bounds (milliseconds)
+    bounds: Vec<f64>,
+    /// Maximum number of series (all services)
+    max_series: usize,
+    /// Number of series
+    num_series: usize,
+    /// Series per service
+    services: Vec<ServiceSeries>,
+    /// Time of the last emission
+    emitted: Instant,
+    /// Start of the current interval (UNIX nanoseconds)
+    start: i128,
+}
+
+/// The series of a service
+#[derive(Debug, Clone)]
+struct ServiceSeries {
+    /// Service
+    service: Service,
+    /// Series
+    series: HashMap<SeriesKey, Series>,
+}
+
+/// Series key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    /// Span name
+    name: String,
+    /// Span kind
+    kind: SpanKind,
+    /// Status code
+    status: StatusCode,
+    /// Dimension values
+    dims: Vec<Option<String>>,
+    /// Is it the overflow series
+    overflow: bool,
+}
+
+/// Series aggregation
+#[derive(Debug, Clone)]
+struct Series {
+    /// Point attributes
+    attrs: HashMap<String, AttrValue>,
+    /// Number of spans
+    count: u64,
+    /// Sum of the durations (milliseconds)
+    sum: f64,
+    /// Minimum duration (milliseconds)
+    min: f64,
+    /// Maximum duration (milliseconds)
+    max: f64,
+    /// Bucket counts
+    bucket_counts: Vec<u64>,
+}
+
+impl Default for SpanMetricsProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpanMetricsProcessor {
+    /// Creates a new span metrics processor
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            dimensions: vec![],
+            bounds: DEFAULT_BOUNDS.to_vec(),
+            max_series: DEFAULT_MAX_SERIES,
+            num_series: 0,
+            services: vec![],
+            emitted: Instant::now(),
+            start: unix_now(),
+        }
+    }
+
+    /// Sets the emission interval
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Adds a span attribute to the series
+    pub fn dimension(mut self, key: &str) -> Self {
+        self.dimensions.push(key.to_string());
+        self
+    }
+
+    /// Sets the histogram bounds (milliseconds)
+    pub fn buckets(mut self, bounds: Vec<f64>) -> Self {
+        self.bounds = bounds;
+        self.bounds.sort_by(f64::total_cmp);
+        self
+    }
+
+    /// Sets the maximum number of series per interval (all services)
+    pub fn max_series(mut self, max_series: usize) -> Self {
+        self.max_series = max_series;
+        self
+    }
+
+    /// Aggregates the spans
+    fn aggregate(&mut self, traces: &TraceData) {
+        for group in &traces.spans {
+            let index = match self
+                .services
+                .iter()
+                .position(|s| s.service == group.service)
+            {
+                Some(index) => index,
+                None => {
+                    self.services.push(ServiceSeries {
+                        service: group.service.clone(),
+                        series: HashMap::new(),
+                    });
+                    self.services.len() - 1
+                }
+            };
+            for span in &group.spans {
+                self.record(index, span);
+            }
+        }
+    }
+
+    /// Records a span
+    fn record(&mut self, index: usize, span: &Span) {
+        let mut key = SeriesKey {
+            name: span.name.clone(),
+            kind: span.kind,
+            status: span.status.code,
+            dims: self
+                .dimensions
+                .iter()
+                .map(|d| span.attrs.get(d).map(|v| v.to_string()))
+                .collect(),
+            overflow: false,
+        };
+        let service_series = &mut self.services[index].series;
+        if !service_series.contains_key(&key) && self.num_series >= self.max_series {
+            key = SeriesKey {
+                overflow: true,
+                ..Default::default()
+            };
+        }
+        if !service_series.contains_key(&key) {
+            // NB: the overflow series are not capped, there is at most one per service
+            self.num_series += 1;
+        }
+        let overflow = key.overflow;
+        let series = service_series.entry(key).or_insert_with(|| {
+            if overflow {
+                let attrs = HashMap::from([(OVERFLOW_ATTR.to_string(), AttrValue::Bool(true))]);
+                return Series::new(attrs, self.bounds.len());
+            }
+            let mut attrs = HashMap::from([
+                (
+                    "span.name".to_string(),
+                    AttrValue::String(span.name.clone()),
+                ),
+                (
+                    "span.kind".to_string(),
+                    AttrValue::String(span.kind.as_str().to_string()),
+                ),
+                (
+                    "status.code".to_string(),
+                    AttrValue::String(span.status.code.as_str().to_string()),
+                ),
+            ]);
+            for dim in &self.dimensions {
+                if let Some(value) = span.attrs.get(dim) {
+                    attrs.insert(dim.clone(), value.clone());
+                }
+            }
+            Series::new(attrs, self.bounds.len())
+        });
+
+        let duration = (span.end - span.start).max(0) as f64 / 1_000_000.0;
+        let bucket = self.bounds.partition_point(|b| *b < duration);
+        series.count += 1;
+        series.sum += duration;
+        series.min = series.min.min(duration);
+        series.max = series.max.max(duration);
+        series.bucket_counts[bucket] += 1;
+    }
+
+    /// Returns the metrics of the interval, and resets the series
+    fn emit(&mut self) -> Option<Data> {
+        let start = self.start;
+        let timestamp = unix_now();
+        self.start = timestamp;
+        self.emitted = Instant::now();
+        self.num_series = 0;
+
+        let scope = Scope {
+            name: "obsv-collect/spanmetrics".to_string(),
+            version: None,
+            attrs: HashMap::new(),
+        };
+        let mut metrics = MetricsData::default();
+        for ServiceSeries { service, series } in self.services.drain(..) {
+            let mut calls = vec![];
+            let mut durations = vec![];
+            for series in series.into_values() {
+                calls.push(NumberDataPoint {
+                    attrs: series.attrs.clone(),
+                    start,
+                    timestamp,
+                    value: NumberValue::Int(series.count as i64),
+                    exemplars: vec![],
+                    flags: 0,
+                });
+                durations.push(HistogramDataPoint {
+                    attrs: series.attrs,
+                    start,
+                    timestamp,
+                    count: series.count,
+                    sum: Some(series.sum),
+                    bucket_counts: series.bucket_counts,
+                    bounds: self.bounds.clone(),
+                    min: Some(series.min),
+                    max: Some(series.max),
+                    exemplars: vec![],
+                    flags: 0,
+                });
+            }
+            metrics.add_metric(
+                &service,
+                Some(&scope),
+                Metric {
+                    name: CALLS_METRIC.to_string(),
+                    descr: "Number of spans".to_string(),
+                    unit: "1".to_string(),
+                    data: MetricData::Sum {
+                        points: calls,
+                        temporality: AggregationTemporality::Delta,
+                        monotonic: true,
+                    },
+                },
+            );
+            metrics.add_metric(
+                &service,
+                Some(&scope),
+                Metric {
+                    name: DURATION_METRIC.to_string(),
+                    descr: "Span durations".to_string(),
+                    unit: "ms".to_string(),
+                    data: MetricData::Histogram {
+                        points: durations,
+                        temporality: AggregationTemporality::Delta,
+                    },
+                },
+            );
+        }
+
+        if metrics.is_empty() {
+            None
+        } else {
+            Some(Data::Metrics(metrics))
+        }
+    }
+}
+
+impl Series {
+    /// Creates an empty series
+    fn new(attrs: HashMap<String, AttrValue>, num_bounds: usize) -> Self {
+        Self {
+            attrs,
+            count: 0,
+            sum: 0.0,
+            min: f64::MAX,
+            max: f64::MIN,
+            bucket_counts: vec![0; num_bounds + 1],
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for SpanMetricsProcessor {
+    async fn process(&mut self, data: Vec<Data>) -> Option<Vec<Data>> {
+        log::trace!("span metrics processing");
+        for d in &data {
+            if let Data::Traces(traces) = d {
+                self.aggregate(traces);
+            }
+        }
+        Some(data)
+    }
+
+    async fn tick(&mut self) -> Option<Vec<Data>> {
+        if self.emitted.elapsed() < self.interval {
+            return None;
+        }
+        self.emit().map(|d| vec![d])
+    }
+
+    async fn flush(&mut self) -> Vec<Data> {
+        self.emit().into_iter().collect()
+    }
+}
+
+/// Returns the current time (UNIX nanoseconds)
+fn unix_now() -> i128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::data::SpanStatus;
+
+    use super::*;
+
+    /// Returns a span
+    fn span(name: &str, duration_ms: i128, error: bool, route: &str) -> Span {
+        Span {
+            id: 1,
+            trace_id: 1,
+            name: name.to_string(),
+            start: 0,
+            end: duration_ms * 1_000_000,
+            kind: SpanKind::Server,
+            status: SpanStatus {
+                code: if error {
+                    StatusCode::Error
+                } else {
+                    StatusCode::Unset
+                },
+                message: String::new(),
+            },
+            attrs: HashMap::from([(
+                "http.route".to_string(),
+                AttrValue::String(route.to_string()),
+            )]),
+            ..Default::default()
+        }
+    }
+
+    /// Checks the attributes of a series
+    fn is_series(attrs: &HashMap<String, AttrValue>, route: &str, status: &str) -> bool {
+        attrs.get("http.route") == Some(&AttrValue::String(route.to_string()))
+            && attrs.get("status.code") == Some(&AttrValue::String(status.to_string()))
+            && attrs.get("span.kind") == Some(&AttrValue::String("server".to_string()))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn span_metrics() {
+        let mut processor = SpanMetricsProcessor::new()
+            .interval(Duration::from_secs(10))
+            .dimension("http.route")
+            .buckets(vec![100.0, 10.0]);
+
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut traces = TraceData::default();
+        for span in [
+            span("GET", 5, false, "/users"),
+            span("GET", 50, false, "/users"),
+            span("GET", 500, true, "/users"),
+            span("GET", 5, false, "/items"),
+        ] {
+            traces.add_span(&service, None, span);
+        }
+        let data = vec![Data::Traces(traces)];
+        assert_eq!(processor.process(data.clone()).await, Some(data));
+        assert_eq!(processor.tick().await, None);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let Some(Data::Metrics(metrics)) =
+            processor.tick().await.and_then(|d| d.into_iter().next())
+        else {
+            panic!("no metrics");
+        };
+        assert_eq!(metrics.metrics.len(), 1);
+        let [calls, durations] = &metrics.metrics[0].metrics[..] else {
+            panic!("invalid metrics");
+        };
+        assert_eq!(calls.name, CALLS_METRIC);
+        assert_eq!(durations.name, DURATION_METRIC);
+
+        let MetricData::Sum { points, .. } = &calls.data else {
+            panic!("invalid calls");
+        };
+        assert_eq!(points.len(), 3);
+        let ok = points
+            .iter()
+            .find(|p| is_series(&p.attrs, "/users", "unset"))
+            .unwrap();
+        assert_eq!(ok.value, NumberValue::Int(2));
+        let errors = points
+            .iter()
+            .find(|p| is_series(&p.attrs, "/users", "error"))
+            .unwrap();
+        assert_eq!(errors.value, NumberValue::Int(1));
+
+        let MetricData::Histogram { points, .. } = &durations.data else {
+            panic!("invalid durations");
+        };
+        let ok = points
+            .iter()
+            .find(|p| is_series(&p.attrs, "/users", "unset"))
+            .unwrap();
+        assert_eq!(ok.bounds, vec![10.0, 100.0]);
+        assert_eq!(ok.bucket_counts, vec![1, 1, 0]);
+        assert_eq!(
+            (ok.sum, ok.min, ok.max),
+            (Some(55.0), Some(5.0), Some(50.0))
+        );
+
+        // NB: the series are reset after each emission
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(processor.tick().await, None);
+        assert!(processor.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn span_metrics_max_series() {
+        let mut processor = SpanMetricsProcessor::new()
+            .dimension("http.route")
+            .max_series(2);
+
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut traces = TraceData::default();
+        for route in ["/a", "/b", "/c", "/d", "/a"] {
+            traces.add_span(&service, None, span("GET", 5, false, route));
+        }
+        processor.process(vec![Data::Traces(traces)]).await;
+
+        let data = processor.flush().await;
+        let [Data::Metrics(metrics)] = &data[..] else {
+            panic!("no metrics");
+        };
+        let MetricData::Sum { points, .. } = &metrics.metrics[0].metrics[0].data else {
+            panic!("invalid calls");
+        };
+        assert_eq!(points.len(), 3);
+        let a = points
+            .iter()
+            .find(|p| is_series(&p.attrs, "/a", "unset"))
+            .unwrap();
+        assert_eq!(a.value, NumberValue::Int(2));
+        let overflow = points
+            .iter()
+            .find(|p| p.attrs.get(OVERFLOW_ATTR) == Some(&AttrValue::Bool(true)))
+            .unwrap();
+        assert_eq!(overflow.value, NumberValue::Int(2));
+        assert_eq!(overflow.attrs.len(), 1);
+    }
+}
@@ -0,0 +1,337 @@
+//! Bounded queue
+//!
+//! The queue connects the receivers to the processors, and the processors to each exporter.
+//! When the queue is full, the [QueuePolicy] decides what happens to the data.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::Data;
+
+/// Queue configuration
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// Capacity (number of data)
+    pub capacity: usize,
+    /// Policy when the queue is full
+    pub policy: QueuePolicy,
+    /// Maximum time to wait for space (blocking policy)
+    pub block_timeout: Duration,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            policy: QueuePolicy::Block,
+            block_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl QueueConfig {
+    /// Creates a new queue configuration
+    pub fn new(capacity: usize, policy: QueuePolicy) -> Self {
+        Self {
+            capacity,
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the maximum time to wait for space (blocking policy)
+    pub fn block_timeout(mut self, timeout: Duration) -> Self {
+        self.block_timeout = timeout;
+        self
+    }
+}
+
+/// Policy when a queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// The sender waits for space (up to a timeout)
+    Block,
+    /// The new data is dropped
+    DropNewest,
+    /// The oldest data is dropped to make space
+    DropOldest,
+}
+
+/// Error when sending data to a queue
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendError {
+    /// The queue is still full after waiting, the data can be sent again later
+    ///
+    /// NB: the data is not counted as dropped, since the sender still owns it
+    Full(Data),
+    /// The queue is full, and the data has been dropped
+    Dropped(Data),
+    /// The queue is closed
+    Closed(Data),
+}
+
+impl SendError {
+    /// Returns the data which has not been queued
+    pub fn into_data(self) -> Data {
+        match self {
+            SendError::Full(data) | SendError::Dropped(data) | SendError::Closed(data) => data,
+        }
+    }
+}
+
+/// Shared queue state
+#[derive(Debug)]
+struct Shared {
+    /// Configuration
+    config: QueueConfig,
+    /// Items
+    items: Mutex<VecDeque<Data>>,
+    /// Number of senders
+    senders: AtomicUsize,
+    /// Is the receiver dropped
+    closed: Mutex<bool>,
+    /// Notified when an item is added, or when the queue is closed
+    not_empty: Notify,
+    /// Notified when an item is removed, or when the queue is closed
+    not_full: Notify,
+    /// Number of dropped items (spans, logs or metrics)
+    dropped: Arc<AtomicU64>,
+}
+
+impl Shared {
+    /// Locks the items
+    fn items(&self) -> MutexGuard<'_, VecDeque<Data>> {
+        // NB: the lock is never held across a panic
+        self.items.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Checks if the receiver is dropped
+    fn is_closed(&self) -> bool {
+        *self.closed.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Counts dropped data
+    fn count_dropped(&self, data: &Data) {
+        self.dropped.fetch_add(data.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Creates a bounded queue
+///
+/// The dropped items (spans, logs or metrics) are added to the `dropped` counter.
+pub fn queue(config: QueueConfig, dropped: Arc<AtomicU64>) -> (DataSender, DataReceiver) {
+    let shared = Arc::new(Shared {
+        config,
+        items: Mutex::new(VecDeque::new()),
+        senders: AtomicUsize::new(1),
+        closed: Mutex::new(false),
+        not_empty: Notify::new(),
+        not_full: Notify::new(),
+        dropped,
+    });
+    (
+        DataSender {
+            shared: shared.clone(),
+        },
+        DataReceiver { shared },
+    )
+}
+
+/// Queue sender
+#[derive(Debug)]
+pub struct DataSender {
+    /// Shared state
+    shared: Arc<Shared>,
+}
+
+impl Clone for DataSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for DataSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.not_empty.notify_one();
+        }
+    }
+}
+
+impl DataSender {
+    /// Sends data to the queue, applying the queue policy when it is full
+    pub async fn send(&self, data: Data) -> Result<(), SendError> {
+        let deadline = tokio::time::Instant::now() + self.shared.config.block_timeout;
+        loop {
+            // NB: the notification is registered before checking the queue, to avoid missing it
+            let not_full = self.shared.not_full.notified();
+            {
+                if self.shared.is_closed() {
+                    return Err(SendError::Closed(data));
+                }
+                let mut items = self.shared.items();
+                if items.len() < self.shared.config.capacity {
+                    items.push_back(data);
+                    self.shared.not_empty.notify_one();
+                    return Ok(());
+                }
+                match self.shared.config.policy {
+                    QueuePolicy::Block => {}
+                    QueuePolicy::DropNewest => {
+                        self.shared.count_dropped(&data);
+                        return Err(SendError::Dropped(data));
+                    }
+                    QueuePolicy::DropOldest => {
+                        if let Some(oldest) = items.pop_front() {
+                            self.shared.count_dropped(&oldest);
+                        }
+                        items.push_back(data);
+                        self.shared.not_empty.notify_one();
+                        return Ok(());
+                    }
+                }
+            }
+            if tokio::time::timeout_at(deadline, not_full).await.is_err() {
+                return Err(SendError::Full(data));
+            }
+        }
+    }
+}
+
+/// Queue receiver
+#[derive(Debug)]
+pub struct DataReceiver {
+    /// Shared state
+    shared: Arc<Shared>,
+}
+
+impl Drop for DataReceiver {
+    fn drop(&mut self) {
+        *self
+            .shared
+            .closed
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = true;
+        self.shared.not_full.notify_waiters();
+    }
+}
+
+impl DataReceiver {
+    /// Receives data
+    ///
+    /// None is returned when the queue is empty and all the senders are dropped.
+    pub async fn recv(&mut self) -> Option<Data> {
+        let shared = self.shared.clone();
+        loop {
+            let not_empty = shared.not_empty.notified();
+            if let Some(data) = self.try_recv() {
+                return Some(data);
+            }
+            if shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            not_empty.await;
+        }
+    }
+
+    /// Receives data if the queue is not empty
+    pub fn try_recv(&mut self) -> Option<Data> {
+        let data = self.shared.items().pop_front();
+        if data.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use obsv_core::data::{Service, TraceData};
+
+    use super::*;
+
+    /// Returns data with a number of spans
+    fn data(n: u64) -> Data {
+        let mut data = TraceData::default();
+        for id in 0..n {
+            data.add_span(
+                &Service {
+                    name: "my_service".to_string(),
+                    attrs: Default::default(),
+                },
+                None,
+                obsv_core::data::Span {
+                    id,
+                    trace_id: 1,
+                    name: "span".to_string(),
+                    start: 0,
+                    end: 0,
+                    ..Default::default()
+                },
+            );
+        }
+        data.into()
+    }
+
+    #[tokio::test]
+    async fn queue_drop_policies() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (tx, mut rx) = queue(
+            QueueConfig::new(2, QueuePolicy::DropNewest),
+            dropped.clone(),
+        );
+        tx.send(data(1)).await.unwrap();
+        tx.send(data(2)).await.unwrap();
+        assert_eq!(tx.send(data(3)).await, Err(SendError::Dropped(data(3))));
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(rx.recv().await, Some(data(1)));
+
+        let (tx, mut rx) = queue(
+            QueueConfig::new(2, QueuePolicy::DropOldest),
+            dropped.clone(),
+        );
+        for n in 1..=3 {
+            tx.send(data(n)).await.unwrap();
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 4);
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(data(2)));
+        assert_eq!(rx.recv().await, Some(data(3)));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queue_block_policy() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let config = QueueConfig::new(1, QueuePolicy::Block).block_timeout(Duration::from_secs(1));
+        let (tx, mut rx) = queue(config, dropped.clone());
+        tx.send(data(1)).await.unwrap();
+
+        // the sender waits for the receiver
+        let task = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.send(data(2)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(rx.recv().await, Some(data(1)));
+        task.await.unwrap().unwrap();
+
+        // the sender times out, and keeps the data
+        assert_eq!(tx.send(data(3)).await, Err(SendError::Full(data(3))));
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+
+        drop(rx);
+        assert_eq!(tx.send(data(4)).await, Err(SendError::Closed(data(4))));
+    }
+}
@@ -3,13 +3,30 @@
 use std::net::SocketAddr;
 
 use async_trait::async_trait;
-use obsv_core::conn::otlp::proto::collector::trace::v1::{
-    trace_service_server, ExportTraceServiceRequest, ExportTraceServiceResponse,
+use obsv_core::data::{LogData, MetricsData, TraceData};
+use obsv_otlp::{
+    proto::collector::{
+        logs::v1::{
+            logs_service_server::LogsService, ExportLogsPartialSuccess, ExportLogsServiceRequest,
+            ExportLogsServiceResponse,
+        },
+        metrics::v1::{
+            metrics_service_server::MetricsService, ExportMetricsPartialSuccess,
+            ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+        },
+        trace::v1::{
+            trace_service_server::TraceService, ExportTracePartialSuccess,
+            ExportTraceServiceRequest, ExportTraceServiceResponse,
+        },
+    },
+    server::grpc::GrpcServer,
 };
-use tokio::sync::mpsc::UnboundedSender;
-use tonic::{Request, Response};
+use tonic::{Request, Response, Status};
 
-use crate::Data;
+use crate::{
+    queue::{DataSender, SendError},
+    Data,
+};
 
 use super::Receiver;
 
@@ -31,50 +48,110 @@ impl GrpcReceiver {
 
 #[async_trait]
 impl Receiver for GrpcReceiver {
-    async fn start(&self, tx: UnboundedSender<Data>) {
-        let trace_service = trace_service_server::TraceServiceServer::new(TraceHandler::new(tx));
-        tonic::transport::Server::builder()
-            .add_service(trace_service)
-            .serve(self.addr)
-            .await
-            .unwrap();
+    async fn start(&self, tx: DataSender) {
+        let res = GrpcServer::new()
+            .addr(&self.addr.to_string())
+            .trace_service(GrpcHandler::new(tx.clone()))
+            .logs_service(GrpcHandler::new(tx.clone()))
+            .metrics_service(GrpcHandler::new(tx))
+            .start()
+            .await;
+        if let Err(err) = res {
+            log::error!("GRPC server error: {err}");
+        }
     }
 }
 
-/// GRPC trace handler
+/// GRPC handler (traces, logs and metrics)
 #[derive(Clone)]
-struct TraceHandler {
-    /// Channel sender
-    tx: UnboundedSender<Data>,
+struct GrpcHandler {
+    /// Queue sender
+    tx: DataSender,
 }
 
-impl TraceHandler {
-    /// Creates a new [TraceHandler]
-    pub fn new(tx: UnboundedSender<Data>) -> Self {
+impl GrpcHandler {
+    /// Creates a new [GrpcHandler]
+    fn new(tx: DataSender) -> Self {
         Self { tx }
     }
+
+    /// Sends the data to the queue, and returns the number of rejected items
+    #[allow(clippy::result_large_err)]
+    async fn send(&self, data: Data) -> Result<i64, Status> {
+        match self.tx.send(data).await {
+            Ok(()) => Ok(0),
+            Err(SendError::Full(_)) => {
+                log::warn!("collector queue is full");
+                Err(Status::resource_exhausted("collector queue is full"))
+            }
+            Err(SendError::Dropped(data)) => {
+                log::warn!("collector queue is full, data dropped");
+                Ok(data.otlp_len() as i64)
+            }
+            Err(SendError::Closed(_)) => {
+                log::error!("collector queue is closed");
+                Err(Status::unavailable("collector is not running"))
+            }
+        }
+    }
 }
 
+/// Error message of a partial success
+const QUEUE_FULL: &str = "collector queue is full";
+
 #[tonic::async_trait]
-impl trace_service_server::TraceService for TraceHandler {
+impl TraceService for GrpcHandler {
     async fn export(
         &self,
         req: Request<ExportTraceServiceRequest>,
-    ) -> Result<Response<ExportTraceServiceResponse>, tonic::Status> {
-        log::trace!("received GRPC request");
+    ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+        log::trace!("received GRPC traces");
+        let data = TraceData::try_from(req.into_inner())
+            .map_err(|err| Status::invalid_argument(err.message))?;
+        let rejected = self.send(data.into()).await?;
+        Ok(Response::new(ExportTraceServiceResponse {
+            partial_success: (rejected > 0).then(|| ExportTracePartialSuccess {
+                rejected_spans: rejected,
+                error_message: QUEUE_FULL.to_string(),
+            }),
+        }))
+    }
+}
 
-        // // sending to channel
-        // let (_, _, req) = req.into_parts();
-        // let data: Data = Data::Spans(req.into());
-        // match self.tx.send(data) {
-        //     Ok(_ok) => {}
-        //     Err(err) => {
-        //         log::error!("Error sending data to channel: {err}");
-        //     }
-        // }
-        // Ok(Response::new(ExportTraceServiceResponse {
-        //     partial_success: None,
-        // }))
-        todo!("implement trace handler")
+#[tonic::async_trait]
+impl LogsService for GrpcHandler {
+    async fn export(
+        &self,
+        req: Request<ExportLogsServiceRequest>,
+    ) -> Result<Response<ExportLogsServiceResponse>, Status> {
+        log::trace!("received GRPC logs");
+        let data = LogData::try_from(req.into_inner())
+            .map_err(|err| Status::invalid_argument(err.message))?;
+        let rejected = self.send(data.into()).await?;
+        Ok(Response::new(ExportLogsServiceResponse {
+            partial_success: (rejected > 0).then(|| ExportLogsPartialSuccess {
+                rejected_log_records: rejected,
+                error_message: QUEUE_FULL.to_string(),
+            }),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl MetricsService for GrpcHandler {
+    async fn export(
+        &self,
+        req: Request<ExportMetricsServiceRequest>,
+    ) -> Result<Response<ExportMetricsServiceResponse>, Status> {
+        log::trace!("received GRPC metrics");
+        let data = MetricsData::try_from(req.into_inner())
+            .map_err(|err| Status::invalid_argument(err.message))?;
+        let rejected = self.send(data.into()).await?;
+        Ok(Response::new(ExportMetricsServiceResponse {
+            partial_success: (rejected > 0).then(|| ExportMetricsPartialSuccess {
+                rejected_data_points: rejected,
+                error_message: QUEUE_FULL.to_string(),
+            }),
+        }))
     }
 }
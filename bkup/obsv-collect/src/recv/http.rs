@@ -1,50 +1,85 @@
 //! OpenTelemetry HTTP receiver
 
-use std::{convert::Infallible, net::SocketAddr};
+use std::{convert::Infallible, io::Read, net::SocketAddr};
 
+use crate::{
+    queue::{DataSender, SendError},
+    Data,
+};
 use async_trait::async_trait;
 use hyper::{
+    body::HttpBody,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
     Body, Method, Request, Response, Server, StatusCode,
 };
-use obsv_core::conn::otlp::proto::collector::{
-    logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
-    trace::v1::ExportTraceServiceRequest,
+use obsv_core::{
+    data::{LogData, MetricsData, TraceData},
+    error::Error,
+};
+use obsv_otlp::{
+    proto::collector::{
+        logs::v1::{ExportLogsPartialSuccess, ExportLogsServiceRequest, ExportLogsServiceResponse},
+        metrics::v1::{
+            ExportMetricsPartialSuccess, ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+        },
+        trace::v1::{
+            ExportTracePartialSuccess, ExportTraceServiceRequest, ExportTraceServiceResponse,
+        },
+    },
+    server::http::{
+        HttpConvert, APPLICATION_JSON, APPLICATION_PROTOBUF, ENDPOINT_LOGS, ENDPOINT_METRICS,
+        ENDPOINT_TRACES,
+    },
 };
-use tokio::sync::mpsc::UnboundedSender;
-
-use crate::Data;
 
 use super::Receiver;
 
+/// Default maximum size of a request body (bytes)
+pub const DEFAULT_MAX_BODY_SIZE: usize = 20 * 1024 * 1024;
+
 /// HTTP receiver
 ///
 /// This receiver implements the OpenTelemetry HTTP receiver specs.
 pub struct HttpReceiver {
     /// Address
     addr: SocketAddr,
+    /// Maximum size of a request body, after decompression (bytes)
+    max_body_size: usize,
 }
 
 impl HttpReceiver {
     /// Instantiates a new HTTP receiver
     pub fn new(addr: &str) -> Self {
         let addr = addr.parse().unwrap();
-        Self { addr }
+        Self {
+            addr,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of a request body, after decompression (bytes)
+    ///
+    /// Larger requests are rejected with a 413 status.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
     }
 }
 
 #[async_trait]
 impl Receiver for HttpReceiver {
-    async fn start(&self, tx: UnboundedSender<Data>) {
+    async fn start(&self, tx: DataSender) {
+        let max_body_size = self.max_body_size;
         let make_svc = make_service_fn(|conn: &AddrStream| {
             let _addr = conn.remote_addr();
             let tx = tx.clone();
-            async {
+            async move {
                 // service_fn converts our function into a `Service`
                 Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
                     let tx = tx.clone();
-                    async move { handle_req(tx, req).await }
+                    async move { handle_req_limited(tx, req, max_body_size).await }
                 }))
             }
         });
@@ -52,89 +87,344 @@ impl Receiver for HttpReceiver {
         let addr = self.addr;
         let server = Server::bind(&addr).serve(make_svc);
         if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
+            log::error!("HTTP server error: {}", e);
         }
     }
 }
 
-/// Handles the request
-pub async fn handle_req(
-    tx: UnboundedSender<Data>,
+/// Error message of a partial success
+const QUEUE_FULL: &str = "collector queue is full";
+
+/// Error message of a request body which is too large
+const BODY_TOO_LARGE: &str = "request body is too large";
+
+/// Handles the request, with the default maximum body size
+pub async fn handle_req(tx: DataSender, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    handle_req_limited(tx, req, DEFAULT_MAX_BODY_SIZE).await
+}
+
+/// Handles the request, with a maximum body size (bytes)
+pub async fn handle_req_limited(
+    tx: DataSender,
     req: Request<Body>,
+    max_body_size: usize,
 ) -> Result<Response<Body>, Infallible> {
     log::trace!("received HTTP request");
     match (req.method(), req.uri().path()) {
         (&Method::GET, "") => Ok(Response::new("Hello, World".into())),
         (&Method::GET, "/up") => Ok(Response::new("UP".into())),
         // OTLP/HTTP trace collector
-        (&Method::POST, "/v1/traces") => {
-            // parse the OTLP trace as JSON payload
-            let body_bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
-            let otlp_req: ExportTraceServiceRequest = match serde_json::from_slice(&body_bytes) {
-                Ok(ok) => ok,
-                Err(err) => {
-                    log::error!("Invalid HTTP request body: {}", err);
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_REQUEST)
-                        .body(Body::from(err.to_string()))
-                        .unwrap());
-                }
-            };
-            // sending to channel
-            todo!("handle http request for traces");
-            // if let Err(err) = tx.send(otlp_req.into()) {
-            //     log::error!("Error sending data to channel: {err}");
-            // }
-            // // ok
-            // Ok(Response::new(Body::empty()))
-        }
+        (&Method::POST, ENDPOINT_TRACES) => Ok(handle_export(
+            tx,
+            req,
+            max_body_size,
+            |otlp_req: ExportTraceServiceRequest| TraceData::try_from(otlp_req).map(Data::from),
+            |rejected| ExportTraceServiceResponse {
+                partial_success: rejected.map(|n| ExportTracePartialSuccess {
+                    rejected_spans: n,
+                    error_message: QUEUE_FULL.to_string(),
+                }),
+            },
+        )
+        .await),
         // OTLP/HTTP metrics collector
-        (&Method::POST, "/v1/metrics") => {
-            // parse the OTLP trace as JSON payload
-            let body_bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
-            let otlp_req: ExportMetricsServiceRequest = match serde_json::from_slice(&body_bytes) {
-                Ok(ok) => ok,
-                Err(err) => {
-                    log::error!("Invalid HTTP request body: {}", err);
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_REQUEST)
-                        .body(Body::from(err.to_string()))
-                        .unwrap());
-                }
-            };
-            // sending to channel
-            todo!("handle http request for metrics");
-            // if let Err(err) = tx.send(otlp_req.into()) {
-            //     log::error!("Error sending data to channel: {err}");
-            // }
-            // // ok
-            // Ok(Response::new(Body::empty()))
-        }
+        (&Method::POST, ENDPOINT_METRICS) => Ok(handle_export(
+            tx,
+            req,
+            max_body_size,
+            |otlp_req: ExportMetricsServiceRequest| MetricsData::try_from(otlp_req).map(Data::from),
+            |rejected| ExportMetricsServiceResponse {
+                partial_success: rejected.map(|n| ExportMetricsPartialSuccess {
+                    rejected_data_points: n,
+                    error_message: QUEUE_FULL.to_string(),
+                }),
+            },
+        )
+        .await),
         // OTLP/HTTP logs collector
-        (&Method::POST, "/v1/logs") => {
-            // parse the OTLP trace as JSON payload
-            let body_bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
-            let otlp_req: ExportLogsServiceRequest = match serde_json::from_slice(&body_bytes) {
-                Ok(ok) => ok,
-                Err(err) => {
-                    log::error!("Invalid HTTP request body: {}", err);
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_REQUEST)
-                        .body(Body::from(err.to_string()))
-                        .unwrap());
-                }
-            };
-            // sending to channel
-            todo!("handle http request for logs");
-            // if let Err(err) = tx.send(otlp_req.into()) {
-            //     log::error!("Error sending data to channel: {err}");
-            // }
-            // // ok
-            // Ok(Response::new(Body::empty()))
-        }
+        (&Method::POST, ENDPOINT_LOGS) => Ok(handle_export(
+            tx,
+            req,
+            max_body_size,
+            |otlp_req: ExportLogsServiceRequest| LogData::try_from(otlp_req).map(Data::from),
+            |rejected| ExportLogsServiceResponse {
+                partial_success: rejected.map(|n| ExportLogsPartialSuccess {
+                    rejected_log_records: n,
+                    error_message: QUEUE_FULL.to_string(),
+                }),
+            },
+        )
+        .await),
         _ => Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(format!("Not Found {} {}", req.method(), req.uri().path()).into())
             .unwrap()),
     }
 }
+
+/// Handles an OTLP export request
+///
+/// The response has the same content type as the request (protobuf by default).
+/// When the collector queue is full, the response has the number of rejected items.
+/// The body is limited in size, before and after decompression.
+async fn handle_export<T, U>(
+    tx: DataSender,
+    req: Request<Body>,
+    max_body_size: usize,
+    convert: impl FnOnce(T) -> Result<Data, Error>,
+    response: impl FnOnce(Option<i64>) -> U,
+) -> Response<Body>
+where
+    T: HttpConvert,
+    U: HttpConvert,
+{
+    // NB: the content type may have parameters (eg. charset)
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_string())
+        .unwrap_or_else(|| APPLICATION_PROTOBUF.to_string());
+    if content_type != APPLICATION_JSON && content_type != APPLICATION_PROTOBUF {
+        return error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Invalid content-type: {content_type}"),
+        );
+    }
+
+    let encoding = req
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map(|v| v.to_str().unwrap_or_default());
+    let gzip = match encoding {
+        None | Some("identity") => false,
+        Some("gzip") => true,
+        Some(encoding) => {
+            return error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Invalid content-encoding: {encoding}"),
+            )
+        }
+    };
+    let too_large = || error_response(StatusCode::PAYLOAD_TOO_LARGE, BODY_TOO_LARGE.to_string());
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > max_body_size) {
+        return too_large();
+    }
+    let mut body = req.into_body();
+    let mut body_bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) if body_bytes.len() + chunk.len() > max_body_size => return too_large(),
+            Ok(chunk) => body_bytes.extend_from_slice(&chunk),
+            Err(err) => return error_response(StatusCode::BAD_REQUEST, err.to_string()),
+        }
+    }
+    let body_bytes = if gzip {
+        // NB: the decompressed size is limited too, against decompression bombs
+        let mut bytes = vec![];
+        if let Err(err) = flate2::read::GzDecoder::new(body_bytes.as_slice())
+            .take(max_body_size as u64 + 1)
+            .read_to_end(&mut bytes)
+        {
+            return error_response(StatusCode::BAD_REQUEST, format!("Invalid gzip body: {err}"));
+        }
+        if bytes.len() > max_body_size {
+            return too_large();
+        }
+        bytes
+    } else {
+        body_bytes
+    };
+    let data = match T::from_http_request(&content_type, &body_bytes)
+        .and_then(|otlp_req| convert(otlp_req).map_err(|err| err.message))
+    {
+        Ok(ok) => ok,
+        Err(err) => {
+            log::error!("Invalid HTTP request body: {}", err);
+            return error_response(StatusCode::BAD_REQUEST, err);
+        }
+    };
+
+    // sending to the queue
+    let rejected = match tx.send(data).await {
+        Ok(()) => None,
+        Err(SendError::Full(_)) => {
+            log::warn!("collector queue is full");
+            return error_response(StatusCode::TOO_MANY_REQUESTS, QUEUE_FULL.to_string());
+        }
+        Err(SendError::Dropped(data)) => {
+            log::warn!("collector queue is full, data dropped");
+            Some(data.otlp_len() as i64)
+        }
+        Err(SendError::Closed(_)) => {
+            log::error!("collector queue is closed");
+            return error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "collector is not running".to_string(),
+            );
+        }
+    };
+
+    match response(rejected).into_http_body(&content_type) {
+        Ok(body) => Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap(),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+/// Returns an error response
+fn error_response(status: StatusCode, message: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{atomic::AtomicU64, Arc},
+        time::Duration,
+    };
+
+    use obsv_core::data::{Service, ServiceLogs};
+
+    use crate::queue::{queue, QueueConfig, QueuePolicy};
+
+    use super::*;
+
+    /// Returns a HTTP request
+    fn request(path: &str, content_type: &str, body: Vec<u8>) -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn http_export_logs() {
+        let logs = LogData {
+            logs: vec![ServiceLogs {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                logs: vec![obsv_core::data::Log {
+                    trace_id: 0,
+                    span_id: 0,
+                    timestamp: 1,
+                    level: 9,
+                    message: "hello".to_string(),
+                    attrs: HashMap::new(),
+                }],
+            }],
+        };
+        let otlp_req = ExportLogsServiceRequest::from(logs);
+        // NB: the service name is added to the resource attributes
+        let logs = LogData::try_from(otlp_req.clone()).unwrap();
+        let (tx, mut rx) = queue(
+            QueueConfig::new(1, QueuePolicy::DropNewest),
+            Arc::new(AtomicU64::new(0)),
+        );
+
+        for content_type in [APPLICATION_JSON, APPLICATION_PROTOBUF] {
+            let body = otlp_req.clone().into_http_body(content_type).unwrap();
+            let res = handle_req(tx.clone(), request(ENDPOINT_LOGS, content_type, body))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(rx.recv().await.unwrap(), Data::Logs(logs.clone()));
+        }
+
+        let res = handle_req(
+            tx.clone(),
+            request(ENDPOINT_LOGS, APPLICATION_JSON, b"{".to_vec()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = handle_req(tx, request(ENDPOINT_LOGS, "text/plain", vec![]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn http_export_queue_full() {
+        let otlp_req = ExportLogsServiceRequest::default();
+        let body = otlp_req.into_http_body(APPLICATION_PROTOBUF).unwrap();
+
+        // NB: the data is dropped, and the client is notified with a partial success
+        let (tx, _rx) = queue(
+            QueueConfig::new(0, QueuePolicy::DropNewest),
+            Arc::new(AtomicU64::new(0)),
+        );
+        let res = handle_req(
+            tx,
+            request(ENDPOINT_LOGS, APPLICATION_PROTOBUF, body.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res_body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let res =
+            ExportLogsServiceResponse::from_http_request(APPLICATION_PROTOBUF, &res_body).unwrap();
+        assert_eq!(res.partial_success.unwrap().error_message, QUEUE_FULL);
+
+        // NB: the client is asked to retry later
+        let config = QueueConfig::new(0, QueuePolicy::Block).block_timeout(Duration::ZERO);
+        let (tx, _rx) = queue(config, Arc::new(AtomicU64::new(0)));
+        let res = handle_req(tx, request(ENDPOINT_LOGS, APPLICATION_PROTOBUF, body))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn http_export_body_limit() {
+        let (tx, _rx) = queue(QueueConfig::default(), Arc::new(AtomicU64::new(0)));
+        let body = ExportLogsServiceRequest::default()
+            .into_http_body(APPLICATION_JSON)
+            .unwrap();
+        let res = handle_req_limited(
+            tx.clone(),
+            request(ENDPOINT_LOGS, APPLICATION_JSON, body.clone()),
+            body.len(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = handle_req_limited(
+            tx.clone(),
+            request(ENDPOINT_LOGS, APPLICATION_JSON, body),
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // NB: a small gzip body can inflate to a large body
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &vec![b' '; 1_000_000]).unwrap();
+        let gzip_body = encoder.finish().unwrap();
+        let mut req = request(ENDPOINT_LOGS, APPLICATION_JSON, gzip_body.clone());
+        req.headers_mut()
+            .insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+        let res = handle_req_limited(tx, req, 10 * gzip_body.len())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -1,9 +1,8 @@
 //! Receiver
 
 use async_trait::async_trait;
-use tokio::sync::mpsc::UnboundedSender;
 
-use crate::Data;
+use crate::queue::DataSender;
 
 #[cfg(feature = "grpc")]
 pub mod grpc;
@@ -14,5 +13,7 @@ pub mod http;
 #[async_trait]
 pub trait Receiver: Send + Sync {
     /// Starts receiving metrics/traces/logs/etc data
-    async fn start(&self, tx: UnboundedSender<Data>);
+    ///
+    /// The data is sent to the collector queue.
+    async fn start(&self, tx: DataSender);
 }
@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use crate::data::AttrValue;
+use crate::data::{AttrValue, LogIndex, TraceData};
 
 /// A Grafana data frame for the trace viewer chart
 #[derive(Debug, Clone)]
@@ -57,6 +57,73 @@ pub struct GrafanaDataFrame {
     pub error_icon_color: Option<String>,
 }
 
+impl GrafanaDataFrame {
+    /// Returns the data frames of a trace
+    ///
+    /// The span logs are the span events and the correlated logs (by trace and span IDs).
+    pub fn from_trace(trace: &TraceData, logs: &LogIndex) -> Vec<Self> {
+        let mut frames = vec![];
+        for group in &trace.spans {
+            for span in &group.spans {
+                let mut span_logs = span
+                    .events
+                    .iter()
+                    .map(|event| {
+                        let mut fields = event.attrs.clone();
+                        fields.insert("event".to_string(), AttrValue::String(event.name.clone()));
+                        GrafanaTraceLog {
+                            timestamp: millis(event.timestamp),
+                            fields,
+                        }
+                    })
+                    .chain(
+                        logs.span_logs(span.trace_id, span.id)
+                            .into_iter()
+                            .map(|log| {
+                                let mut fields = log.attrs.clone();
+                                fields.insert(
+                                    "message".to_string(),
+                                    AttrValue::String(log.message.clone()),
+                                );
+                                fields
+                                    .insert("level".to_string(), AttrValue::Int(log.level.into()));
+                                GrafanaTraceLog {
+                                    timestamp: millis(log.timestamp),
+                                    fields,
+                                }
+                            }),
+                    )
+                    .collect::<Vec<_>>();
+                span_logs.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+                frames.push(GrafanaDataFrame {
+                    trace_id: format!("{:032x}", span.trace_id),
+                    span_id: format!("{:016x}", span.id),
+                    parent_span_id: span
+                        .parent_id
+                        .map(|id| format!("{id:016x}"))
+                        .unwrap_or_default(),
+                    service_name: group.service.name.clone(),
+                    service_tags: group.service.attrs.clone(),
+                    start_time: millis(span.start),
+                    duration: millis(span.end - span.start),
+                    logs: if span_logs.is_empty() {
+                        None
+                    } else {
+                        Some(span_logs)
+                    },
+                    tags: Some(span.attrs.clone()),
+                    warnings: None,
+                    stack_traces: None,
+                    error_icon_color: None,
+                });
+            }
+        }
+        frames
+    }
+}
+
+/// A Grafana trace log (span log)
 #[derive(Debug, Clone)]
 pub struct GrafanaTraceLog {
     /// Millisecond epoch time
@@ -64,3 +131,71 @@ pub struct GrafanaTraceLog {
     /// Fields
     pub fields: HashMap<String, AttrValue>,
 }
+
+/// Converts UNIX nanoseconds to milliseconds
+fn millis(nanos: i128) -> f64 {
+    nanos as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Log, LogData, Service, Span, SpanEvent};
+
+    use super::*;
+
+    #[test]
+    fn grafana_trace_logs() {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut trace = TraceData::default();
+        trace.add_span(
+            &service,
+            None,
+            Span {
+                id: 2,
+                parent_id: Some(1),
+                trace_id: 1,
+                name: "GET".to_string(),
+                start: 1_000_000,
+                end: 3_000_000,
+                events: vec![SpanEvent {
+                    timestamp: 2_000_000,
+                    name: "retry".to_string(),
+                    attrs: HashMap::new(),
+                    dropped_attrs: 0,
+                }],
+                ..Default::default()
+            },
+        );
+        let mut logs = LogData::default();
+        logs.add_log(
+            &service,
+            None,
+            Log {
+                trace_id: 1,
+                span_id: 2,
+                timestamp: 1_500_000,
+                level: 17,
+                message: "request failed".to_string(),
+                attrs: HashMap::new(),
+            },
+        );
+        let mut index = LogIndex::new();
+        index.add_logs(&logs);
+
+        let frames = GrafanaDataFrame::from_trace(&trace, &index);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].span_id, "0000000000000002");
+        assert_eq!(frames[0].parent_span_id, "0000000000000001");
+        assert_eq!((frames[0].start_time, frames[0].duration), (1.0, 2.0));
+        let logs = frames[0].logs.as_ref().unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0].fields.get("message"),
+            Some(&AttrValue::String("request failed".to_string()))
+        );
+        assert_eq!(logs[1].timestamp, 2.0);
+    }
+}
@@ -1,171 +1,1524 @@
 //! OpenTelemetry adapter
+//!
+//! Conversions between the OTLP export requests and the core data model.
+//!
+//! The conversions from OTLP are fallible because the OTLP IDs are byte arrays
+//! which must have the correct length (16 bytes for trace IDs, 8 bytes for span IDs).
+//!
+//! The conversions to OTLP are infallible, and regroup the data by service and scope.
 
-use obsv_otlp::proto::{
-    collector::{
-        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
-        trace::v1::ExportTraceServiceRequest,
-    },
-    common::v1::{any_value::Value, AnyValue, InstrumentationScope, KeyValue},
-    logs::v1::{LogRecord, ResourceLogs, ScopeLogs},
-    metrics::v1::{
-        metric::Data, summary_data_point::ValueAtQuantile, Metric, ResourceMetrics, ScopeMetrics,
-        Summary, SummaryDataPoint,
+use std::collections::HashMap;
+
+use obsv_otlp::{
+    conv::SemConv,
+    proto::{
+        collector::{
+            logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+            trace::v1::ExportTraceServiceRequest,
+        },
+        common::v1::{
+            any_value::Value, AnyValue, ArrayValue, InstrumentationScope, KeyValue, KeyValueList,
+        },
+        logs::v1::{LogRecord, ResourceLogs, ScopeLogs},
+        metrics::v1::{
+            exemplar, exponential_histogram_data_point::Buckets, metric::Data, number_data_point,
+            summary_data_point::ValueAtQuantile, AggregationTemporality as OtlpTemporality,
+            Exemplar as OtlpExemplar, ExponentialHistogram,
+            ExponentialHistogramDataPoint as OtlpExpHistogramDataPoint, Gauge, Histogram,
+            HistogramDataPoint as OtlpHistogramDataPoint, Metric as OtlpMetric,
+            NumberDataPoint as OtlpNumberDataPoint, ResourceMetrics, ScopeMetrics, Sum, Summary,
+            SummaryDataPoint as OtlpSummaryDataPoint,
+        },
+        resource::v1::Resource,
+        trace::v1::{
+            span::{Event, Link, SpanKind as OtlpSpanKind},
+            status::StatusCode as OtlpStatusCode,
+            ResourceSpans, ScopeSpans, Span as OtlpSpan, Status,
+        },
     },
-    resource::v1::Resource,
-    trace::v1::{
-        span::{Event, Link},
-        ResourceSpans, ScopeSpans, Span, Status,
+};
+
+use crate::{
+    data::{
+        AggregationTemporality, AttrValue, Exemplar, ExpHistogramBuckets, ExpHistogramDataPoint,
+        HistogramDataPoint, Log, LogData, Metric, MetricData, MetricsData, NumberDataPoint,
+        NumberValue, QuantileValue, Scope, Service, ServiceLogs, ServiceMetrics, ServiceSpans,
+        Span, SpanEvent, SpanKind, SpanLink, SpanStatus, StatusCode, SummaryDataPoint, TraceData,
     },
+    error::Error,
 };
 
-use crate::data::{ServiceSpans, TraceData};
-
-impl From<ExportTraceServiceRequest> for TraceData {
-    fn from(value: ExportTraceServiceRequest) -> Self {
-        todo!()
-    }
-}
-
-// fn test_trace() {
-//     let _ = ExportTraceServiceRequest {
-//         resource_spans: vec![ResourceSpans {
-//             // a resource is the entity producing the telemetry
-//             resource: Some(Resource {
-//                 attributes: vec![KeyValue {
-//                     key: "key".to_string(),
-//                     value: Some(AnyValue {
-//                         value: Some(Value::BoolValue(false)),
-//                     }),
-//                 }],
-//                 dropped_attributes_count: 0,
-//             }),
-//             // a unit of scope is the unit of code (eg package) producing the telemetry
-//             scope_spans: vec![ScopeSpans {
-//                 scope: Some(InstrumentationScope {
-//                     name: "my scope".to_string(),
-//                     version: "v1".to_string(),
-//                     attributes: vec![],
-//                     dropped_attributes_count: 0,
-//                 }),
-//                 spans: vec![Span {
-//                     trace_id: [0x00; 16].to_vec(),
-//                     span_id: [0x00; 8].to_vec(),
-//                     trace_state: "state".to_string(),
-//                     parent_span_id: [0x00; 8].to_vec(),
-//                     name: "span name".to_string(),
-//                     kind: 1,
-//                     start_time_unix_nano: 1,
-//                     end_time_unix_nano: 2,
-//                     attributes: vec![],
-//                     dropped_attributes_count: 0,
-//                     events: vec![Event {
-//                         time_unix_nano: 1,
-//                         name: "my_event".to_string(),
-//                         attributes: vec![],
-//                         dropped_attributes_count: 0,
-//                     }],
-//                     dropped_events_count: 0,
-//                     links: vec![Link {
-//                         trace_id: [0x00; 16].to_vec(),
-//                         span_id: [0x00; 8].to_vec(),
-//                         trace_state: "state".to_string(),
-//                         attributes: vec![],
-//                         dropped_attributes_count: 0,
-//                     }],
-//                     dropped_links_count: 0,
-//                     status: Some(Status {
-//                         message: "status message".to_string(),
-//                         code: 1,
-//                     }),
-//                 }],
-//                 schema_url: "scope_schema_url".to_string(),
-//             }],
-//             schema_url: "resource_schema_url".to_string(),
-//         }],
-//     };
-// }
-
-// fn test_logs() {
-//     let _ = ExportLogsServiceRequest {
-//         resource_logs: vec![ResourceLogs {
-//             resource: Some(Resource {
-//                 attributes: vec![KeyValue {
-//                     key: "key".to_string(),
-//                     value: Some(AnyValue {
-//                         value: Some(Value::BoolValue(false)),
-//                     }),
-//                 }],
-//                 dropped_attributes_count: 0,
-//             }),
-//             scope_logs: vec![ScopeLogs {
-//                 scope: Some(InstrumentationScope {
-//                     name: "my scope".to_string(),
-//                     version: "v1".to_string(),
-//                     attributes: vec![],
-//                     dropped_attributes_count: 0,
-//                 }),
-//                 log_records: vec![LogRecord {
-//                     time_unix_nano: 10_000_000,
-//                     observed_time_unix_nano: 10_000_000,
-//                     severity_number: 1,
-//                     severity_text: "info".to_string(),
-//                     body: Some(AnyValue {
-//                         value: Some(Value::StringValue("body".to_string())),
-//                     }),
-//                     attributes: vec![],
-//                     dropped_attributes_count: 0,
-//                     flags: 0,
-//                     trace_id: [0x00; 16].to_vec(),
-//                     span_id: [0x00; 8].to_vec(),
-//                 }],
-//                 schema_url: "schema_url".to_string(),
-//             }],
-//             schema_url: "schema_url".to_string(),
-//         }],
-//     };
-// }
-
-// fn test_metrics() {
-//     let _ = ExportMetricsServiceRequest {
-//         resource_metrics: vec![ResourceMetrics {
-//             resource: Some(Resource {
-//                 attributes: vec![KeyValue {
-//                     key: "key".to_string(),
-//                     value: Some(AnyValue {
-//                         value: Some(Value::BoolValue(false)),
-//                     }),
-//                 }],
-//                 dropped_attributes_count: 0,
-//             }),
-//             scope_metrics: vec![ScopeMetrics {
-//                 scope: Some(InstrumentationScope {
-//                     name: "my scope".to_string(),
-//                     version: "v1".to_string(),
-//                     attributes: vec![],
-//                     dropped_attributes_count: 0,
-//                 }),
-//                 metrics: vec![Metric {
-//                     name: "my metric".to_string(),
-//                     description: "desc".to_string(),
-//                     unit: "MB".to_string(),
-//                     data: Some(Data::Summary(Summary {
-//                         data_points: vec![SummaryDataPoint {
-//                             attributes: vec![],
-//                             start_time_unix_nano: 0,
-//                             time_unix_nano: 1,
-//                             count: 1,
-//                             sum: 2.0,
-//                             quantile_values: vec![ValueAtQuantile {
-//                                 quantile: 1.1,
-//                                 value: 1.2,
-//                             }],
-//                             flags: 0,
-//                         }],
-//                     })),
-//                 }],
-//                 schema_url: "schema_url".to_string(),
-//             }],
-//             schema_url: "schema_url".to_string(),
-//         }],
-//     };
-// }
+impl TryFrom<ExportTraceServiceRequest> for TraceData {
+    type Error = Error;
+
+    fn try_from(value: ExportTraceServiceRequest) -> Result<Self, Self::Error> {
+        let mut spans = vec![];
+        for resource_spans in value.resource_spans {
+            let service = Service::from(resource_spans.resource);
+            for scope_spans in resource_spans.scope_spans {
+                spans.push(ServiceSpans {
+                    service: service.clone(),
+                    scope: scope_spans.scope.map(Scope::from),
+                    spans: scope_spans
+                        .spans
+                        .into_iter()
+                        .map(Span::try_from)
+                        .collect::<Result<Vec<_>, _>>()?,
+                });
+            }
+        }
+        Ok(TraceData { spans })
+    }
+}
+
+impl TryFrom<ExportLogsServiceRequest> for LogData {
+    type Error = Error;
+
+    fn try_from(value: ExportLogsServiceRequest) -> Result<Self, Self::Error> {
+        let mut logs = vec![];
+        for resource_logs in value.resource_logs {
+            let service = Service::from(resource_logs.resource);
+            for scope_logs in resource_logs.scope_logs {
+                logs.push(ServiceLogs {
+                    service: service.clone(),
+                    scope: scope_logs.scope.map(Scope::from),
+                    logs: scope_logs
+                        .log_records
+                        .into_iter()
+                        .map(Log::try_from)
+                        .collect::<Result<Vec<_>, _>>()?,
+                });
+            }
+        }
+        Ok(LogData { logs })
+    }
+}
+
+impl TryFrom<ExportMetricsServiceRequest> for MetricsData {
+    type Error = Error;
+
+    fn try_from(value: ExportMetricsServiceRequest) -> Result<Self, Self::Error> {
+        let mut metrics = vec![];
+        for resource_metrics in value.resource_metrics {
+            let service = Service::from(resource_metrics.resource);
+            for scope_metrics in resource_metrics.scope_metrics {
+                metrics.push(ServiceMetrics {
+                    service: service.clone(),
+                    scope: scope_metrics.scope.map(Scope::from),
+                    metrics: scope_metrics
+                        .metrics
+                        .into_iter()
+                        .map(Metric::try_from)
+                        .collect::<Result<Vec<_>, _>>()?,
+                });
+            }
+        }
+        Ok(MetricsData { metrics })
+    }
+}
+
+impl From<Option<Resource>> for Service {
+    fn from(value: Option<Resource>) -> Self {
+        let attrs = match value {
+            Some(resource) => attrs_from_kvs(resource.attributes),
+            None => HashMap::new(),
+        };
+        // NB: the service name is defined by the semantic conventions
+        let name = attrs
+            .get(SemConv::SERVICE_NAME)
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        Service { name, attrs }
+    }
+}
+
+impl From<InstrumentationScope> for Scope {
+    fn from(value: InstrumentationScope) -> Self {
+        Scope {
+            name: value.name,
+            version: if value.version.is_empty() {
+                None
+            } else {
+                Some(value.version)
+            },
+            attrs: attrs_from_kvs(value.attributes),
+        }
+    }
+}
+
+impl TryFrom<OtlpSpan> for Span {
+    type Error = Error;
+
+    fn try_from(value: OtlpSpan) -> Result<Self, Self::Error> {
+        let trace_id = trace_id_from_bytes(&value.trace_id)?;
+        let id = span_id_from_bytes(&value.span_id)?;
+        // NB: a root span has no parent (empty bytes)
+        let parent_id = if value.parent_span_id.is_empty() {
+            None
+        } else {
+            Some(span_id_from_bytes(&value.parent_span_id)?)
+        };
+        Ok(Span {
+            id,
+            parent_id,
+            trace_id,
+            name: value.name,
+            start: value.start_time_unix_nano.into(),
+            end: value.end_time_unix_nano.into(),
+            kind: span_kind_from_i32(value.kind),
+            status: value.status.map(SpanStatus::from).unwrap_or_default(),
+            trace_state: value.trace_state,
+            attrs: attrs_from_kvs(value.attributes),
+            dropped_attrs: value.dropped_attributes_count,
+            events: value.events.into_iter().map(SpanEvent::from).collect(),
+            dropped_events: value.dropped_events_count,
+            links: value
+                .links
+                .into_iter()
+                .map(SpanLink::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            dropped_links: value.dropped_links_count,
+        })
+    }
+}
+
+impl From<Event> for SpanEvent {
+    fn from(value: Event) -> Self {
+        SpanEvent {
+            timestamp: value.time_unix_nano.into(),
+            name: value.name,
+            attrs: attrs_from_kvs(value.attributes),
+            dropped_attrs: value.dropped_attributes_count,
+        }
+    }
+}
+
+impl TryFrom<Link> for SpanLink {
+    type Error = Error;
+
+    fn try_from(value: Link) -> Result<Self, Self::Error> {
+        Ok(SpanLink {
+            trace_id: trace_id_from_bytes(&value.trace_id)?,
+            span_id: span_id_from_bytes(&value.span_id)?,
+            trace_state: value.trace_state,
+            attrs: attrs_from_kvs(value.attributes),
+            dropped_attrs: value.dropped_attributes_count,
+        })
+    }
+}
+
+impl From<Status> for SpanStatus {
+    fn from(value: Status) -> Self {
+        let code = match OtlpStatusCode::try_from(value.code) {
+            Ok(OtlpStatusCode::Ok) => StatusCode::Ok,
+            Ok(OtlpStatusCode::Error) => StatusCode::Error,
+            _ => StatusCode::Unset,
+        };
+        SpanStatus {
+            code,
+            message: value.message,
+        }
+    }
+}
+
+/// Converts an OTLP span kind
+fn span_kind_from_i32(value: i32) -> SpanKind {
+    match OtlpSpanKind::try_from(value) {
+        Ok(OtlpSpanKind::Internal) => SpanKind::Internal,
+        Ok(OtlpSpanKind::Server) => SpanKind::Server,
+        Ok(OtlpSpanKind::Client) => SpanKind::Client,
+        Ok(OtlpSpanKind::Producer) => SpanKind::Producer,
+        Ok(OtlpSpanKind::Consumer) => SpanKind::Consumer,
+        _ => SpanKind::Unspecified,
+    }
+}
+
+impl TryFrom<LogRecord> for Log {
+    type Error = Error;
+
+    fn try_from(value: LogRecord) -> Result<Self, Self::Error> {
+        // NB: a log which is not part of a trace has no trace and span IDs
+        let trace_id = if value.trace_id.is_empty() {
+            0
+        } else {
+            trace_id_from_bytes(&value.trace_id)?
+        };
+        let span_id = if value.span_id.is_empty() {
+            0
+        } else {
+            span_id_from_bytes(&value.span_id)?
+        };
+        // NB: if the time is unknown, the observed time is used
+        let timestamp = if value.time_unix_nano == 0 {
+            value.observed_time_unix_nano
+        } else {
+            value.time_unix_nano
+        };
+        let level = i16::try_from(value.severity_number).map_err(|_| {
+            Error::string(format!(
+                "invalid log severity number: {}",
+                value.severity_number
+            ))
+        })?;
+        let message = match value.body.map(AttrValue::from) {
+            Some(AttrValue::String(s)) => s,
+            Some(body) => body.to_string(),
+            None => String::new(),
+        };
+        Ok(Log {
+            trace_id,
+            span_id,
+            timestamp: timestamp.into(),
+            level,
+            message,
+            attrs: attrs_from_kvs(value.attributes),
+        })
+    }
+}
+
+impl TryFrom<OtlpMetric> for Metric {
+    type Error = Error;
+
+    fn try_from(value: OtlpMetric) -> Result<Self, Self::Error> {
+        let data = match value.data {
+            Some(Data::Gauge(gauge)) => MetricData::Gauge {
+                points: gauge
+                    .data_points
+                    .into_iter()
+                    .map(NumberDataPoint::try_from)
+                    .collect::<Result<_, _>>()?,
+            },
+            Some(Data::Sum(sum)) => MetricData::Sum {
+                points: sum
+                    .data_points
+                    .into_iter()
+                    .map(NumberDataPoint::try_from)
+                    .collect::<Result<_, _>>()?,
+                temporality: temporality_from_i32(sum.aggregation_temporality),
+                monotonic: sum.is_monotonic,
+            },
+            Some(Data::Histogram(histogram)) => MetricData::Histogram {
+                points: histogram
+                    .data_points
+                    .into_iter()
+                    .map(HistogramDataPoint::try_from)
+                    .collect::<Result<_, _>>()?,
+                temporality: temporality_from_i32(histogram.aggregation_temporality),
+            },
+            Some(Data::ExponentialHistogram(histogram)) => MetricData::ExpHistogram {
+                points: histogram
+                    .data_points
+                    .into_iter()
+                    .map(ExpHistogramDataPoint::try_from)
+                    .collect::<Result<_, _>>()?,
+                temporality: temporality_from_i32(histogram.aggregation_temporality),
+            },
+            Some(Data::Summary(summary)) => MetricData::Summary {
+                points: summary
+                    .data_points
+                    .into_iter()
+                    .map(SummaryDataPoint::from)
+                    .collect(),
+            },
+            // NB: a metric without data is kept as an empty gauge
+            None => MetricData::Gauge { points: vec![] },
+        };
+        Ok(Metric {
+            name: value.name,
+            descr: value.description,
+            unit: value.unit,
+            data,
+        })
+    }
+}
+
+impl TryFrom<OtlpNumberDataPoint> for NumberDataPoint {
+    type Error = Error;
+
+    fn try_from(value: OtlpNumberDataPoint) -> Result<Self, Self::Error> {
+        Ok(NumberDataPoint {
+            attrs: attrs_from_kvs(value.attributes),
+            start: value.start_time_unix_nano.into(),
+            timestamp: value.time_unix_nano.into(),
+            // NB: a point without value is flagged, and is stored as 0
+            value: match value.value {
+                Some(number_data_point::Value::AsInt(i)) => NumberValue::Int(i),
+                Some(number_data_point::Value::AsDouble(x)) => NumberValue::Float(x),
+                None => NumberValue::Int(0),
+            },
+            exemplars: exemplars_from_otlp(value.exemplars)?,
+            flags: value.flags,
+        })
+    }
+}
+
+impl TryFrom<OtlpHistogramDataPoint> for HistogramDataPoint {
+    type Error = Error;
+
+    fn try_from(value: OtlpHistogramDataPoint) -> Result<Self, Self::Error> {
+        Ok(HistogramDataPoint {
+            attrs: attrs_from_kvs(value.attributes),
+            start: value.start_time_unix_nano.into(),
+            timestamp: value.time_unix_nano.into(),
+            count: value.count,
+            sum: value.sum,
+            bucket_counts: value.bucket_counts,
+            bounds: value.explicit_bounds,
+            min: value.min,
+            max: value.max,
+            exemplars: exemplars_from_otlp(value.exemplars)?,
+            flags: value.flags,
+        })
+    }
+}
+
+impl TryFrom<OtlpExpHistogramDataPoint> for ExpHistogramDataPoint {
+    type Error = Error;
+
+    fn try_from(value: OtlpExpHistogramDataPoint) -> Result<Self, Self::Error> {
+        let buckets = |buckets: Option<Buckets>| {
+            buckets
+                .map(|b| ExpHistogramBuckets {
+                    offset: b.offset,
+                    counts: b.bucket_counts,
+                })
+                .unwrap_or_default()
+        };
+        Ok(ExpHistogramDataPoint {
+            attrs: attrs_from_kvs(value.attributes),
+            start: value.start_time_unix_nano.into(),
+            timestamp: value.time_unix_nano.into(),
+            count: value.count,
+            sum: value.sum,
+            scale: value.scale,
+            zero_count: value.zero_count,
+            zero_threshold: value.zero_threshold,
+            positive: buckets(value.positive),
+            negative: buckets(value.negative),
+            min: value.min,
+            max: value.max,
+            exemplars: exemplars_from_otlp(value.exemplars)?,
+            flags: value.flags,
+        })
+    }
+}
+
+impl From<OtlpSummaryDataPoint> for SummaryDataPoint {
+    fn from(value: OtlpSummaryDataPoint) -> Self {
+        SummaryDataPoint {
+            attrs: attrs_from_kvs(value.attributes),
+            start: value.start_time_unix_nano.into(),
+            timestamp: value.time_unix_nano.into(),
+            count: value.count,
+            sum: value.sum,
+            quantiles: value
+                .quantile_values
+                .into_iter()
+                .map(|q| QuantileValue {
+                    quantile: q.quantile,
+                    value: q.value,
+                })
+                .collect(),
+            flags: value.flags,
+        }
+    }
+}
+
+/// Converts OTLP exemplars
+fn exemplars_from_otlp(exemplars: Vec<OtlpExemplar>) -> Result<Vec<Exemplar>, Error> {
+    exemplars
+        .into_iter()
+        .map(|e| {
+            Ok(Exemplar {
+                timestamp: e.time_unix_nano.into(),
+                value: match e.value {
+                    Some(exemplar::Value::AsInt(i)) => NumberValue::Int(i),
+                    Some(exemplar::Value::AsDouble(x)) => NumberValue::Float(x),
+                    None => NumberValue::Int(0),
+                },
+                // NB: an exemplar which is not recorded in a trace has no trace and span IDs
+                trace_id: if e.trace_id.is_empty() {
+                    0
+                } else {
+                    trace_id_from_bytes(&e.trace_id)?
+                },
+                span_id: if e.span_id.is_empty() {
+                    0
+                } else {
+                    span_id_from_bytes(&e.span_id)?
+                },
+                attrs: attrs_from_kvs(e.filtered_attributes),
+            })
+        })
+        .collect()
+}
+
+/// Converts an OTLP aggregation temporality
+fn temporality_from_i32(value: i32) -> AggregationTemporality {
+    match OtlpTemporality::try_from(value) {
+        Ok(OtlpTemporality::Delta) => AggregationTemporality::Delta,
+        Ok(OtlpTemporality::Cumulative) => AggregationTemporality::Cumulative,
+        _ => AggregationTemporality::Unspecified,
+    }
+}
+
+impl From<AnyValue> for AttrValue {
+    fn from(value: AnyValue) -> Self {
+        match value.value {
+            Some(Value::StringValue(s)) => AttrValue::String(s),
+            Some(Value::BoolValue(b)) => AttrValue::Bool(b),
+            Some(Value::IntValue(i)) => AttrValue::Int(i),
+            Some(Value::DoubleValue(x)) => AttrValue::Float(x),
+            Some(Value::ArrayValue(array)) => {
+                AttrValue::Array(array.values.into_iter().map(AttrValue::from).collect())
+            }
+            Some(Value::KvlistValue(kvs)) => AttrValue::Map(attrs_from_kvs(kvs.values)),
+            Some(Value::BytesValue(b)) => AttrValue::Bytes(b),
+            None => AttrValue::None,
+        }
+    }
+}
+
+/// Converts OTLP key/values to attributes
+fn attrs_from_kvs(kvs: Vec<KeyValue>) -> HashMap<String, AttrValue> {
+    kvs.into_iter()
+        .map(|kv| {
+            let value = kv.value.map(AttrValue::from).unwrap_or(AttrValue::None);
+            (kv.key, value)
+        })
+        .collect()
+}
+
+/// Parses a trace ID (16 bytes, big endian)
+fn trace_id_from_bytes(bytes: &[u8]) -> Result<u128, Error> {
+    let bytes: [u8; 16] = bytes.try_into().map_err(|_| {
+        Error::string(format!(
+            "invalid trace ID '{}': expected 16 bytes, got {}",
+            hex::encode(bytes),
+            bytes.len()
+        ))
+    })?;
+    Ok(u128::from_be_bytes(bytes))
+}
+
+/// Parses a span ID (8 bytes, big endian)
+fn span_id_from_bytes(bytes: &[u8]) -> Result<u64, Error> {
+    let bytes: [u8; 8] = bytes.try_into().map_err(|_| {
+        Error::string(format!(
+            "invalid span ID '{}': expected 8 bytes, got {}",
+            hex::encode(bytes),
+            bytes.len()
+        ))
+    })?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+impl From<TraceData> for ExportTraceServiceRequest {
+    fn from(value: TraceData) -> Self {
+        let groups = value
+            .spans
+            .into_iter()
+            .map(|s| (s.service, s.scope, s.spans));
+        trace_request(groups, OtlpSpan::from)
+    }
+}
+
+impl From<&TraceData> for ExportTraceServiceRequest {
+    fn from(value: &TraceData) -> Self {
+        // NB: the spans are cloned one by one, while they are converted
+        let groups = value.spans.iter().map(|s| {
+            let spans = s.spans.iter().collect::<Vec<_>>();
+            (s.service.clone(), s.scope.clone(), spans)
+        });
+        trace_request(groups, |span| OtlpSpan::from(span.clone()))
+    }
+}
+
+impl From<LogData> for ExportLogsServiceRequest {
+    fn from(value: LogData) -> Self {
+        let groups = value.logs.into_iter().map(|l| (l.service, l.scope, l.logs));
+        logs_request(groups, LogRecord::from)
+    }
+}
+
+impl From<&LogData> for ExportLogsServiceRequest {
+    fn from(value: &LogData) -> Self {
+        let groups = value.logs.iter().map(|l| {
+            let logs = l.logs.iter().collect::<Vec<_>>();
+            (l.service.clone(), l.scope.clone(), logs)
+        });
+        logs_request(groups, |log| LogRecord::from(log.clone()))
+    }
+}
+
+impl From<MetricsData> for ExportMetricsServiceRequest {
+    fn from(value: MetricsData) -> Self {
+        let groups = value
+            .metrics
+            .into_iter()
+            .map(|m| (m.service, m.scope, m.metrics));
+        metrics_request(groups, OtlpMetric::from)
+    }
+}
+
+impl From<&MetricsData> for ExportMetricsServiceRequest {
+    fn from(value: &MetricsData) -> Self {
+        let groups = value.metrics.iter().map(|m| {
+            let metrics = m.metrics.iter().collect::<Vec<_>>();
+            (m.service.clone(), m.scope.clone(), metrics)
+        });
+        metrics_request(groups, |metric| OtlpMetric::from(metric.clone()))
+    }
+}
+
+/// Builds a trace export request, converting the spans
+fn trace_request<T>(
+    groups: impl Iterator<Item = (Service, Option<Scope>, Vec<T>)>,
+    convert: impl Fn(T) -> OtlpSpan,
+) -> ExportTraceServiceRequest {
+    let resource_spans = group_by_service(groups)
+        .into_iter()
+        .map(|(service, scopes)| ResourceSpans {
+            resource: Some(Resource::from(service)),
+            scope_spans: scopes
+                .into_iter()
+                .map(|(scope, spans)| ScopeSpans {
+                    scope: scope.map(InstrumentationScope::from),
+                    spans: spans.into_iter().map(&convert).collect(),
+                    schema_url: String::new(),
+                })
+                .collect(),
+            schema_url: String::new(),
+        })
+        .collect();
+    ExportTraceServiceRequest { resource_spans }
+}
+
+/// Builds a logs export request, converting the logs
+fn logs_request<T>(
+    groups: impl Iterator<Item = (Service, Option<Scope>, Vec<T>)>,
+    convert: impl Fn(T) -> LogRecord,
+) -> ExportLogsServiceRequest {
+    let resource_logs = group_by_service(groups)
+        .into_iter()
+        .map(|(service, scopes)| ResourceLogs {
+            resource: Some(Resource::from(service)),
+            scope_logs: scopes
+                .into_iter()
+                .map(|(scope, logs)| ScopeLogs {
+                    scope: scope.map(InstrumentationScope::from),
+                    log_records: logs.into_iter().map(&convert).collect(),
+                    schema_url: String::new(),
+                })
+                .collect(),
+            schema_url: String::new(),
+        })
+        .collect();
+    ExportLogsServiceRequest { resource_logs }
+}
+
+/// Builds a metrics export request, converting the metrics
+fn metrics_request<T>(
+    groups: impl Iterator<Item = (Service, Option<Scope>, Vec<T>)>,
+    convert: impl Fn(T) -> OtlpMetric,
+) -> ExportMetricsServiceRequest {
+    let resource_metrics = group_by_service(groups)
+        .into_iter()
+        .map(|(service, scopes)| ResourceMetrics {
+            resource: Some(Resource::from(service)),
+            scope_metrics: scopes
+                .into_iter()
+                .map(|(scope, metrics)| ScopeMetrics {
+                    scope: scope.map(InstrumentationScope::from),
+                    metrics: metrics.into_iter().map(&convert).collect(),
+                    schema_url: String::new(),
+                })
+                .collect(),
+            schema_url: String::new(),
+        })
+        .collect();
+    ExportMetricsServiceRequest { resource_metrics }
+}
+
+/// Items grouped by scope
+type ScopeGroups<T> = Vec<(Option<Scope>, Vec<T>)>;
+
+/// Groups items by service, then by scope
+///
+/// The order of first appearance is preserved, and items sharing the same service
+/// and scope are merged.
+fn group_by_service<T>(
+    groups: impl Iterator<Item = (Service, Option<Scope>, Vec<T>)>,
+) -> Vec<(Service, ScopeGroups<T>)> {
+    let mut services: Vec<(Service, ScopeGroups<T>)> = vec![];
+    for (service, scope, items) in groups {
+        let scopes = match services.iter().position(|(s, _)| *s == service) {
+            Some(i) => &mut services[i].1,
+            None => {
+                services.push((service, vec![]));
+                &mut services.last_mut().unwrap().1
+            }
+        };
+        match scopes.iter_mut().find(|(s, _)| *s == scope) {
+            Some((_, scope_items)) => scope_items.extend(items),
+            None => scopes.push((scope, items)),
+        }
+    }
+    services
+}
+
+impl From<Service> for Resource {
+    fn from(value: Service) -> Self {
+        let mut attrs = value.attrs;
+        // NB: the service name is always set as a resource attribute
+        if !value.name.is_empty() {
+            attrs
+                .entry(SemConv::SERVICE_NAME.to_string())
+                .or_insert(AttrValue::String(value.name));
+        }
+        Resource {
+            attributes: kvs_from_attrs(attrs),
+            dropped_attributes_count: 0,
+        }
+    }
+}
+
+impl From<Scope> for InstrumentationScope {
+    fn from(value: Scope) -> Self {
+        InstrumentationScope {
+            name: value.name,
+            version: value.version.unwrap_or_default(),
+            attributes: kvs_from_attrs(value.attrs),
+            dropped_attributes_count: 0,
+        }
+    }
+}
+
+impl From<Span> for OtlpSpan {
+    fn from(value: Span) -> Self {
+        OtlpSpan {
+            trace_id: value.trace_id.to_be_bytes().to_vec(),
+            span_id: value.id.to_be_bytes().to_vec(),
+            trace_state: value.trace_state,
+            parent_span_id: value
+                .parent_id
+                .map(|id| id.to_be_bytes().to_vec())
+                .unwrap_or_default(),
+            name: value.name,
+            kind: OtlpSpanKind::from(value.kind).into(),
+            start_time_unix_nano: unix_nanos(value.start),
+            end_time_unix_nano: unix_nanos(value.end),
+            attributes: kvs_from_attrs(value.attrs),
+            dropped_attributes_count: value.dropped_attrs,
+            events: value.events.into_iter().map(Event::from).collect(),
+            dropped_events_count: value.dropped_events,
+            links: value.links.into_iter().map(Link::from).collect(),
+            dropped_links_count: value.dropped_links,
+            // NB: an unset status is omitted
+            status: if value.status == SpanStatus::default() {
+                None
+            } else {
+                Some(Status::from(value.status))
+            },
+        }
+    }
+}
+
+impl From<SpanEvent> for Event {
+    fn from(value: SpanEvent) -> Self {
+        Event {
+            time_unix_nano: unix_nanos(value.timestamp),
+            name: value.name,
+            attributes: kvs_from_attrs(value.attrs),
+            dropped_attributes_count: value.dropped_attrs,
+        }
+    }
+}
+
+impl From<SpanLink> for Link {
+    fn from(value: SpanLink) -> Self {
+        Link {
+            trace_id: value.trace_id.to_be_bytes().to_vec(),
+            span_id: value.span_id.to_be_bytes().to_vec(),
+            trace_state: value.trace_state,
+            attributes: kvs_from_attrs(value.attrs),
+            dropped_attributes_count: value.dropped_attrs,
+        }
+    }
+}
+
+impl From<SpanStatus> for Status {
+    fn from(value: SpanStatus) -> Self {
+        let code = match value.code {
+            StatusCode::Unset => OtlpStatusCode::Unset,
+            StatusCode::Ok => OtlpStatusCode::Ok,
+            StatusCode::Error => OtlpStatusCode::Error,
+        };
+        Status {
+            message: value.message,
+            code: code.into(),
+        }
+    }
+}
+
+impl From<SpanKind> for OtlpSpanKind {
+    fn from(value: SpanKind) -> Self {
+        match value {
+            SpanKind::Unspecified => OtlpSpanKind::Unspecified,
+            SpanKind::Internal => OtlpSpanKind::Internal,
+            SpanKind::Server => OtlpSpanKind::Server,
+            SpanKind::Client => OtlpSpanKind::Client,
+            SpanKind::Producer => OtlpSpanKind::Producer,
+            SpanKind::Consumer => OtlpSpanKind::Consumer,
+        }
+    }
+}
+
+impl From<Log> for LogRecord {
+    fn from(value: Log) -> Self {
+        let timestamp = unix_nanos(value.timestamp);
+        LogRecord {
+            time_unix_nano: timestamp,
+            observed_time_unix_nano: timestamp,
+            severity_number: value.level.into(),
+            severity_text: String::new(),
+            body: if value.message.is_empty() {
+                None
+            } else {
+                Some(AnyValue::from(AttrValue::String(value.message)))
+            },
+            attributes: kvs_from_attrs(value.attrs),
+            dropped_attributes_count: 0,
+            flags: 0,
+            // NB: a log which is not part of a trace has no trace and span IDs
+            trace_id: if value.trace_id == 0 {
+                vec![]
+            } else {
+                value.trace_id.to_be_bytes().to_vec()
+            },
+            span_id: if value.span_id == 0 {
+                vec![]
+            } else {
+                value.span_id.to_be_bytes().to_vec()
+            },
+        }
+    }
+}
+
+impl From<Metric> for OtlpMetric {
+    fn from(value: Metric) -> Self {
+        let data = match value.data {
+            MetricData::Gauge { points } => Data::Gauge(Gauge {
+                data_points: points.into_iter().map(OtlpNumberDataPoint::from).collect(),
+            }),
+            MetricData::Sum {
+                points,
+                temporality,
+                monotonic,
+            } => Data::Sum(Sum {
+                data_points: points.into_iter().map(OtlpNumberDataPoint::from).collect(),
+                aggregation_temporality: OtlpTemporality::from(temporality).into(),
+                is_monotonic: monotonic,
+            }),
+            MetricData::Histogram {
+                points,
+                temporality,
+            } => Data::Histogram(Histogram {
+                data_points: points
+                    .into_iter()
+                    .map(OtlpHistogramDataPoint::from)
+                    .collect(),
+                aggregation_temporality: OtlpTemporality::from(temporality).into(),
+            }),
+            MetricData::ExpHistogram {
+                points,
+                temporality,
+            } => Data::ExponentialHistogram(ExponentialHistogram {
+                data_points: points
+                    .into_iter()
+                    .map(OtlpExpHistogramDataPoint::from)
+                    .collect(),
+                aggregation_temporality: OtlpTemporality::from(temporality).into(),
+            }),
+            MetricData::Summary { points } => Data::Summary(Summary {
+                data_points: points.into_iter().map(OtlpSummaryDataPoint::from).collect(),
+            }),
+        };
+        OtlpMetric {
+            name: value.name,
+            description: value.descr,
+            unit: value.unit,
+            data: Some(data),
+        }
+    }
+}
+
+impl From<NumberDataPoint> for OtlpNumberDataPoint {
+    fn from(value: NumberDataPoint) -> Self {
+        OtlpNumberDataPoint {
+            attributes: kvs_from_attrs(value.attrs),
+            start_time_unix_nano: unix_nanos(value.start),
+            time_unix_nano: unix_nanos(value.timestamp),
+            exemplars: value
+                .exemplars
+                .into_iter()
+                .map(OtlpExemplar::from)
+                .collect(),
+            flags: value.flags,
+            value: Some(match value.value {
+                NumberValue::Int(i) => number_data_point::Value::AsInt(i),
+                NumberValue::Float(x) => number_data_point::Value::AsDouble(x),
+            }),
+        }
+    }
+}
+
+impl From<HistogramDataPoint> for OtlpHistogramDataPoint {
+    fn from(value: HistogramDataPoint) -> Self {
+        OtlpHistogramDataPoint {
+            attributes: kvs_from_attrs(value.attrs),
+            start_time_unix_nano: unix_nanos(value.start),
+            time_unix_nano: unix_nanos(value.timestamp),
+            count: value.count,
+            sum: value.sum,
+            bucket_counts: value.bucket_counts,
+            explicit_bounds: value.bounds,
+            exemplars: value
+                .exemplars
+                .into_iter()
+                .map(OtlpExemplar::from)
+                .collect(),
+            flags: value.flags,
+            min: value.min,
+            max: value.max,
+        }
+    }
+}
+
+impl From<ExpHistogramDataPoint> for OtlpExpHistogramDataPoint {
+    fn from(value: ExpHistogramDataPoint) -> Self {
+        let buckets = |buckets: ExpHistogramBuckets| {
+            Some(Buckets {
+                offset: buckets.offset,
+                bucket_counts: buckets.counts,
+            })
+        };
+        OtlpExpHistogramDataPoint {
+            attributes: kvs_from_attrs(value.attrs),
+            start_time_unix_nano: unix_nanos(value.start),
+            time_unix_nano: unix_nanos(value.timestamp),
+            count: value.count,
+            sum: value.sum,
+            scale: value.scale,
+            zero_count: value.zero_count,
+            positive: buckets(value.positive),
+            negative: buckets(value.negative),
+            flags: value.flags,
+            exemplars: value
+                .exemplars
+                .into_iter()
+                .map(OtlpExemplar::from)
+                .collect(),
+            min: value.min,
+            max: value.max,
+            zero_threshold: value.zero_threshold,
+        }
+    }
+}
+
+impl From<SummaryDataPoint> for OtlpSummaryDataPoint {
+    fn from(value: SummaryDataPoint) -> Self {
+        OtlpSummaryDataPoint {
+            attributes: kvs_from_attrs(value.attrs),
+            start_time_unix_nano: unix_nanos(value.start),
+            time_unix_nano: unix_nanos(value.timestamp),
+            count: value.count,
+            sum: value.sum,
+            quantile_values: value
+                .quantiles
+                .into_iter()
+                .map(|q| ValueAtQuantile {
+                    quantile: q.quantile,
+                    value: q.value,
+                })
+                .collect(),
+            flags: value.flags,
+        }
+    }
+}
+
+impl From<Exemplar> for OtlpExemplar {
+    fn from(value: Exemplar) -> Self {
+        OtlpExemplar {
+            filtered_attributes: kvs_from_attrs(value.attrs),
+            time_unix_nano: unix_nanos(value.timestamp),
+            span_id: if value.span_id == 0 {
+                vec![]
+            } else {
+                value.span_id.to_be_bytes().to_vec()
+            },
+            trace_id: if value.trace_id == 0 {
+                vec![]
+            } else {
+                value.trace_id.to_be_bytes().to_vec()
+            },
+            value: Some(match value.value {
+                NumberValue::Int(i) => exemplar::Value::AsInt(i),
+                NumberValue::Float(x) => exemplar::Value::AsDouble(x),
+            }),
+        }
+    }
+}
+
+impl From<AggregationTemporality> for OtlpTemporality {
+    fn from(value: AggregationTemporality) -> Self {
+        match value {
+            AggregationTemporality::Unspecified => OtlpTemporality::Unspecified,
+            AggregationTemporality::Delta => OtlpTemporality::Delta,
+            AggregationTemporality::Cumulative => OtlpTemporality::Cumulative,
+        }
+    }
+}
+
+impl From<AttrValue> for AnyValue {
+    fn from(value: AttrValue) -> Self {
+        let value = match value {
+            AttrValue::None => None,
+            AttrValue::Bool(b) => Some(Value::BoolValue(b)),
+            // NB: OTLP has no unsigned integers, large values are stringified
+            AttrValue::Uint(u) => Some(match i64::try_from(u) {
+                Ok(i) => Value::IntValue(i),
+                Err(_) => Value::StringValue(u.to_string()),
+            }),
+            AttrValue::Int(i) => Some(Value::IntValue(i)),
+            AttrValue::Float(x) => Some(Value::DoubleValue(x)),
+            AttrValue::String(s) => Some(Value::StringValue(s)),
+            AttrValue::Bytes(b) => Some(Value::BytesValue(b)),
+            AttrValue::Array(values) => Some(Value::ArrayValue(ArrayValue {
+                values: values.into_iter().map(AnyValue::from).collect(),
+            })),
+            AttrValue::Map(map) => Some(Value::KvlistValue(KeyValueList {
+                values: kvs_from_attrs(map),
+            })),
+        };
+        AnyValue { value }
+    }
+}
+
+/// Converts attributes to OTLP key/values
+///
+/// The key/values are sorted by key, for a deterministic output.
+fn kvs_from_attrs(attrs: HashMap<String, AttrValue>) -> Vec<KeyValue> {
+    let mut kvs = attrs
+        .into_iter()
+        .map(|(key, value)| KeyValue {
+            key,
+            value: Some(AnyValue::from(value)),
+        })
+        .collect::<Vec<_>>();
+    kvs.sort_by(|a, b| a.key.cmp(&b.key));
+    kvs
+}
+
+/// Converts a timestamp to OTLP unix nanoseconds (out of range values are clamped)
+fn unix_nanos(timestamp: i128) -> u64 {
+    timestamp.clamp(0, u64::MAX.into()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trace fixture (OTLP/JSON)
+    static TRACE_DATA: &str = include_str!("../../../obsv-otlp/src/server/http/trace.json");
+
+    /// Returns an OTLP key/value
+    fn kv(key: &str, value: Value) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue { value: Some(value) }),
+        }
+    }
+
+    /// Returns an OTLP resource for a service
+    fn resource(service: &str) -> Option<Resource> {
+        Some(Resource {
+            attributes: vec![kv(
+                SemConv::SERVICE_NAME,
+                Value::StringValue(service.to_string()),
+            )],
+            dropped_attributes_count: 0,
+        })
+    }
+
+    #[test]
+    fn otlp_trace_to_core() {
+        let req = serde_json::from_str::<ExportTraceServiceRequest>(TRACE_DATA).unwrap();
+        let data = TraceData::try_from(req).unwrap();
+
+        assert_eq!(data.spans.len(), 1);
+        let service_spans = &data.spans[0];
+        assert_eq!(service_spans.service.name, "my_service");
+        let scope = service_spans.scope.as_ref().unwrap();
+        assert_eq!(scope.name, "my_span");
+        assert_eq!(scope.version.as_deref(), Some("v1"));
+        assert_eq!(
+            scope.attrs.get("scoped_span.attr"),
+            Some(&AttrValue::String("my_service".to_string()))
+        );
+
+        let span = &service_spans.spans[0];
+        assert_eq!(span.trace_id, 0x5b8aa5a2d2c872e8321cf37308d69df2);
+        assert_eq!(span.id, 0x5fb397be34d26b51);
+        assert_eq!(span.parent_id, None);
+        assert_eq!(span.kind, SpanKind::Internal);
+        assert_eq!(span.status, SpanStatus::default());
+        assert_eq!(span.start, 1);
+        assert_eq!(span.end, 2);
+        assert_eq!(
+            span.attrs.get("attr1"),
+            Some(&AttrValue::String("attr1_value".to_string()))
+        );
+        assert_eq!(span.events.len(), 1);
+        assert_eq!(span.events[0].name, "event 1");
+
+        // the core data survives a serde round trip
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<TraceData>(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn otlp_trace_invalid_ids() {
+        let mut req = serde_json::from_str::<ExportTraceServiceRequest>(TRACE_DATA).unwrap();
+        req.resource_spans[0].scope_spans[0].spans[0].trace_id = vec![0x01, 0x02];
+        let err = TraceData::try_from(req).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid trace ID '0102': expected 16 bytes, got 2"
+        );
+
+        let mut req = serde_json::from_str::<ExportTraceServiceRequest>(TRACE_DATA).unwrap();
+        req.resource_spans[0].scope_spans[0].spans[0].parent_span_id = vec![0x01; 9];
+        let err = TraceData::try_from(req).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid span ID '010101010101010101': expected 8 bytes, got 9"
+        );
+    }
+
+    #[test]
+    fn otlp_logs_to_core() {
+        let req = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: resource("my_service"),
+                scope_logs: vec![ScopeLogs {
+                    scope: None,
+                    log_records: vec![LogRecord {
+                        time_unix_nano: 0,
+                        observed_time_unix_nano: 10_000_000,
+                        severity_number: 9,
+                        severity_text: "info".to_string(),
+                        body: Some(AnyValue {
+                            value: Some(Value::StringValue("body".to_string())),
+                        }),
+                        attributes: vec![kv(
+                            "list",
+                            Value::ArrayValue(ArrayValue {
+                                values: vec![AnyValue {
+                                    value: Some(Value::KvlistValue(KeyValueList {
+                                        values: vec![kv("key", Value::BytesValue(vec![0xff]))],
+                                    })),
+                                }],
+                            }),
+                        )],
+                        dropped_attributes_count: 0,
+                        flags: 0,
+                        trace_id: 1_u128.to_be_bytes().to_vec(),
+                        span_id: vec![],
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        let data = LogData::try_from(req).unwrap();
+
+        let service_logs = &data.logs[0];
+        assert_eq!(service_logs.service.name, "my_service");
+        assert_eq!(service_logs.scope, None);
+        let log = &service_logs.logs[0];
+        assert_eq!(log.trace_id, 1);
+        assert_eq!(log.span_id, 0);
+        assert_eq!(log.timestamp, 10_000_000);
+        assert_eq!(log.level, 9);
+        assert_eq!(log.message, "body");
+        assert_eq!(
+            log.attrs.get("list"),
+            Some(&AttrValue::Array(vec![AttrValue::Map(HashMap::from([(
+                "key".to_string(),
+                AttrValue::Bytes(vec![0xff])
+            )]))]))
+        );
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<LogData>(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn otlp_metrics_to_core() {
+        let req = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: resource("my_service"),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: "my scope".to_string(),
+                        version: String::new(),
+                        attributes: vec![],
+                        dropped_attributes_count: 0,
+                    }),
+                    metrics: vec![OtlpMetric {
+                        name: "my metric".to_string(),
+                        description: "desc".to_string(),
+                        unit: "MB".to_string(),
+                        data: Some(Data::Summary(Summary {
+                            data_points: vec![OtlpSummaryDataPoint {
+                                attributes: vec![kv("key", Value::IntValue(1))],
+                                start_time_unix_nano: 0,
+                                time_unix_nano: 1,
+                                count: 1,
+                                sum: 2.5,
+                                quantile_values: vec![ValueAtQuantile {
+                                    quantile: 1.1,
+                                    value: 1.2,
+                                }],
+                                flags: 0,
+                            }],
+                        })),
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        let data = MetricsData::try_from(req).unwrap();
+
+        let service_metrics = &data.metrics[0];
+        assert_eq!(service_metrics.service.name, "my_service");
+        assert_eq!(service_metrics.scope.as_ref().unwrap().version, None);
+        let metric = &service_metrics.metrics[0];
+        assert_eq!(metric.name, "my metric");
+        assert_eq!(
+            metric.data,
+            MetricData::Summary {
+                points: vec![SummaryDataPoint {
+                    attrs: HashMap::from([("key".to_string(), AttrValue::Int(1))]),
+                    start: 0,
+                    timestamp: 1,
+                    count: 1,
+                    sum: 2.5,
+                    quantiles: vec![QuantileValue {
+                        quantile: 1.1,
+                        value: 1.2
+                    }],
+                    flags: 0,
+                }]
+            }
+        );
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<MetricsData>(&json).unwrap(), data);
+    }
+
+    /// Returns a core span
+    fn span(id: u64, parent_id: Option<u64>) -> Span {
+        Span {
+            id,
+            parent_id,
+            trace_id: 1,
+            name: format!("span {id}"),
+            start: 1,
+            end: 2,
+            kind: SpanKind::Server,
+            status: SpanStatus {
+                code: StatusCode::Error,
+                message: "error".to_string(),
+            },
+            trace_state: "key=value".to_string(),
+            attrs: HashMap::from([
+                ("key".to_string(), AttrValue::Int(-1)),
+                ("big".to_string(), AttrValue::Uint(u64::MAX)),
+            ]),
+            dropped_attrs: 1,
+            events: vec![SpanEvent {
+                timestamp: 1,
+                name: "event".to_string(),
+                attrs: HashMap::new(),
+                dropped_attrs: 0,
+            }],
+            dropped_events: 2,
+            links: vec![SpanLink {
+                trace_id: 2,
+                span_id: 3,
+                trace_state: String::new(),
+                attrs: HashMap::new(),
+                dropped_attrs: 0,
+            }],
+            dropped_links: 3,
+        }
+    }
+
+    #[test]
+    fn otlp_trace_round_trip() {
+        let req = serde_json::from_str::<ExportTraceServiceRequest>(TRACE_DATA).unwrap();
+        let data = TraceData::try_from(req).unwrap();
+        let data_rt = TraceData::try_from(ExportTraceServiceRequest::from(data.clone())).unwrap();
+        assert_eq!(data_rt, data);
+    }
+
+    #[test]
+    fn otlp_trace_from_ref() {
+        let req = serde_json::from_str::<ExportTraceServiceRequest>(TRACE_DATA).unwrap();
+        let data = TraceData::try_from(req).unwrap();
+        let data_rt = TraceData::try_from(ExportTraceServiceRequest::from(&data)).unwrap();
+        assert_eq!(data_rt, data);
+    }
+
+    #[test]
+    fn core_trace_to_otlp() {
+        let service = Service::from(resource("my_service"));
+        let scope = |name: &str| {
+            Some(Scope {
+                name: name.to_string(),
+                version: Some("v1".to_string()),
+                attrs: HashMap::new(),
+            })
+        };
+        let data = TraceData {
+            spans: vec![
+                ServiceSpans {
+                    service: service.clone(),
+                    scope: scope("a"),
+                    spans: vec![span(1, None)],
+                },
+                ServiceSpans {
+                    service: service.clone(),
+                    scope: scope("b"),
+                    spans: vec![span(2, Some(1))],
+                },
+                ServiceSpans {
+                    service: service.clone(),
+                    scope: scope("a"),
+                    spans: vec![span(3, Some(1))],
+                },
+            ],
+        };
+        let req = ExportTraceServiceRequest::from(data);
+
+        // spans are regrouped by service and scope
+        assert_eq!(req.resource_spans.len(), 1);
+        let resource_spans = &req.resource_spans[0];
+        assert_eq!(resource_spans.resource, resource("my_service"));
+        assert_eq!(resource_spans.scope_spans.len(), 2);
+        let scope_spans = &resource_spans.scope_spans[0];
+        assert_eq!(scope_spans.scope.as_ref().unwrap().name, "a");
+        assert_eq!(scope_spans.spans.len(), 2);
+        let span_1 = &scope_spans.spans[0];
+        assert_eq!(span_1.trace_id, 1_u128.to_be_bytes().to_vec());
+        assert_eq!(span_1.span_id, 1_u64.to_be_bytes().to_vec());
+        assert!(span_1.parent_span_id.is_empty());
+        assert_eq!(span_1.kind, OtlpSpanKind::Server as i32);
+        assert_eq!(
+            span_1.status,
+            Some(Status {
+                message: "error".to_string(),
+                code: OtlpStatusCode::Error as i32,
+            })
+        );
+        assert_eq!(span_1.links[0].span_id, 3_u64.to_be_bytes().to_vec());
+        assert_eq!(span_1.dropped_links_count, 3);
+        assert_eq!(
+            span_1.attributes,
+            vec![
+                kv("big", Value::StringValue(u64::MAX.to_string())),
+                kv("key", Value::IntValue(-1)),
+            ]
+        );
+        assert_eq!(
+            scope_spans.spans[1].parent_span_id,
+            1_u64.to_be_bytes().to_vec()
+        );
+
+        // the regrouped data converts back to core
+        let data = TraceData::try_from(req).unwrap();
+        assert_eq!(data.spans.len(), 2);
+        // NB: large unsigned integers are stringified in OTLP
+        let mut span_3 = span(3, Some(1));
+        span_3
+            .attrs
+            .insert("big".to_string(), AttrValue::String(u64::MAX.to_string()));
+        assert_eq!(data.spans[0].spans[1], span_3);
+    }
+
+    #[test]
+    fn core_logs_round_trip() {
+        let data = LogData {
+            logs: vec![ServiceLogs {
+                service: Service::from(resource("my_service")),
+                scope: None,
+                logs: vec![
+                    Log {
+                        trace_id: 1,
+                        span_id: 2,
+                        timestamp: 10,
+                        level: 9,
+                        message: "message".to_string(),
+                        attrs: HashMap::from([(
+                            "map".to_string(),
+                            AttrValue::Map(HashMap::from([(
+                                "list".to_string(),
+                                AttrValue::Array(vec![
+                                    AttrValue::Bool(true),
+                                    AttrValue::Float(1.5),
+                                    AttrValue::Bytes(vec![0xff]),
+                                ]),
+                            )])),
+                        )]),
+                    },
+                    Log {
+                        trace_id: 0,
+                        span_id: 0,
+                        timestamp: 20,
+                        level: 17,
+                        message: String::new(),
+                        attrs: HashMap::new(),
+                    },
+                ],
+            }],
+        };
+        let req = ExportLogsServiceRequest::from(data.clone());
+        let log_record = &req.resource_logs[0].scope_logs[0].log_records[1];
+        assert!(log_record.trace_id.is_empty());
+        assert!(log_record.span_id.is_empty());
+        assert_eq!(log_record.body, None);
+        assert_eq!(LogData::try_from(req).unwrap(), data);
+    }
+
+    #[test]
+    fn core_metrics_round_trip() {
+        let attrs = || HashMap::from([("key".to_string(), AttrValue::String("value".to_string()))]);
+        let metric = |name: &str, data: MetricData| Metric {
+            name: name.to_string(),
+            descr: "desc".to_string(),
+            unit: "MB".to_string(),
+            data,
+        };
+        let exemplar = Exemplar {
+            timestamp: 1,
+            value: NumberValue::Float(0.5),
+            trace_id: 1,
+            span_id: 2,
+            attrs: attrs(),
+        };
+        let data = MetricsData {
+            metrics: vec![ServiceMetrics {
+                service: Service::from(resource("my_service")),
+                scope: None,
+                metrics: vec![
+                    metric(
+                        "gauge",
+                        MetricData::Gauge {
+                            points: vec![NumberDataPoint {
+                                attrs: attrs(),
+                                start: 0,
+                                timestamp: 1,
+                                value: NumberValue::Float(2.5),
+                                exemplars: vec![],
+                                flags: 0,
+                            }],
+                        },
+                    ),
+                    metric(
+                        "sum",
+                        MetricData::Sum {
+                            points: vec![NumberDataPoint {
+                                attrs: attrs(),
+                                start: 0,
+                                timestamp: 1,
+                                value: NumberValue::Int(3),
+                                exemplars: vec![exemplar.clone()],
+                                flags: 0,
+                            }],
+                            temporality: AggregationTemporality::Cumulative,
+                            monotonic: true,
+                        },
+                    ),
+                    metric(
+                        "histogram",
+                        MetricData::Histogram {
+                            points: vec![HistogramDataPoint {
+                                attrs: attrs(),
+                                start: 0,
+                                timestamp: 1,
+                                count: 3,
+                                sum: Some(6.0),
+                                bucket_counts: vec![1, 2, 0],
+                                bounds: vec![1.0, 5.0],
+                                min: Some(1.0),
+                                max: Some(3.0),
+                                exemplars: vec![exemplar],
+                                flags: 0,
+                            }],
+                            temporality: AggregationTemporality::Delta,
+                        },
+                    ),
+                    metric(
+                        "exp_histogram",
+                        MetricData::ExpHistogram {
+                            points: vec![ExpHistogramDataPoint {
+                                attrs: attrs(),
+                                start: 0,
+                                timestamp: 1,
+                                count: 4,
+                                sum: None,
+                                scale: 2,
+                                zero_count: 1,
+                                zero_threshold: 0.001,
+                                positive: ExpHistogramBuckets {
+                                    offset: -1,
+                                    counts: vec![1, 2],
+                                },
+                                negative: ExpHistogramBuckets::default(),
+                                min: None,
+                                max: None,
+                                exemplars: vec![],
+                                flags: 1,
+                            }],
+                            temporality: AggregationTemporality::Delta,
+                        },
+                    ),
+                ],
+            }],
+        };
+        let req = ExportMetricsServiceRequest::from(data.clone());
+        let metrics = &req.resource_metrics[0].scope_metrics[0].metrics;
+        assert_eq!(metrics.len(), 4);
+        match &metrics[1].data {
+            Some(Data::Sum(sum)) => {
+                assert_eq!(
+                    sum.aggregation_temporality,
+                    OtlpTemporality::Cumulative as i32
+                );
+                assert!(sum.is_monotonic);
+                assert_eq!(
+                    sum.data_points[0].exemplars[0].trace_id,
+                    1_u128.to_be_bytes().to_vec()
+                );
+            }
+            data => panic!("unexpected metric data: {data:?}"),
+        }
+        assert_eq!(MetricsData::try_from(req).unwrap(), data);
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<MetricsData>(&json).unwrap(), data);
+    }
+}
@@ -22,6 +22,11 @@ pub struct Context {
 }
 
 impl Context {
+    /// Checks if the trace is sampled (W3C sampled flag)
+    pub fn is_sampled(&self) -> bool {
+        self.flags & TraceParentHeader::SAMPLED != 0
+    }
+
     /// Returns the HTTP header `traceparent`
     pub fn parent_header(&self) -> TraceParentHeader {
         TraceParentHeader {
@@ -0,0 +1,158 @@
+//! Log correlation
+
+use std::collections::{HashMap, VecDeque};
+
+use super::{Log, LogData};
+
+/// Logs indexed by trace
+///
+/// The logs without a trace ID (0) are not indexed.
+/// When the number of traces is bounded, the oldest traces are evicted first,
+/// and when the number of logs per trace is bounded, the extra logs of a trace are not indexed.
+#[derive(Debug, Clone, Default)]
+pub struct LogIndex {
+    /// Logs by trace ID
+    traces: HashMap<u128, LogData>,
+    /// Trace IDs, in order of arrival
+    order: VecDeque<u128>,
+    /// Maximum number of traces
+    max_traces: Option<usize>,
+    /// Maximum number of logs per trace
+    max_trace_logs: Option<usize>,
+}
+
+impl LogIndex {
+    /// Creates a new log index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of traces
+    pub fn max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = Some(max_traces.max(1));
+        self
+    }
+
+    /// Sets the maximum number of logs per trace
+    pub fn max_trace_logs(mut self, max_trace_logs: usize) -> Self {
+        self.max_trace_logs = Some(max_trace_logs.max(1));
+        self
+    }
+
+    /// Indexes logs
+    pub fn add_logs(&mut self, data: &LogData) {
+        for group in &data.logs {
+            for log in group.logs.iter().filter(|l| l.trace_id != 0) {
+                if let Some(trace_logs) = self.traces.get(&log.trace_id) {
+                    if self
+                        .max_trace_logs
+                        .is_some_and(|max| trace_logs.len() >= max)
+                    {
+                        continue;
+                    }
+                } else {
+                    if self.max_traces.is_some_and(|max| self.traces.len() >= max) {
+                        if let Some(trace_id) = self.order.pop_front() {
+                            self.traces.remove(&trace_id);
+                        }
+                    }
+                    self.order.push_back(log.trace_id);
+                }
+                self.traces.entry(log.trace_id).or_default().add_log(
+                    &group.service,
+                    group.scope.as_ref(),
+                    log.clone(),
+                );
+            }
+        }
+    }
+
+    /// Returns the number of indexed traces
+    pub fn len(&self) -> usize {
+        self.traces.len()
+    }
+
+    /// Checks if there are no indexed traces
+    pub fn is_empty(&self) -> bool {
+        self.traces.is_empty()
+    }
+
+    /// Returns the logs of a trace
+    pub fn trace_logs(&self, trace_id: u128) -> LogData {
+        self.traces.get(&trace_id).cloned().unwrap_or_default()
+    }
+
+    /// Returns the logs of a span
+    pub fn span_logs(&self, trace_id: u128, span_id: u64) -> Vec<&Log> {
+        self.traces
+            .get(&trace_id)
+            .map(|data| {
+                data.logs
+                    .iter()
+                    .flat_map(|g| g.logs.iter())
+                    .filter(|l| l.span_id == span_id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::data::Service;
+
+    use super::*;
+
+    /// Returns logs
+    fn logs(ids: &[(u128, u64)]) -> LogData {
+        let service = Service {
+            name: "api".to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut data = LogData::default();
+        for (trace_id, span_id) in ids {
+            data.add_log(
+                &service,
+                None,
+                Log {
+                    trace_id: *trace_id,
+                    span_id: *span_id,
+                    timestamp: 0,
+                    level: 9,
+                    message: format!("log {trace_id}/{span_id}"),
+                    attrs: HashMap::new(),
+                },
+            );
+        }
+        data
+    }
+
+    #[test]
+    fn log_index() {
+        let mut index = LogIndex::new().max_traces(2);
+        index.add_logs(&logs(&[(1, 1), (1, 2), (1, 2), (0, 0)]));
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.trace_logs(1).len(), 3);
+        assert_eq!(index.span_logs(1, 2).len(), 2);
+        assert!(index.span_logs(2, 1).is_empty());
+
+        // NB: the oldest trace is evicted
+        index.add_logs(&logs(&[(2, 1), (3, 1)]));
+        assert_eq!(index.len(), 2);
+        assert!(index.trace_logs(1).is_empty());
+        assert_eq!(index.span_logs(3, 1)[0].message, "log 3/1");
+    }
+
+    #[test]
+    fn log_index_max_trace_logs() {
+        let mut index = LogIndex::new().max_trace_logs(2);
+        index.add_logs(&logs(&[(1, 1), (1, 2), (1, 3), (2, 1)]));
+        index.add_logs(&logs(&[(1, 4)]));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.trace_logs(1).len(), 2);
+        assert!(index.span_logs(1, 3).is_empty());
+        assert_eq!(index.trace_logs(2).len(), 1);
+    }
+}
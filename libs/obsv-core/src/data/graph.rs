@@ -0,0 +1,604 @@
+//! Service graph
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AggregationTemporality, AttrValue, Metric, MetricData, MetricsData, NumberDataPoint,
+    NumberValue, QuantileValue, Service, SummaryDataPoint, TraceData,
+};
+
+/// Name of the metric holding the durations of the calls between services (summary, nanoseconds)
+pub const SERVICE_GRAPH_DURATION_METRIC: &str = "traces.service.graph.request.duration";
+
+/// Name of the metric holding the number of failed calls between services (sum)
+pub const SERVICE_GRAPH_FAILED_METRIC: &str = "traces.service.graph.request.failed";
+
+/// A service graph
+///
+/// The graph is derived from the parent/child relationships of spans:
+/// a span in a service whose parent span is in another service is a call between the 2 services.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServiceGraph {
+    /// Service names
+    pub services: Vec<String>,
+    /// Calls between services
+    pub edges: Vec<ServiceEdge>,
+}
+
+/// Calls from a service to another service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceEdge {
+    /// Calling service
+    pub client: String,
+    /// Called service
+    pub server: String,
+    /// Number of requests
+    pub requests: u64,
+    /// Number of failed requests
+    pub errors: u64,
+    /// Total duration of the called service spans (nanoseconds)
+    #[serde(default)]
+    pub duration: i128,
+    /// Latency percentiles of the called service spans
+    pub latency: LatencyPercentiles,
+}
+
+/// Latency percentiles (nanoseconds)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyPercentiles {
+    /// 50th percentile
+    pub p50: i128,
+    /// 90th percentile
+    pub p90: i128,
+    /// 99th percentile
+    pub p99: i128,
+}
+
+impl LatencyPercentiles {
+    /// Computes the percentiles of durations (nearest rank)
+    pub fn from_durations(mut durations: Vec<i128>) -> Self {
+        if durations.is_empty() {
+            return Self::default();
+        }
+        durations.sort_unstable();
+        let rank = |p: f64| {
+            let index = (p * durations.len() as f64).ceil() as usize;
+            durations[index.clamp(1, durations.len()) - 1]
+        };
+        Self {
+            p50: rank(0.5),
+            p90: rank(0.9),
+            p99: rank(0.99),
+        }
+    }
+}
+
+impl ServiceGraph {
+    /// Builds the service graph of traces
+    pub fn from_traces(data: &TraceData) -> Self {
+        let mut builder = ServiceGraphBuilder::new();
+        builder.add_traces(data);
+        builder.build()
+    }
+
+    /// Keeps the calls from or to a service
+    pub fn service(mut self, service: &str) -> Self {
+        self.edges
+            .retain(|e| e.client == service || e.server == service);
+        self.services = self
+            .edges
+            .iter()
+            .flat_map(|e| [e.client.clone(), e.server.clone()])
+            .chain([service.to_string()])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        self
+    }
+
+    /// Converts the graph to metrics (see [ServiceGraph::from_metrics])
+    ///
+    /// The metrics of a call are added to the called service.
+    pub fn to_metrics(&self, start: i128, timestamp: i128) -> MetricsData {
+        let mut data = MetricsData::default();
+        for edge in &self.edges {
+            let service = Service {
+                name: edge.server.clone(),
+                attrs: HashMap::new(),
+            };
+            let attrs = HashMap::from([
+                ("client".to_string(), AttrValue::String(edge.client.clone())),
+                ("server".to_string(), AttrValue::String(edge.server.clone())),
+            ]);
+            let latency = edge.latency;
+            data.add_metric(
+                &service,
+                None,
+                Metric {
+                    name: SERVICE_GRAPH_DURATION_METRIC.to_string(),
+                    descr: "Durations of the calls between services".to_string(),
+                    unit: "ns".to_string(),
+                    data: MetricData::Summary {
+                        points: vec![SummaryDataPoint {
+                            attrs: attrs.clone(),
+                            start,
+                            timestamp,
+                            count: edge.requests,
+                            sum: edge.duration as f64,
+                            quantiles: [
+                                (0.5, latency.p50),
+                                (0.9, latency.p90),
+                                (0.99, latency.p99),
+                            ]
+                            .into_iter()
+                            .map(|(quantile, value)| QuantileValue {
+                                quantile,
+                                value: value as f64,
+                            })
+                            .collect(),
+                            flags: 0,
+                        }],
+                    },
+                },
+            );
+            data.add_metric(
+                &service,
+                None,
+                Metric {
+                    name: SERVICE_GRAPH_FAILED_METRIC.to_string(),
+                    descr: "Number of failed calls between services".to_string(),
+                    unit: "1".to_string(),
+                    data: MetricData::Sum {
+                        points: vec![NumberDataPoint {
+                            attrs,
+                            start,
+                            timestamp,
+                            value: NumberValue::Int(edge.errors as i64),
+                            exemplars: vec![],
+                            flags: 0,
+                        }],
+                        temporality: AggregationTemporality::Delta,
+                        monotonic: true,
+                    },
+                },
+            );
+        }
+        data
+    }
+
+    /// Builds the service graph from its metrics (see [ServiceGraph::to_metrics])
+    ///
+    /// The calls of several intervals are merged, and the latency percentiles are approximated
+    /// by the mean of the interval percentiles, weighted by their number of requests.
+    pub fn from_metrics(data: &MetricsData) -> Self {
+        let mut edges: BTreeMap<(String, String), (ServiceEdge, [f64; 3])> = BTreeMap::new();
+        let metrics = data.metrics.iter().flat_map(|m| m.metrics.iter());
+        for metric in metrics {
+            match (metric.name.as_str(), &metric.data) {
+                (SERVICE_GRAPH_DURATION_METRIC, MetricData::Summary { points }) => {
+                    for point in points {
+                        let Some(key) = edge_key(&point.attrs) else {
+                            continue;
+                        };
+                        let (edge, weighted) = edges.entry(key).or_insert_with_key(new_edge);
+                        edge.requests += point.count;
+                        edge.duration += point.sum as i128;
+                        for q in &point.quantiles {
+                            let index = [0.5, 0.9, 0.99].iter().position(|p| *p == q.quantile);
+                            if let Some(index) = index {
+                                weighted[index] += q.value * point.count as f64;
+                            }
+                        }
+                    }
+                }
+                (SERVICE_GRAPH_FAILED_METRIC, MetricData::Sum { points, .. }) => {
+                    for point in points {
+                        let Some(key) = edge_key(&point.attrs) else {
+                            continue;
+                        };
+                        let (edge, _) = edges.entry(key).or_insert_with_key(new_edge);
+                        edge.errors += point.value.as_f64().max(0.0) as u64;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut services = BTreeSet::new();
+        let edges = edges
+            .into_values()
+            .map(|(mut edge, [p50, p90, p99])| {
+                let requests = edge.requests.max(1) as f64;
+                edge.latency = LatencyPercentiles {
+                    p50: (p50 / requests) as i128,
+                    p90: (p90 / requests) as i128,
+                    p99: (p99 / requests) as i128,
+                };
+                services.insert(edge.client.clone());
+                services.insert(edge.server.clone());
+                edge
+            })
+            .collect();
+        Self {
+            services: services.into_iter().collect(),
+            edges,
+        }
+    }
+}
+
+/// Returns the client and server of a service graph data point
+fn edge_key(attrs: &HashMap<String, AttrValue>) -> Option<(String, String)> {
+    match (attrs.get("client"), attrs.get("server")) {
+        (Some(AttrValue::String(client)), Some(AttrValue::String(server))) => {
+            Some((client.clone(), server.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Returns an empty edge (with its weighted percentiles)
+fn new_edge((client, server): &(String, String)) -> (ServiceEdge, [f64; 3]) {
+    let edge = ServiceEdge {
+        client: client.clone(),
+        server: server.clone(),
+        requests: 0,
+        errors: 0,
+        duration: 0,
+        latency: LatencyPercentiles::default(),
+    };
+    (edge, [0.0; 3])
+}
+
+/// Service graph builder
+///
+/// The spans can be added in several batches, since the parent and child spans
+/// of a call are not always received together.
+///
+/// The calls are aggregated as soon as both spans are received, so the graph can be taken
+/// periodically, while the spans are buffered until they expire.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceGraphBuilder {
+    /// Service names
+    services: BTreeSet<String>,
+    /// Services and start times of the spans, by trace and span IDs
+    spans: HashMap<(u128, u64), (String, i128)>,
+    /// Spans whose parent span is missing, by trace and parent span IDs
+    children: HashMap<(u128, u64), Vec<ChildSpan>>,
+    /// Calls (client, server) -> (errors, durations)
+    edges: BTreeMap<(String, String), (u64, Vec<i128>)>,
+}
+
+/// A span with a parent span
+#[derive(Debug, Clone)]
+struct ChildSpan {
+    /// Span ID
+    id: u64,
+    /// Service
+    service: String,
+    /// Start time
+    start: i128,
+    /// Duration
+    duration: i128,
+    /// Is it an error
+    error: bool,
+}
+
+impl ServiceGraphBuilder {
+    /// Creates a new builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds spans
+    pub fn add_traces(&mut self, data: &TraceData) {
+        for group in &data.spans {
+            let service = &group.service.name;
+            self.services.insert(service.clone());
+            for span in &group.spans {
+                self.spans
+                    .insert((span.trace_id, span.id), (service.clone(), span.start));
+
+                // NB: a child span may be received before its parent span
+                if let Some(children) = self.children.remove(&(span.trace_id, span.id)) {
+                    for child in children {
+                        add_call(&mut self.edges, service, child);
+                    }
+                }
+                let Some(parent_id) = span.parent_id else {
+                    continue;
+                };
+                let child = ChildSpan {
+                    id: span.id,
+                    service: service.clone(),
+                    start: span.start,
+                    duration: span.end - span.start,
+                    error: span.status.is_error(),
+                };
+                match self.spans.get(&(span.trace_id, parent_id)) {
+                    Some((client, _)) => add_call(&mut self.edges, client, child),
+                    None => self
+                        .children
+                        .entry((span.trace_id, parent_id))
+                        .or_default()
+                        .push(child),
+                }
+            }
+        }
+    }
+
+    /// Returns the number of buffered spans
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Checks if there are no buffered spans
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the buffered spans which started before a timestamp (UNIX nanoseconds)
+    ///
+    /// The calls of these spans which are already aggregated are kept.
+    pub fn expire_before(&mut self, timestamp: i128) {
+        self.spans.retain(|_, (_, start)| *start >= timestamp);
+        self.children.retain(|_, children| {
+            children.retain(|c| c.start >= timestamp);
+            !children.is_empty()
+        });
+    }
+
+    /// Drops the oldest buffered spans, to keep at most `max_spans` spans
+    ///
+    /// The calls of these spans which are already aggregated are kept.
+    pub fn truncate(&mut self, max_spans: usize) {
+        let n = self.spans.len().saturating_sub(max_spans);
+        if n == 0 {
+            return;
+        }
+        let mut spans = self
+            .spans
+            .iter()
+            .map(|(key, (_, start))| (*start, *key))
+            .collect::<Vec<_>>();
+        spans.sort_unstable();
+        let dropped = spans[..n]
+            .iter()
+            .map(|(_, key)| *key)
+            .collect::<HashSet<_>>();
+        self.spans.retain(|key, _| !dropped.contains(key));
+        self.children.retain(|(trace_id, _), children| {
+            children.retain(|c| !dropped.contains(&(*trace_id, c.id)));
+            !children.is_empty()
+        });
+    }
+
+    /// Returns the service graph of the calls aggregated so far, and resets the calls
+    ///
+    /// The buffered spans are kept, so the calls whose spans are received later are not lost.
+    pub fn take(&mut self) -> ServiceGraph {
+        let edges = std::mem::take(&mut self.edges);
+        ServiceGraph {
+            edges: edges
+                .into_iter()
+                .map(|((client, server), (errors, durations))| ServiceEdge {
+                    client,
+                    server,
+                    requests: durations.len() as u64,
+                    errors,
+                    duration: durations.iter().sum(),
+                    latency: LatencyPercentiles::from_durations(durations),
+                })
+                .collect(),
+            services: std::mem::take(&mut self.services).into_iter().collect(),
+        }
+    }
+
+    /// Builds the service graph
+    ///
+    /// The spans whose parent span is missing are ignored.
+    pub fn build(mut self) -> ServiceGraph {
+        self.take()
+    }
+}
+
+/// Aggregates a call from a client service
+fn add_call(
+    edges: &mut BTreeMap<(String, String), (u64, Vec<i128>)>,
+    client: &str,
+    child: ChildSpan,
+) {
+    if client == child.service {
+        return;
+    }
+    let (errors, durations) = edges
+        .entry((client.to_string(), child.service))
+        .or_default();
+    durations.push(child.duration);
+    if child.error {
+        *errors += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::data::{Service, Span, SpanStatus, StatusCode};
+
+    use super::*;
+
+    /// Returns a span
+    fn span(id: u64, parent_id: Option<u64>, duration: i128, error: bool) -> Span {
+        Span {
+            id,
+            parent_id,
+            trace_id: 1,
+            name: "span".to_string(),
+            start: 0,
+            end: duration,
+            status: SpanStatus {
+                code: if error {
+                    StatusCode::Error
+                } else {
+                    StatusCode::Unset
+                },
+                message: String::new(),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Returns spans of a service
+    fn traces(service: &str, spans: Vec<Span>) -> TraceData {
+        let service = Service {
+            name: service.to_string(),
+            attrs: HashMap::new(),
+        };
+        let mut data = TraceData::default();
+        for span in spans {
+            data.add_span(&service, None, span);
+        }
+        data
+    }
+
+    #[test]
+    fn service_graph() {
+        let mut builder = ServiceGraphBuilder::new();
+        builder.add_traces(&traces(
+            "web",
+            vec![span(1, None, 100, false), span(2, Some(1), 90, false)],
+        ));
+        // NB: the called spans are received in another batch
+        builder.add_traces(&traces(
+            "api",
+            vec![
+                span(3, Some(2), 10, false),
+                span(4, Some(2), 20, true),
+                span(5, Some(2), 30, false),
+                span(6, Some(5), 5, false),
+                span(7, Some(99), 5, false),
+            ],
+        ));
+        builder.add_traces(&traces("db", vec![span(8, Some(6), 1, false)]));
+
+        let graph = builder.build();
+        assert_eq!(graph.services, vec!["api", "db", "web"]);
+        assert_eq!(
+            graph.edges,
+            vec![
+                ServiceEdge {
+                    client: "api".to_string(),
+                    server: "db".to_string(),
+                    requests: 1,
+                    errors: 0,
+                    duration: 1,
+                    latency: LatencyPercentiles {
+                        p50: 1,
+                        p90: 1,
+                        p99: 1
+                    },
+                },
+                ServiceEdge {
+                    client: "web".to_string(),
+                    server: "api".to_string(),
+                    requests: 3,
+                    errors: 1,
+                    duration: 60,
+                    latency: LatencyPercentiles {
+                        p50: 20,
+                        p90: 30,
+                        p99: 30
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn service_graph_percentiles() {
+        let latency = LatencyPercentiles::from_durations((1..=100).rev().collect());
+        assert_eq!(
+            latency,
+            LatencyPercentiles {
+                p50: 50,
+                p90: 90,
+                p99: 99
+            }
+        );
+        assert_eq!(
+            LatencyPercentiles::from_durations(vec![]),
+            LatencyPercentiles::default()
+        );
+    }
+
+    #[test]
+    fn service_graph_take() {
+        let mut builder = ServiceGraphBuilder::new();
+        builder.add_traces(&traces("api", vec![span(2, Some(1), 10, false)]));
+        assert!(builder.take().edges.is_empty());
+
+        // NB: the parent span is received after the graph was taken
+        builder.add_traces(&traces("web", vec![span(1, None, 100, false)]));
+        let graph = builder.take();
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].requests, 1);
+        assert!(builder.take().edges.is_empty());
+
+        assert_eq!(builder.len(), 2);
+        builder.expire_before(1);
+        assert!(builder.is_empty());
+    }
+
+    #[test]
+    fn service_graph_truncate() {
+        let mut builder = ServiceGraphBuilder::new();
+        let mut child = span(2, Some(1), 10, false);
+        child.start = 1;
+        let mut other = span(3, Some(1), 10, false);
+        other.start = 2;
+        builder.add_traces(&traces("api", vec![child, other]));
+        assert_eq!(builder.len(), 2);
+
+        // NB: only the oldest span is dropped, the other one still waits for its parent
+        builder.truncate(1);
+        assert_eq!(builder.len(), 1);
+        builder.add_traces(&traces("web", vec![span(1, None, 100, false)]));
+        let graph = builder.take();
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].requests, 1);
+    }
+
+    #[test]
+    fn service_graph_metrics() {
+        let mut builder = ServiceGraphBuilder::new();
+        builder.add_traces(&traces(
+            "web",
+            vec![span(1, None, 100, false), span(2, Some(1), 90, false)],
+        ));
+        builder.add_traces(&traces(
+            "api",
+            vec![span(3, Some(2), 10, false), span(4, Some(2), 30, true)],
+        ));
+        builder.add_traces(&traces("db", vec![span(5, Some(3), 1, false)]));
+        let graph = builder.build();
+
+        let metrics = graph.to_metrics(0, 1);
+        assert_eq!(ServiceGraph::from_metrics(&metrics), graph);
+
+        // NB: the intervals are merged
+        let mut merged = metrics.clone();
+        merged.metrics.extend(metrics.metrics);
+        let merged = ServiceGraph::from_metrics(&merged);
+        assert_eq!(merged.edges[1].requests, 4);
+        assert_eq!(merged.edges[1].errors, 2);
+        assert_eq!(merged.edges[1].duration, 80);
+        assert_eq!(merged.edges[1].latency, graph.edges[1].latency);
+
+        let graph = graph.service("db");
+        assert_eq!(graph.services, vec!["api", "db"]);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].server, "db");
+    }
+}
@@ -7,12 +7,40 @@ use serde::{Deserialize, Serialize};
 use super::{AttrValue, Scope, Service};
 
 /// A set of log data
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct LogData {
     /// Logs
     pub logs: Vec<ServiceLogs>,
 }
 
+impl LogData {
+    /// Adds a log to the logs of a service and scope
+    pub fn add_log(&mut self, service: &Service, scope: Option<&Scope>, log: Log) {
+        match self
+            .logs
+            .iter_mut()
+            .find(|l| l.service == *service && l.scope.as_ref() == scope)
+        {
+            Some(service_logs) => service_logs.logs.push(log),
+            None => self.logs.push(ServiceLogs {
+                service: service.clone(),
+                scope: scope.cloned(),
+                logs: vec![log],
+            }),
+        }
+    }
+
+    /// Returns the number of logs
+    pub fn len(&self) -> usize {
+        self.logs.iter().map(|l| l.logs.len()).sum()
+    }
+
+    /// Checks if there are no logs
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// A collection of logs for a specific service and scope
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceLogs {
@@ -7,12 +7,40 @@ use serde::{Deserialize, Serialize};
 use super::{AttrValue, Scope, Service};
 
 /// A collection of metrics
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct MetricsData {
     /// Metrics
     pub metrics: Vec<ServiceMetrics>,
 }
 
+impl MetricsData {
+    /// Adds a metric to the metrics of a service and scope
+    pub fn add_metric(&mut self, service: &Service, scope: Option<&Scope>, metric: Metric) {
+        match self
+            .metrics
+            .iter_mut()
+            .find(|m| m.service == *service && m.scope.as_ref() == scope)
+        {
+            Some(service_metrics) => service_metrics.metrics.push(metric),
+            None => self.metrics.push(ServiceMetrics {
+                service: service.clone(),
+                scope: scope.cloned(),
+                metrics: vec![metric],
+            }),
+        }
+    }
+
+    /// Returns the number of metrics
+    pub fn len(&self) -> usize {
+        self.metrics.iter().map(|m| m.metrics.len()).sum()
+    }
+
+    /// Checks if there are no metrics
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// A collection of metrics
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceMetrics {
@@ -33,8 +61,243 @@ pub struct Metric {
     pub descr: String,
     /// Unit description
     pub unit: String,
+    /// Data
+    pub data: MetricData,
+}
+
+/// Metric data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MetricData {
+    /// Gauge (sampled value)
+    Gauge {
+        /// Data points
+        points: Vec<NumberDataPoint>,
+    },
+    /// Sum (aggregated value)
+    Sum {
+        /// Data points
+        points: Vec<NumberDataPoint>,
+        /// Aggregation temporality
+        temporality: AggregationTemporality,
+        /// Monotonic sum (ie a counter)
+        monotonic: bool,
+    },
+    /// Histogram with explicit buckets
+    Histogram {
+        /// Data points
+        points: Vec<HistogramDataPoint>,
+        /// Aggregation temporality
+        temporality: AggregationTemporality,
+    },
+    /// Exponential histogram
+    ExpHistogram {
+        /// Data points
+        points: Vec<ExpHistogramDataPoint>,
+        /// Aggregation temporality
+        temporality: AggregationTemporality,
+    },
+    /// Summary (quantiles)
+    Summary {
+        /// Data points
+        points: Vec<SummaryDataPoint>,
+    },
+}
+
+impl MetricData {
+    /// Returns the number of data points
+    pub fn len(&self) -> usize {
+        match self {
+            MetricData::Gauge { points } => points.len(),
+            MetricData::Sum { points, .. } => points.len(),
+            MetricData::Histogram { points, .. } => points.len(),
+            MetricData::ExpHistogram { points, .. } => points.len(),
+            MetricData::Summary { points } => points.len(),
+        }
+    }
+
+    /// Checks if there are no data points
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the latest timestamp of the data points
+    pub fn last_timestamp(&self) -> Option<i128> {
+        match self {
+            MetricData::Gauge { points } | MetricData::Sum { points, .. } => {
+                points.iter().map(|p| p.timestamp).max()
+            }
+            MetricData::Histogram { points, .. } => points.iter().map(|p| p.timestamp).max(),
+            MetricData::ExpHistogram { points, .. } => points.iter().map(|p| p.timestamp).max(),
+            MetricData::Summary { points } => points.iter().map(|p| p.timestamp).max(),
+        }
+    }
+}
+
+/// Aggregation temporality
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregationTemporality {
+    /// Unspecified
+    #[default]
+    Unspecified,
+    /// Delta (values since the previous report)
+    Delta,
+    /// Cumulative (values since the start time)
+    Cumulative,
+}
+
+/// A number value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NumberValue {
+    /// Integer value
+    Int(i64),
+    /// Floating point value
+    Float(f64),
+}
+
+impl NumberValue {
+    /// Returns the value as a float
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            NumberValue::Int(i) => *i as f64,
+            NumberValue::Float(x) => *x,
+        }
+    }
+}
+
+impl std::fmt::Display for NumberValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumberValue::Int(i) => write!(f, "{i}"),
+            NumberValue::Float(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// A data point for gauges and sums
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumberDataPoint {
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Start time (UNIX nanoseconds)
+    pub start: i128,
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
     /// Value
-    pub value: String,
+    pub value: NumberValue,
+    /// Exemplars
+    pub exemplars: Vec<Exemplar>,
+    /// Flags
+    pub flags: u32,
+}
+
+/// A data point for histograms with explicit buckets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramDataPoint {
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Start time (UNIX nanoseconds)
+    pub start: i128,
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
+    /// Number of observations
+    pub count: u64,
+    /// Sum of the observations
+    pub sum: Option<f64>,
+    /// Bucket counts (one more than the bounds)
+    pub bucket_counts: Vec<u64>,
+    /// Bucket upper bounds
+    pub bounds: Vec<f64>,
+    /// Minimum value
+    pub min: Option<f64>,
+    /// Maximum value
+    pub max: Option<f64>,
+    /// Exemplars
+    pub exemplars: Vec<Exemplar>,
+    /// Flags
+    pub flags: u32,
+}
+
+/// A data point for exponential histograms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpHistogramDataPoint {
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Start time (UNIX nanoseconds)
+    pub start: i128,
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
+    /// Number of observations
+    pub count: u64,
+    /// Sum of the observations
+    pub sum: Option<f64>,
+    /// Scale (resolution of the buckets)
+    pub scale: i32,
+    /// Number of observations in the zero bucket
+    pub zero_count: u64,
+    /// Width of the zero bucket
+    pub zero_threshold: f64,
+    /// Buckets for the positive values
+    pub positive: ExpHistogramBuckets,
+    /// Buckets for the negative values
+    pub negative: ExpHistogramBuckets,
+    /// Minimum value
+    pub min: Option<f64>,
+    /// Maximum value
+    pub max: Option<f64>,
+    /// Exemplars
+    pub exemplars: Vec<Exemplar>,
+    /// Flags
+    pub flags: u32,
+}
+
+/// Exponential histogram buckets
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExpHistogramBuckets {
+    /// Index of the first bucket
+    pub offset: i32,
+    /// Bucket counts
+    pub counts: Vec<u64>,
+}
+
+/// A data point for summaries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryDataPoint {
     /// Attributes
     pub attrs: HashMap<String, AttrValue>,
+    /// Start time (UNIX nanoseconds)
+    pub start: i128,
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
+    /// Number of observations
+    pub count: u64,
+    /// Sum of the observations
+    pub sum: f64,
+    /// Quantile values
+    pub quantiles: Vec<QuantileValue>,
+    /// Flags
+    pub flags: u32,
+}
+
+/// A value at a given quantile
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QuantileValue {
+    /// Quantile (between 0 and 1)
+    pub quantile: f64,
+    /// Value
+    pub value: f64,
+}
+
+/// An exemplar (sample measurement linked to a trace)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exemplar {
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
+    /// Value
+    pub value: NumberValue,
+    /// Trace ID (0 if none)
+    pub trace_id: u128,
+    /// Span ID (0 if none)
+    pub span_id: u64,
+    /// Attributes which were filtered out of the data point
+    pub attrs: HashMap<String, AttrValue>,
 }
@@ -1,6 +1,8 @@
 //! Data
 
 mod context;
+mod correlation;
+mod graph;
 mod log;
 mod metric;
 mod monitor;
@@ -10,6 +12,8 @@ mod trace;
 mod value;
 
 pub use context::*;
+pub use correlation::*;
+pub use graph::*;
 pub use log::*;
 pub use metric::*;
 pub use monitor::*;
@@ -11,6 +11,8 @@ use super::AttrValue;
 pub struct Scope {
     /// Name
     pub name: String,
+    /// Version
+    pub version: Option<String>,
     /// Attributes
     pub attrs: HashMap<String, AttrValue>,
 }
@@ -7,12 +7,40 @@ use serde::{Deserialize, Serialize};
 use super::{AttrValue, Scope, Service};
 
 /// A collection of trace data
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TraceData {
     /// Spans
     pub spans: Vec<ServiceSpans>,
 }
 
+impl TraceData {
+    /// Adds a span to the spans of a service and scope
+    pub fn add_span(&mut self, service: &Service, scope: Option<&Scope>, span: Span) {
+        match self
+            .spans
+            .iter_mut()
+            .find(|s| s.service == *service && s.scope.as_ref() == scope)
+        {
+            Some(service_spans) => service_spans.spans.push(span),
+            None => self.spans.push(ServiceSpans {
+                service: service.clone(),
+                scope: scope.cloned(),
+                spans: vec![span],
+            }),
+        }
+    }
+
+    /// Returns the number of spans
+    pub fn len(&self) -> usize {
+        self.spans.iter().map(|s| s.spans.len()).sum()
+    }
+
+    /// Checks if there are no spans
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// A collection of spans for a specific service and scope
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceSpans {
@@ -25,7 +53,7 @@ pub struct ServiceSpans {
 }
 
 /// A trace span
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Span {
     /// Span ID
     pub id: u64,
@@ -39,10 +67,24 @@ pub struct Span {
     pub start: i128,
     /// End time (UNIX nanoseconds)
     pub end: i128,
+    /// Span kind
+    pub kind: SpanKind,
+    /// Span status
+    pub status: SpanStatus,
+    /// Trace state (W3C `tracestate` header value)
+    pub trace_state: String,
     /// Span attributes
     pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
     /// Span events
     pub events: Vec<SpanEvent>,
+    /// Number of dropped events
+    pub dropped_events: u32,
+    /// Span links
+    pub links: Vec<SpanLink>,
+    /// Number of dropped links
+    pub dropped_links: u32,
     // service name + attrs
     // scope name + attrs
     // logs
@@ -57,4 +99,92 @@ pub struct SpanEvent {
     pub name: String,
     /// Attributes
     pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
+}
+
+/// A span kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SpanKind {
+    /// Unspecified
+    #[default]
+    Unspecified,
+    /// Internal operation
+    Internal,
+    /// Server side of a synchronous request
+    Server,
+    /// Client side of a synchronous request
+    Client,
+    /// Producer of an asynchronous request
+    Producer,
+    /// Consumer of an asynchronous request
+    Consumer,
+}
+
+impl SpanKind {
+    /// Returns the kind name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanKind::Unspecified => "unspecified",
+            SpanKind::Internal => "internal",
+            SpanKind::Server => "server",
+            SpanKind::Client => "client",
+            SpanKind::Producer => "producer",
+            SpanKind::Consumer => "consumer",
+        }
+    }
+}
+
+/// A span status
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpanStatus {
+    /// Status code
+    pub code: StatusCode,
+    /// Status message (for errors)
+    pub message: String,
+}
+
+impl SpanStatus {
+    /// Checks if the status is an error
+    pub fn is_error(&self) -> bool {
+        self.code == StatusCode::Error
+    }
+}
+
+/// A span status code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StatusCode {
+    /// Unset
+    #[default]
+    Unset,
+    /// Success
+    Ok,
+    /// Error
+    Error,
+}
+
+impl StatusCode {
+    /// Returns the code name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusCode::Unset => "unset",
+            StatusCode::Ok => "ok",
+            StatusCode::Error => "error",
+        }
+    }
+}
+
+/// A link to another span
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpanLink {
+    /// Trace ID of the linked span
+    pub trace_id: u128,
+    /// Span ID of the linked span
+    pub span_id: u64,
+    /// Trace state of the linked span
+    pub trace_state: String,
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
 }
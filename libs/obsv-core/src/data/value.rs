@@ -17,3 +17,40 @@ pub enum AttrValue {
     Array(Vec<AttrValue>),
     Map(HashMap<String, AttrValue>),
 }
+
+impl std::fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttrValue::None => write!(f, ""),
+            AttrValue::Bool(b) => write!(f, "{b}"),
+            AttrValue::Uint(u) => write!(f, "{u}"),
+            AttrValue::Int(i) => write!(f, "{i}"),
+            AttrValue::Float(x) => write!(f, "{x}"),
+            AttrValue::String(s) => write!(f, "{s}"),
+            AttrValue::Bytes(b) => write!(f, "{}", hex::encode(b)),
+            AttrValue::Array(values) => write!(
+                f,
+                "[{}]",
+                values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AttrValue::Map(map) => {
+                // NB: keys are sorted to get a stable output
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                write!(
+                    f,
+                    "{{{}}}",
+                    entries
+                        .iter()
+                        .map(|(k, v)| format!("{k}: {v}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+        }
+    }
+}
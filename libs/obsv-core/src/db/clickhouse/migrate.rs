@@ -0,0 +1,575 @@
+//! Clickhouse schema migrations
+
+use std::collections::BTreeMap;
+
+use clickhouse_client::{
+    orm::prelude::*,
+    query::Format,
+    schema::{ColumnSchema, TableSchema},
+    value::Type,
+};
+use time::OffsetDateTime;
+
+use crate::error::Error as DbError;
+
+use super::{sql_str, tables, ChClient, ChSchemaConfig, ChTable};
+
+/// A schema migration
+#[derive(Debug)]
+pub struct Migration {
+    /// Version
+    pub version: u32,
+    /// Description
+    pub descr: String,
+    /// Schema changes
+    pub changes: Vec<SchemaChange>,
+}
+
+/// A schema change
+///
+/// The changes never lose the existing data: a table is only dropped once its data is copied
+/// to a new table (eg. to change the partitioning, which cannot be altered).
+#[derive(Debug)]
+pub enum SchemaChange {
+    /// Creates a table
+    CreateTable(ChTable),
+    /// Adds a column to a table
+    AddColumn {
+        /// Table name
+        table: String,
+        /// Column
+        column: ColumnSchema,
+    },
+    /// Sets the TTL of a table
+    ModifyTtl {
+        /// Table name
+        table: String,
+        /// TTL expression
+        ttl: String,
+    },
+    /// Inserts the result of a query into a table
+    InsertSelect {
+        /// Table name
+        table: String,
+        /// Columns
+        columns: Vec<String>,
+        /// SELECT query (in the DB of the client)
+        select: String,
+    },
+    /// Drops a table
+    DropTable {
+        /// Table name
+        table: String,
+    },
+}
+
+impl Migration {
+    /// Creates a new migration
+    pub fn new(version: u32, descr: &str) -> Self {
+        Self {
+            version,
+            descr: descr.to_string(),
+            changes: vec![],
+        }
+    }
+
+    /// Creates a table
+    pub fn create_table(mut self, table: ChTable) -> Self {
+        self.changes.push(SchemaChange::CreateTable(table));
+        self
+    }
+
+    /// Adds a column to a table
+    pub fn add_column(mut self, table: &str, column: ColumnSchema) -> Self {
+        self.changes.push(SchemaChange::AddColumn {
+            table: table.to_string(),
+            column,
+        });
+        self
+    }
+
+    /// Sets the TTL of a table
+    pub fn modify_ttl(mut self, table: &str, ttl: &str) -> Self {
+        self.changes.push(SchemaChange::ModifyTtl {
+            table: table.to_string(),
+            ttl: ttl.to_string(),
+        });
+        self
+    }
+
+    /// Inserts the result of a query into a table
+    ///
+    /// NB: the query must skip the rows which were already inserted, to keep the migration idempotent.
+    pub fn insert_select(mut self, table: &str, columns: &[&str], select: &str) -> Self {
+        self.changes.push(SchemaChange::InsertSelect {
+            table: table.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            select: select.to_string(),
+        });
+        self
+    }
+
+    /// Drops a table
+    pub fn drop_table(mut self, table: &str) -> Self {
+        self.changes.push(SchemaChange::DropTable {
+            table: table.to_string(),
+        });
+        self
+    }
+
+    /// Returns the DDL statements of the migration
+    pub fn to_sql(&self, db: &str) -> Vec<String> {
+        self.changes.iter().map(|c| c.to_sql(db)).collect()
+    }
+}
+
+impl SchemaChange {
+    /// Returns the DDL statement of the change
+    ///
+    /// NB: statements are idempotent, so a partially applied migration can be applied again.
+    pub fn to_sql(&self, db: &str) -> String {
+        match self {
+            SchemaChange::CreateTable(table) => table.create_sql(db),
+            SchemaChange::AddColumn { table, column } => format!(
+                "ALTER TABLE {db}.{table} ADD COLUMN IF NOT EXISTS {} {}",
+                column.id, column.ty
+            ),
+            SchemaChange::ModifyTtl { table, ttl } => {
+                format!("ALTER TABLE {db}.{table} MODIFY TTL {ttl}")
+            }
+            SchemaChange::InsertSelect {
+                table,
+                columns,
+                select,
+            } => format!("INSERT INTO {db}.{table} ({}) {select}", columns.join(", ")),
+            SchemaChange::DropTable { table } => format!("DROP TABLE IF EXISTS {db}.{table}"),
+        }
+    }
+}
+
+/// Returns the schema migrations, ordered by version
+///
+/// The table configuration only applies to the tables created by the migrations.
+///
+/// NB: an applied migration must never change, so the migrations do not derive the columns from the records.
+/// A change of the records requires a new migration (see the `ch_migrations_schema` test).
+pub fn migrations(config: &ChSchemaConfig) -> Vec<Migration> {
+    vec![
+        v1_tables(config)
+            .into_iter()
+            .fold(Migration::new(1, "initial schema"), |migration, table| {
+                migration.create_table(table)
+            }),
+        v2_span_links(config),
+    ]
+}
+
+/// Returns the migration which adds the span start time to the span links (v2)
+///
+/// NB: the partitioning of a table cannot be altered, so the links are copied to a new table
+/// with the start time of their span (the links of the deleted spans are dropped).
+fn v2_span_links(config: &ChSchemaConfig) -> Migration {
+    let mut links = v1_tables(config)
+        .into_iter()
+        .find(|table| table.schema.name == "spans_links")
+        .unwrap();
+    links.schema.name = "spans_links_v2".to_string();
+    links
+        .schema
+        .columns
+        .push(ColumnSchema::new("start", Type::Int128, false));
+    let links = links.with_config("start", config.span_links());
+
+    let columns = [
+        "span_id",
+        "trace_id",
+        "link_trace_id",
+        "link_span_id",
+        "trace_state",
+        "attrs",
+        "dropped_attrs",
+        "start",
+    ];
+    let select = format!(
+        "SELECT {}, s.start FROM spans_links AS l \
+         INNER JOIN spans AS s ON l.trace_id = s.trace_id AND l.span_id = s.id \
+         WHERE (l.trace_id, l.span_id) NOT IN (SELECT trace_id, span_id FROM spans_links_v2)",
+        columns[..columns.len() - 1]
+            .iter()
+            .map(|c| format!("l.{c}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Migration::new(2, "span links start time")
+        .create_table(links)
+        .insert_select("spans_links_v2", &columns, &select)
+        .drop_table("spans_links")
+}
+
+/// Returns the tables of the initial schema (v1)
+fn v1_tables(config: &ChSchemaConfig) -> Vec<ChTable> {
+    let enum8 = |variants: &[&str]| {
+        Type::Enum8(
+            variants
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v.to_string(), i as i8))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    };
+    let attrs = || {
+        let kind = enum8(&[
+            "none", "bool", "uint", "int", "float", "string", "bytes", "array", "map",
+        ]);
+        Type::Map(
+            Box::new(Type::String),
+            Box::new(Type::Tuple(vec![
+                kind,
+                Type::Bool,
+                Type::UInt64,
+                Type::Int64,
+                Type::Float64,
+                Type::String,
+            ])),
+        )
+    };
+    let table = |name: &str, engine: &str, primary: &[&str], columns: Vec<(&str, Type)>| {
+        let mut schema = TableSchema::new(name);
+        schema.columns = columns
+            .into_iter()
+            .map(|(id, ty)| ColumnSchema::new(id, ty, primary.contains(&id)))
+            .collect();
+        ChTable::with_schema(schema, engine)
+    };
+    let service_cols = || {
+        vec![
+            ("service_attrs", attrs()),
+            ("scope", Type::String),
+            ("scope_version", Type::NullableString),
+            ("scope_attrs", attrs()),
+        ]
+    };
+
+    let mut span_cols = vec![
+        ("id", Type::UInt64),
+        ("trace_id", Type::UInt128),
+        ("parent_span_id", Type::NullableUInt64),
+        ("service", Type::String),
+    ];
+    span_cols.extend(service_cols());
+    span_cols.extend([
+        ("name", Type::String),
+        ("start", Type::Int128),
+        ("end", Type::Int128),
+        (
+            "kind",
+            enum8(&[
+                "unspecified",
+                "internal",
+                "server",
+                "client",
+                "producer",
+                "consumer",
+            ]),
+        ),
+        ("status_code", enum8(&["unset", "ok", "error"])),
+        ("status_message", Type::String),
+        ("trace_state", Type::String),
+        ("attrs", attrs()),
+        ("dropped_attrs", Type::UInt32),
+        ("dropped_events", Type::UInt32),
+        ("dropped_links", Type::UInt32),
+    ]);
+    let mut log_cols = vec![("service", Type::String), ("timestamp", Type::Int128)];
+    log_cols.extend(service_cols());
+    log_cols.extend([
+        ("trace_id", Type::UInt128),
+        ("span_id", Type::UInt64),
+        ("level", Type::Int16),
+        ("message", Type::String),
+        ("attrs", attrs()),
+    ]);
+    let mut metric_cols = vec![
+        ("name", Type::String),
+        ("timestamp", Type::Int128),
+        ("service", Type::String),
+    ];
+    metric_cols.extend(service_cols());
+    metric_cols.extend([
+        ("descr", Type::String),
+        ("unit", Type::String),
+        ("data", Type::String),
+    ]);
+
+    vec![
+        table("spans", "MergeTree", &["id"], span_cols).with_config("start", config.spans.clone()),
+        table(
+            "spans_events",
+            "MergeTree",
+            &["id"],
+            vec![
+                ("id", Type::UInt128),
+                ("trace_id", Type::UInt128),
+                ("span_id", Type::UInt64),
+                ("timestamp", Type::Int128),
+                ("name", Type::String),
+                ("attrs", attrs()),
+                ("dropped_attrs", Type::UInt32),
+            ],
+        )
+        .with_config("timestamp", config.span_events()),
+        ChTable {
+            config: config.span_links(),
+            ..table(
+                "spans_links",
+                "MergeTree",
+                &["span_id"],
+                vec![
+                    ("span_id", Type::UInt64),
+                    ("trace_id", Type::UInt128),
+                    ("link_trace_id", Type::UInt128),
+                    ("link_span_id", Type::UInt64),
+                    ("trace_state", Type::String),
+                    ("attrs", attrs()),
+                    ("dropped_attrs", Type::UInt32),
+                ],
+            )
+        },
+        table("logs", "MergeTree", &["service", "timestamp"], log_cols)
+            .with_config("timestamp", config.logs.clone()),
+        table("metrics", "MergeTree", &["name", "timestamp"], metric_cols)
+            .with_config("timestamp", config.metrics.clone()),
+        table(
+            "monitors",
+            "ReplacingMergeTree",
+            &["id"],
+            vec![
+                ("id", Type::UInt32),
+                ("name", Type::String),
+                ("description", Type::NullableString),
+                ("kind", Type::String),
+                ("frequency", Type::UInt64),
+            ],
+        ),
+        table(
+            "monitors_checks",
+            "MergeTree",
+            &["monitor_id", "timestamp"],
+            vec![
+                ("monitor_id", Type::UInt32),
+                ("timestamp", Type::Int128),
+                ("success", Type::Bool),
+                ("resp_time", Type::UInt64),
+                ("error", Type::String),
+            ],
+        )
+        .with_config("timestamp", config.checks.clone()),
+    ]
+}
+
+/// A schema version in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "schema_versions")]
+pub struct ChSchemaVersion {
+    /// Version
+    #[ch(primary_key)]
+    pub version: u32,
+    /// Description
+    pub descr: String,
+    /// Time of the migration (UNIX nanoseconds)
+    pub timestamp: i128,
+}
+
+impl ChClient {
+    /// Returns the current schema version (0 if the schema has not been created)
+    pub async fn schema_version(&self) -> Result<u32, DbError> {
+        let table = ChSchemaVersion::ch_schema().name;
+        // NB: the DB may not exist yet
+        let exists = self
+            .client
+            .query(&format!(
+                "SELECT name FROM system.tables WHERE database = {} AND name = {}",
+                sql_str(&self.db),
+                sql_str(&table)
+            ))
+            .db(None)
+            .format(Format::RowBinaryWithNamesAndTypes)
+            .exec()
+            .await?
+            .into_table(None)?
+            .n_rows()
+            > 0;
+        if !exists {
+            return Ok(0);
+        }
+        let versions = self
+            .select::<ChSchemaVersion>(&format!("SELECT * FROM {table}"))
+            .await?;
+        Ok(versions.iter().map(|v| v.version).max().unwrap_or(0))
+    }
+
+    /// Returns the DDL statements of the full schema, for the current configuration
+    pub fn db_schema(&self) -> Vec<String> {
+        let mut statements = vec![
+            format!("CREATE DATABASE IF NOT EXISTS {}", self.db),
+            ChTable::new::<ChSchemaVersion>("MergeTree").create_sql(&self.db),
+        ];
+        statements.extend(tables(&self.config).iter().map(|t| t.create_sql(&self.db)));
+        statements
+    }
+
+    /// Updates the TTL of the existing tables to the current configuration
+    ///
+    /// The DDL statements are returned, and only executed if `dry_run` is false.
+    ///
+    /// NB: Clickhouse applies the new TTL to the existing data, which can be expensive.
+    pub async fn update_ttls(&self, dry_run: bool) -> Result<Vec<String>, DbError> {
+        let statements = tables(&self.config)
+            .iter()
+            .filter_map(|table| {
+                table.ttl_sql().map(|ttl| {
+                    SchemaChange::ModifyTtl {
+                        table: table.schema.name.clone(),
+                        ttl,
+                    }
+                    .to_sql(&self.db)
+                })
+            })
+            .collect::<Vec<_>>();
+        if !dry_run {
+            for statement in &statements {
+                self.client.query(statement).exec().await?;
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Migrates the schema to the latest version
+    ///
+    /// The DDL statements are returned, and only executed if `dry_run` is false.
+    pub async fn migrate(&self, dry_run: bool) -> Result<Vec<String>, DbError> {
+        let version = self.schema_version().await?;
+        let mut statements = vec![
+            format!("CREATE DATABASE IF NOT EXISTS {}", self.db),
+            ChTable::new::<ChSchemaVersion>("MergeTree").create_sql(&self.db),
+        ];
+        if !dry_run {
+            for statement in &statements {
+                self.client.query(statement).db(None).exec().await?;
+            }
+        }
+
+        for migration in migrations(&self.config) {
+            if migration.version <= version {
+                continue;
+            }
+            let migration_statements = migration.to_sql(&self.db);
+            if !dry_run {
+                for statement in &migration_statements {
+                    self.client.query(statement).exec().await?;
+                }
+                self.client
+                    .orm::<ChSchemaVersion>()
+                    .insert(vec![ChSchemaVersion {
+                        version: migration.version,
+                        descr: migration.descr.clone(),
+                        timestamp: OffsetDateTime::now_utc().unix_timestamp_nanos(),
+                    }])
+                    .await?;
+            }
+            statements.extend(migration_statements);
+        }
+        Ok(statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ch_migrations_are_ordered() {
+        let versions = migrations(&ChSchemaConfig::default())
+            .iter()
+            .map(|m| m.version)
+            .collect::<Vec<_>>();
+        assert_eq!(versions[0], 1);
+        assert!(versions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn ch_migrations_schema() {
+        // NB: the migrations must produce the schema of the records
+        let config = ChSchemaConfig::default();
+        let mut schema = BTreeMap::new();
+        for migration in migrations(&config) {
+            for change in migration.changes {
+                match change {
+                    SchemaChange::CreateTable(table) => {
+                        schema.insert(table.schema.name.clone(), table.create_sql("obsv"));
+                    }
+                    SchemaChange::AddColumn { table, column } => {
+                        let sql = schema.get_mut(&table).unwrap();
+                        let i = sql.find(") ENGINE").unwrap();
+                        sql.insert_str(i, &format!(", {} {}", column.id, column.ty));
+                    }
+                    SchemaChange::DropTable { table } => {
+                        schema.remove(&table);
+                    }
+                    SchemaChange::ModifyTtl { .. } | SchemaChange::InsertSelect { .. } => {}
+                }
+            }
+        }
+        let expected = tables(&config)
+            .iter()
+            .map(|t| (t.schema.name.clone(), t.create_sql("obsv")))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(schema, expected);
+    }
+
+    #[test]
+    fn ch_span_links_migration_sql() {
+        let migration = v2_span_links(&ChSchemaConfig::default());
+        let statements = migration.to_sql("obsv");
+        assert!(statements[0]
+            .starts_with("CREATE TABLE IF NOT EXISTS obsv.spans_links_v2 (span_id UInt64, "));
+        assert!(statements[0].ends_with(
+            "dropped_attrs UInt32, start Int128) ENGINE = MergeTree \
+             PARTITION BY toYYYYMMDD(toDateTime(intDiv(start, 1000000000))) PRIMARY KEY (span_id)"
+        ));
+        assert_eq!(
+            statements[1..],
+            [
+                "INSERT INTO obsv.spans_links_v2 (span_id, trace_id, link_trace_id, link_span_id, \
+                 trace_state, attrs, dropped_attrs, start) \
+                 SELECT l.span_id, l.trace_id, l.link_trace_id, l.link_span_id, l.trace_state, \
+                 l.attrs, l.dropped_attrs, s.start FROM spans_links AS l \
+                 INNER JOIN spans AS s ON l.trace_id = s.trace_id AND l.span_id = s.id \
+                 WHERE (l.trace_id, l.span_id) NOT IN (SELECT trace_id, span_id FROM spans_links_v2)",
+                "DROP TABLE IF EXISTS obsv.spans_links",
+            ]
+        );
+    }
+
+    #[test]
+    fn ch_migration_sql() {
+        let migration = Migration::new(2, "test")
+            .create_table(ChTable::new::<ChSchemaVersion>("MergeTree"))
+            .add_column("spans", ColumnSchema::new("host", Type::String, false))
+            .modify_ttl(
+                "logs",
+                "toDateTime(intDiv(timestamp, 1000000000)) + INTERVAL 7 DAY",
+            );
+        assert_eq!(
+            migration.to_sql("obsv"),
+            vec![
+                "CREATE TABLE IF NOT EXISTS obsv.schema_versions \
+                 (version UInt32, descr String, timestamp Int128) \
+                 ENGINE = MergeTree PRIMARY KEY (version)",
+                "ALTER TABLE obsv.spans ADD COLUMN IF NOT EXISTS host String",
+                "ALTER TABLE obsv.logs MODIFY TTL \
+                 toDateTime(intDiv(timestamp, 1000000000)) + INTERVAL 7 DAY",
+            ]
+        );
+    }
+}
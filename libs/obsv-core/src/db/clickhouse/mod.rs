@@ -0,0 +1,1341 @@
+//! Clickhouse DB client
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use clickhouse_client::{orm::prelude::*, query::Format, HttpClient};
+
+use crate::{
+    data::{
+        AttrValue, Log, LogData, Metric, MetricsData, Monitor, MonitorCheck, MonitorCheckStatus,
+        Scope, Service, Span, SpanEvent, SpanKind, SpanLink, SpanStatus, StatusCode, TraceData,
+    },
+    error::Error as DbError,
+};
+
+use super::{AttrFilter, AttrOp, DbClient, LogQuery, MetricQuery, SpanQuery};
+
+mod migrate;
+mod schema;
+
+pub use migrate::*;
+pub use schema::*;
+
+/// Clickhouse DB client
+#[derive(Debug)]
+pub struct ChClient {
+    /// Client
+    client: HttpClient,
+    /// DB name
+    db: String,
+    /// Schema configuration
+    config: ChSchemaConfig,
+    /// Are the TTLs of the existing tables updated on init
+    sync_ttls: bool,
+}
+
+impl ChClient {
+    /// Creates a new Clickhouse DB client
+    ///
+    /// The client must have a target DB.
+    pub fn new(client: HttpClient) -> Result<Self, DbError> {
+        let db = match &client.db {
+            Some(db) => db.clone(),
+            None => return Err(DbError::new("Clickhouse client does not have a DB")),
+        };
+        Ok(Self {
+            client,
+            db,
+            config: ChSchemaConfig::default(),
+            sync_ttls: false,
+        })
+    }
+
+    /// Sets the schema configuration
+    ///
+    /// NB: the configuration only applies to the tables created by the migrations,
+    /// except for the TTLs, which can be updated on init (see [ChClient::sync_ttls]).
+    pub fn config(mut self, config: ChSchemaConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Updates the TTLs of the existing tables to the configuration when the DB is initialized
+    ///
+    /// This is disabled by default, because Clickhouse applies the new TTLs to the existing data.
+    pub fn sync_ttls(mut self, sync: bool) -> Self {
+        self.sync_ttls = sync;
+        self
+    }
+
+    /// Selects records with a raw SQL query
+    async fn select<T: ChRecord>(&self, query: &str) -> Result<Vec<T>, DbError> {
+        let table = self
+            .client
+            .query(query)
+            .format(Format::RowBinaryWithNamesAndTypes)
+            .exec()
+            .await?
+            .into_table(None)?;
+        Ok(T::from_query_data(table)?)
+    }
+
+    /// Fetches the events and links of spans, and groups the spans by service and scope
+    async fn complete_spans(&self, spans: Vec<ChSpan>) -> Result<TraceData, DbError> {
+        let mut data = TraceData::default();
+        if spans.is_empty() {
+            return Ok(data);
+        }
+        // NB: the spans are queried in chunks, to bound the size of the queries
+        let mut events: HashMap<(u128, u64), Vec<ChSpanEvent>> = HashMap::new();
+        let mut links: HashMap<(u128, u64), Vec<ChSpanLink>> = HashMap::new();
+        for chunk in spans.chunks(SPAN_IDS_CHUNK_SIZE) {
+            let cond = span_ids_sql(chunk);
+            for event in self
+                .select::<ChSpanEvent>(&format!(
+                    "SELECT * FROM {} WHERE {cond} ORDER BY id",
+                    ChSpanEvent::ch_schema().name
+                ))
+                .await?
+            {
+                events
+                    .entry((event.trace_id, event.span_id))
+                    .or_default()
+                    .push(event);
+            }
+            for link in self
+                .select::<ChSpanLink>(&format!(
+                    "SELECT * FROM {} WHERE {cond}",
+                    ChSpanLink::ch_schema().name
+                ))
+                .await?
+            {
+                links
+                    .entry((link.trace_id, link.span_id))
+                    .or_default()
+                    .push(link);
+            }
+        }
+        for ch_span in spans {
+            let key = (ch_span.trace_id, ch_span.id);
+            let (service, scope, span) = ch_span.into_span(
+                events.remove(&key).unwrap_or_default(),
+                links.remove(&key).unwrap_or_default(),
+            );
+            data.add_span(&service, scope.as_ref(), span);
+        }
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl DbClient for ChClient {
+    async fn init(&self) -> Result<(), DbError> {
+        self.migrate(false).await?;
+        if self.sync_ttls {
+            self.update_ttls(false).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_traces(&self, data: TraceData) -> Result<(), DbError> {
+        let (spans, events, links) = span_records(data);
+        if !spans.is_empty() {
+            self.client.orm::<ChSpan>().insert(spans).await?;
+        }
+        if !events.is_empty() {
+            self.client.orm::<ChSpanEvent>().insert(events).await?;
+        }
+        if !links.is_empty() {
+            self.client.orm::<ChSpanLink>().insert(links).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_logs(&self, data: LogData) -> Result<(), DbError> {
+        let logs = data
+            .logs
+            .into_iter()
+            .flat_map(|service_logs| {
+                let service = service_logs.service;
+                let scope = service_logs.scope;
+                service_logs
+                    .logs
+                    .into_iter()
+                    .map(move |log| ChLog::new(&service, scope.as_ref(), log))
+            })
+            .collect::<Vec<_>>();
+        if !logs.is_empty() {
+            self.client.orm::<ChLog>().insert(logs).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_metrics(&self, data: MetricsData) -> Result<(), DbError> {
+        let mut metrics = vec![];
+        for service_metrics in data.metrics {
+            for metric in service_metrics.metrics {
+                metrics.push(ChMetric::new(
+                    &service_metrics.service,
+                    service_metrics.scope.as_ref(),
+                    metric,
+                )?);
+            }
+        }
+        if !metrics.is_empty() {
+            self.client.orm::<ChMetric>().insert(metrics).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_monitors(&self, monitors: Vec<Monitor>) -> Result<(), DbError> {
+        let monitors = monitors
+            .into_iter()
+            .map(ChMonitor::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        if !monitors.is_empty() {
+            self.client.orm::<ChMonitor>().insert(monitors).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_monitor_checks(&self, checks: Vec<MonitorCheck>) -> Result<(), DbError> {
+        let checks = checks
+            .into_iter()
+            .map(ChMonitorCheck::from)
+            .collect::<Vec<_>>();
+        if !checks.is_empty() {
+            self.client.orm::<ChMonitorCheck>().insert(checks).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_trace(&self, trace_id: u128) -> Result<TraceData, DbError> {
+        let spans = self
+            .select::<ChSpan>(&format!(
+                "SELECT * FROM {} WHERE trace_id = {trace_id} ORDER BY start",
+                ChSpan::ch_schema().name
+            ))
+            .await?;
+        self.complete_spans(spans).await
+    }
+
+    async fn search_spans(&self, query: &SpanQuery) -> Result<TraceData, DbError> {
+        let spans = self.select::<ChSpan>(&span_query_sql(query)?).await?;
+        self.complete_spans(spans).await
+    }
+
+    async fn search_logs(&self, query: &LogQuery) -> Result<LogData, DbError> {
+        let logs = self.select::<ChLog>(&log_query_sql(query)?).await?;
+        let mut data = LogData::default();
+        for ch_log in logs {
+            let (service, scope, log) = ch_log.into_log();
+            data.add_log(&service, scope.as_ref(), log);
+        }
+        Ok(data)
+    }
+
+    async fn search_metrics(&self, query: &MetricQuery) -> Result<MetricsData, DbError> {
+        let metrics = self.select::<ChMetric>(&metric_query_sql(query)).await?;
+        let mut data = MetricsData::default();
+        for ch_metric in metrics {
+            let (service, scope, metric) = ch_metric.into_metric()?;
+            data.add_metric(&service, scope.as_ref(), metric);
+        }
+        Ok(data)
+    }
+
+    async fn get_monitors(&self) -> Result<Vec<Monitor>, DbError> {
+        let monitors = self
+            .select::<ChMonitor>(&format!(
+                "SELECT * FROM {} FINAL ORDER BY id",
+                ChMonitor::ch_schema().name
+            ))
+            .await?;
+        monitors.into_iter().map(Monitor::try_from).collect()
+    }
+
+    async fn delete_before(&self, timestamp: i128) -> Result<(), DbError> {
+        // NB: events and links are deleted before their spans
+        for (table, cond) in [
+            (
+                ChSpanEvent::ch_schema().name,
+                format!("timestamp < {timestamp}"),
+            ),
+            (
+                ChSpanLink::ch_schema().name,
+                format!(
+                    "span_id IN (SELECT id FROM {} WHERE start < {timestamp})",
+                    ChSpan::ch_schema().name
+                ),
+            ),
+            (ChSpan::ch_schema().name, format!("start < {timestamp}")),
+            (ChLog::ch_schema().name, format!("timestamp < {timestamp}")),
+            (
+                ChMetric::ch_schema().name,
+                format!("timestamp < {timestamp}"),
+            ),
+            (
+                ChMonitorCheck::ch_schema().name,
+                format!("timestamp < {timestamp}"),
+            ),
+        ] {
+            self.client
+                .query(&format!("DELETE FROM {table} WHERE {cond}"))
+                .exec()
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts trace data to span, event and link records
+fn span_records(data: TraceData) -> (Vec<ChSpan>, Vec<ChSpanEvent>, Vec<ChSpanLink>) {
+    let (mut spans, mut events, mut links) = (vec![], vec![], vec![]);
+    for service_spans in data.spans {
+        for span in service_spans.spans {
+            let (span, span_events, span_links) =
+                ChSpan::new(&service_spans.service, service_spans.scope.as_ref(), span);
+            spans.push(span);
+            events.extend(span_events);
+            links.extend(span_links);
+        }
+    }
+    (spans, events, links)
+}
+
+/// Maximum number of spans per query of their events and links
+const SPAN_IDS_CHUNK_SIZE: usize = 1000;
+
+/// Returns the SQL condition which matches the events or links of spans
+fn span_ids_sql(spans: &[ChSpan]) -> String {
+    let ids = spans
+        .iter()
+        .map(|span| format!("({}, {})", span.trace_id, span.id))
+        .collect::<Vec<_>>();
+    format!("(trace_id, span_id) IN ({})", ids.join(", "))
+}
+
+/// Returns the SQL query for a span search
+fn span_query_sql(query: &SpanQuery) -> Result<String, DbError> {
+    let mut conds = vec![];
+    if let Some(service) = &query.service {
+        conds.push(format!("service = {}", sql_str(service)));
+    }
+    if let Some(name) = &query.name {
+        conds.push(format!("name = {}", sql_str(name)));
+    }
+    if let Some(from) = query.from {
+        conds.push(format!("start >= {from}"));
+    }
+    if let Some(to) = query.to {
+        conds.push(format!("start < {to}"));
+    }
+    for filter in &query.attrs {
+        conds.push(attr_filter_sql("attrs", filter)?);
+    }
+    Ok(select_sql(
+        &ChSpan::ch_schema().name,
+        conds,
+        "start DESC",
+        query.limit,
+    ))
+}
+
+/// Returns the SQL query for a log search
+fn log_query_sql(query: &LogQuery) -> Result<String, DbError> {
+    let mut conds = vec![];
+    if let Some(service) = &query.service {
+        conds.push(format!("service = {}", sql_str(service)));
+    }
+    if let Some(trace_id) = query.trace_id {
+        conds.push(format!("trace_id = {trace_id}"));
+    }
+    if let Some(span_id) = query.span_id {
+        conds.push(format!("span_id = {span_id}"));
+    }
+    if let Some(text) = &query.text {
+        conds.push(format!("position(message, {}) > 0", sql_str(text)));
+    }
+    if let Some(level) = query.min_level {
+        conds.push(format!("level >= {level}"));
+    }
+    if let Some(from) = query.from {
+        conds.push(format!("timestamp >= {from}"));
+    }
+    if let Some(to) = query.to {
+        conds.push(format!("timestamp < {to}"));
+    }
+    for filter in &query.attrs {
+        conds.push(attr_filter_sql("attrs", filter)?);
+    }
+    Ok(select_sql(
+        &ChLog::ch_schema().name,
+        conds,
+        "timestamp DESC",
+        query.limit,
+    ))
+}
+
+/// Returns the SQL query for a metric search
+fn metric_query_sql(query: &MetricQuery) -> String {
+    let mut conds = vec![];
+    if let Some(service) = &query.service {
+        conds.push(format!("service = {}", sql_str(service)));
+    }
+    if let Some(name) = &query.name {
+        conds.push(format!("name = {}", sql_str(name)));
+    }
+    if let Some(from) = query.from {
+        conds.push(format!("timestamp >= {from}"));
+    }
+    if let Some(to) = query.to {
+        conds.push(format!("timestamp < {to}"));
+    }
+    select_sql(
+        &ChMetric::ch_schema().name,
+        conds,
+        "timestamp DESC",
+        query.limit,
+    )
+}
+
+/// Returns a SELECT query
+fn select_sql(table: &str, conds: Vec<String>, order_by: &str, limit: Option<usize>) -> String {
+    let mut sql = format!("SELECT * FROM {table}");
+    if !conds.is_empty() {
+        sql.push_str(&format!(" WHERE {}", conds.join(" AND ")));
+    }
+    sql.push_str(&format!(" ORDER BY {order_by}"));
+    if let Some(limit) = limit {
+        sql.push_str(&format!(" LIMIT {limit}"));
+    }
+    sql
+}
+
+/// Returns the SQL condition for an attribute filter
+///
+/// The attribute value is compared with the tuple slot of the filter value kind
+/// (see the [ChValue] implementation for [AttrValue]).
+fn attr_filter_sql(col: &str, filter: &AttrFilter) -> Result<String, DbError> {
+    let exists = format!("mapContains({col}, {})", sql_str(&filter.key));
+    let attr = format!("{col}[{}]", sql_str(&filter.key));
+    filter.check()?;
+    if filter.op == AttrOp::Exists {
+        return Ok(exists);
+    }
+    let (kind, slot, value) = match &filter.value {
+        AttrValue::Bool(b) => ("bool", 2, b.into_sql()),
+        AttrValue::Uint(u) => ("uint", 3, u.to_string()),
+        AttrValue::Int(i) => ("int", 4, i.to_string()),
+        AttrValue::Float(x) => ("float", 5, x.to_string()),
+        AttrValue::String(s) => ("string", 6, sql_str(s)),
+        AttrValue::Bytes(b) => ("bytes", 6, sql_str(&hex::encode(b))),
+        _ => unreachable!("checked filter value"),
+    };
+    let cond = match filter.op {
+        AttrOp::Exists => unreachable!(),
+        AttrOp::Eq => format!("{attr}.{slot} = {value}"),
+        AttrOp::Ne => format!("{attr}.{slot} != {value}"),
+        AttrOp::Lt => format!("{attr}.{slot} < {value}"),
+        AttrOp::Le => format!("{attr}.{slot} <= {value}"),
+        AttrOp::Gt => format!("{attr}.{slot} > {value}"),
+        AttrOp::Ge => format!("{attr}.{slot} >= {value}"),
+        AttrOp::Contains => format!("position({attr}.{slot}, {value}) > 0"),
+    };
+    Ok(format!("({exists} AND {attr}.1 = '{kind}' AND {cond})"))
+}
+
+/// Returns an escaped SQL string literal
+fn sql_str(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A span in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "spans")]
+pub struct ChSpan {
+    /// Span ID
+    #[ch(primary_key)]
+    pub id: u64,
+    /// Trace ID
+    pub trace_id: u128,
+    /// Parent span ID
+    pub parent_span_id: Option<u64>,
+    /// Service
+    pub service: String,
+    /// Service attributes
+    pub service_attrs: HashMap<String, AttrValue>,
+    /// Scope
+    pub scope: String,
+    /// Scope version
+    pub scope_version: Option<String>,
+    /// Scope attributes
+    pub scope_attrs: HashMap<String, AttrValue>,
+    /// Span name
+    pub name: String,
+    /// Start time (UNIX nanoseconds)
+    pub start: i128,
+    /// End time (UNIX nanoseconds)
+    pub end: i128,
+    /// Span kind
+    pub kind: SpanKind,
+    /// Status code
+    pub status_code: StatusCode,
+    /// Status message
+    pub status_message: String,
+    /// Trace state
+    pub trace_state: String,
+    /// Span attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
+    /// Number of dropped events
+    pub dropped_events: u32,
+    /// Number of dropped links
+    pub dropped_links: u32,
+}
+
+impl ChSpan {
+    /// Creates the span records (span, events and links)
+    pub fn new(
+        service: &Service,
+        scope: Option<&Scope>,
+        span: Span,
+    ) -> (Self, Vec<ChSpanEvent>, Vec<ChSpanLink>) {
+        let events = span
+            .events
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| ChSpanEvent {
+                id: (u128::from(span.id) << 64) | i as u128,
+                trace_id: span.trace_id,
+                span_id: span.id,
+                timestamp: event.timestamp,
+                name: event.name,
+                attrs: event.attrs,
+                dropped_attrs: event.dropped_attrs,
+            })
+            .collect();
+        let links = span
+            .links
+            .into_iter()
+            .map(|link| ChSpanLink {
+                span_id: span.id,
+                trace_id: span.trace_id,
+                link_trace_id: link.trace_id,
+                link_span_id: link.span_id,
+                trace_state: link.trace_state,
+                attrs: link.attrs,
+                dropped_attrs: link.dropped_attrs,
+                start: span.start,
+            })
+            .collect();
+        let (scope_name, scope_version, scope_attrs) = scope_columns(scope);
+        let ch_span = ChSpan {
+            id: span.id,
+            trace_id: span.trace_id,
+            parent_span_id: span.parent_id,
+            service: service.name.clone(),
+            service_attrs: service.attrs.clone(),
+            scope: scope_name,
+            scope_version,
+            scope_attrs,
+            name: span.name,
+            start: span.start,
+            end: span.end,
+            kind: span.kind,
+            status_code: span.status.code,
+            status_message: span.status.message,
+            trace_state: span.trace_state,
+            attrs: span.attrs,
+            dropped_attrs: span.dropped_attrs,
+            dropped_events: span.dropped_events,
+            dropped_links: span.dropped_links,
+        };
+        (ch_span, events, links)
+    }
+
+    /// Converts to a span, with its service and scope
+    pub fn into_span(
+        self,
+        events: Vec<ChSpanEvent>,
+        links: Vec<ChSpanLink>,
+    ) -> (Service, Option<Scope>, Span) {
+        let service = Service {
+            name: self.service,
+            attrs: self.service_attrs,
+        };
+        let scope = scope_from_columns(self.scope, self.scope_version, self.scope_attrs);
+        let span = Span {
+            id: self.id,
+            parent_id: self.parent_span_id,
+            trace_id: self.trace_id,
+            name: self.name,
+            start: self.start,
+            end: self.end,
+            kind: self.kind,
+            status: SpanStatus {
+                code: self.status_code,
+                message: self.status_message,
+            },
+            trace_state: self.trace_state,
+            attrs: self.attrs,
+            dropped_attrs: self.dropped_attrs,
+            events: events
+                .into_iter()
+                .map(|e| SpanEvent {
+                    timestamp: e.timestamp,
+                    name: e.name,
+                    attrs: e.attrs,
+                    dropped_attrs: e.dropped_attrs,
+                })
+                .collect(),
+            dropped_events: self.dropped_events,
+            links: links
+                .into_iter()
+                .map(|l| SpanLink {
+                    trace_id: l.link_trace_id,
+                    span_id: l.link_span_id,
+                    trace_state: l.trace_state,
+                    attrs: l.attrs,
+                    dropped_attrs: l.dropped_attrs,
+                })
+                .collect(),
+            dropped_links: self.dropped_links,
+        };
+        (service, scope, span)
+    }
+}
+
+/// A span event in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "spans_events")]
+pub struct ChSpanEvent {
+    /// Event ID (span ID and event index)
+    #[ch(primary_key)]
+    pub id: u128,
+    /// Trace ID
+    pub trace_id: u128,
+    /// Span ID
+    pub span_id: u64,
+    /// Timestamp (UNIX nanoseconds)
+    pub timestamp: i128,
+    /// Name
+    pub name: String,
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
+}
+
+/// A span link in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "spans_links_v2")]
+pub struct ChSpanLink {
+    /// Span ID
+    #[ch(primary_key)]
+    pub span_id: u64,
+    /// Trace ID
+    pub trace_id: u128,
+    /// Trace ID of the linked span
+    pub link_trace_id: u128,
+    /// Span ID of the linked span
+    pub link_span_id: u64,
+    /// Trace state of the linked span
+    pub trace_state: String,
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+    /// Number of dropped attributes
+    pub dropped_attrs: u32,
+    /// Start time of the span (UNIX nanoseconds)
+    pub start: i128,
+}
+
+/// A log in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "logs")]
+pub struct ChLog {
+    /// Service
+    #[ch(primary_key)]
+    pub service: String,
+    /// Timestamp (UNIX nanoseconds)
+    #[ch(primary_key)]
+    pub timestamp: i128,
+    /// Service attributes
+    pub service_attrs: HashMap<String, AttrValue>,
+    /// Scope
+    pub scope: String,
+    /// Scope version
+    pub scope_version: Option<String>,
+    /// Scope attributes
+    pub scope_attrs: HashMap<String, AttrValue>,
+    /// Trace ID
+    pub trace_id: u128,
+    /// Span ID
+    pub span_id: u64,
+    /// Level (severity)
+    pub level: i16,
+    /// Message
+    pub message: String,
+    /// Attributes
+    pub attrs: HashMap<String, AttrValue>,
+}
+
+impl ChLog {
+    /// Creates a log record
+    pub fn new(service: &Service, scope: Option<&Scope>, log: Log) -> Self {
+        let (scope_name, scope_version, scope_attrs) = scope_columns(scope);
+        Self {
+            service: service.name.clone(),
+            timestamp: log.timestamp,
+            service_attrs: service.attrs.clone(),
+            scope: scope_name,
+            scope_version,
+            scope_attrs,
+            trace_id: log.trace_id,
+            span_id: log.span_id,
+            level: log.level,
+            message: log.message,
+            attrs: log.attrs,
+        }
+    }
+
+    /// Converts to a log, with its service and scope
+    pub fn into_log(self) -> (Service, Option<Scope>, Log) {
+        let service = Service {
+            name: self.service,
+            attrs: self.service_attrs,
+        };
+        let scope = scope_from_columns(self.scope, self.scope_version, self.scope_attrs);
+        let log = Log {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            timestamp: self.timestamp,
+            level: self.level,
+            message: self.message,
+            attrs: self.attrs,
+        };
+        (service, scope, log)
+    }
+}
+
+/// A metric in Clickhouse DB
+///
+/// The metric data is stored as JSON.
+#[derive(Debug, AsChRecord)]
+#[ch(table = "metrics")]
+pub struct ChMetric {
+    /// Name
+    #[ch(primary_key)]
+    pub name: String,
+    /// Timestamp of the last data point (UNIX nanoseconds)
+    #[ch(primary_key)]
+    pub timestamp: i128,
+    /// Service
+    pub service: String,
+    /// Service attributes
+    pub service_attrs: HashMap<String, AttrValue>,
+    /// Scope
+    pub scope: String,
+    /// Scope version
+    pub scope_version: Option<String>,
+    /// Scope attributes
+    pub scope_attrs: HashMap<String, AttrValue>,
+    /// Description
+    pub descr: String,
+    /// Unit
+    pub unit: String,
+    /// Data (JSON)
+    pub data: String,
+}
+
+impl ChMetric {
+    /// Creates a metric record
+    pub fn new(service: &Service, scope: Option<&Scope>, metric: Metric) -> Result<Self, DbError> {
+        let timestamp = metric.data.last_timestamp().unwrap_or_default();
+        let data = serde_json::to_string(&metric.data)
+            .map_err(|err| DbError::string(format!("invalid metric data: {err}")))?;
+        let (scope_name, scope_version, scope_attrs) = scope_columns(scope);
+        Ok(Self {
+            name: metric.name,
+            timestamp,
+            service: service.name.clone(),
+            service_attrs: service.attrs.clone(),
+            scope: scope_name,
+            scope_version,
+            scope_attrs,
+            descr: metric.descr,
+            unit: metric.unit,
+            data,
+        })
+    }
+
+    /// Converts to a metric, with its service and scope
+    pub fn into_metric(self) -> Result<(Service, Option<Scope>, Metric), DbError> {
+        let data = serde_json::from_str(&self.data)
+            .map_err(|err| DbError::string(format!("invalid metric data: {err}")))?;
+        let service = Service {
+            name: self.service,
+            attrs: self.service_attrs,
+        };
+        let scope = scope_from_columns(self.scope, self.scope_version, self.scope_attrs);
+        let metric = Metric {
+            name: self.name,
+            descr: self.descr,
+            unit: self.unit,
+            data,
+        };
+        Ok((service, scope, metric))
+    }
+}
+
+/// A monitor in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "monitors")]
+pub struct ChMonitor {
+    /// ID
+    #[ch(primary_key)]
+    pub id: u32,
+    /// Name
+    pub name: String,
+    /// Description
+    pub description: Option<String>,
+    /// Kind (JSON)
+    pub kind: String,
+    /// Frequency (milliseconds)
+    pub frequency: u64,
+}
+
+impl TryFrom<Monitor> for ChMonitor {
+    type Error = DbError;
+
+    fn try_from(value: Monitor) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            name: value.name,
+            description: value.description,
+            kind: serde_json::to_string(&value.kind)
+                .map_err(|err| DbError::string(format!("invalid monitor kind: {err}")))?,
+            frequency: value.frequency.as_millis() as u64,
+        })
+    }
+}
+
+impl TryFrom<ChMonitor> for Monitor {
+    type Error = DbError;
+
+    fn try_from(value: ChMonitor) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            name: value.name,
+            description: value.description,
+            kind: serde_json::from_str(&value.kind)
+                .map_err(|err| DbError::string(format!("invalid monitor kind: {err}")))?,
+            frequency: Duration::from_millis(value.frequency),
+        })
+    }
+}
+
+/// A monitor check in Clickhouse DB
+#[derive(Debug, AsChRecord)]
+#[ch(table = "monitors_checks")]
+pub struct ChMonitorCheck {
+    /// Monitor ID
+    #[ch(primary_key)]
+    pub monitor_id: u32,
+    /// Timestamp (UNIX nanoseconds)
+    #[ch(primary_key)]
+    pub timestamp: i128,
+    /// Success
+    pub success: bool,
+    /// Response time (nanoseconds)
+    pub resp_time: u64,
+    /// Error message
+    pub error: String,
+}
+
+impl From<MonitorCheck> for ChMonitorCheck {
+    fn from(value: MonitorCheck) -> Self {
+        let (success, resp_time, error) = match value.status {
+            MonitorCheckStatus::Success { resp_time } => (true, resp_time, String::new()),
+            MonitorCheckStatus::Failure { resp_time, error } => (false, resp_time, error),
+        };
+        Self {
+            monitor_id: value.monitor_id,
+            timestamp: value.timestamp,
+            success,
+            resp_time: resp_time.as_nanos() as u64,
+            error,
+        }
+    }
+}
+
+/// Returns the columns of a scope (name, version, attributes)
+fn scope_columns(scope: Option<&Scope>) -> (String, Option<String>, HashMap<String, AttrValue>) {
+    match scope {
+        Some(scope) => (
+            scope.name.clone(),
+            scope.version.clone(),
+            scope.attrs.clone(),
+        ),
+        None => (String::new(), None, HashMap::new()),
+    }
+}
+
+/// Returns a scope from its columns
+///
+/// An empty scope (no name, version or attributes) is considered as missing.
+fn scope_from_columns(
+    name: String,
+    version: Option<String>,
+    attrs: HashMap<String, AttrValue>,
+) -> Option<Scope> {
+    if name.is_empty() && version.is_none() && attrs.is_empty() {
+        None
+    } else {
+        Some(Scope {
+            name,
+            version,
+            attrs,
+        })
+    }
+}
+
+impl ChValue for SpanKind {
+    fn ch_type() -> Type {
+        Type::Enum8(BTreeMap::from([
+            ("unspecified".to_string(), 0),
+            ("internal".to_string(), 1),
+            ("server".to_string(), 2),
+            ("client".to_string(), 3),
+            ("producer".to_string(), 4),
+            ("consumer".to_string(), 5),
+        ]))
+    }
+
+    fn into_ch_value(self) -> Value {
+        Value::Enum8(match self {
+            SpanKind::Unspecified => 0,
+            SpanKind::Internal => 1,
+            SpanKind::Server => 2,
+            SpanKind::Client => 3,
+            SpanKind::Producer => 4,
+            SpanKind::Consumer => 5,
+        })
+    }
+
+    fn from_ch_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Enum8(0) => Ok(SpanKind::Unspecified),
+            Value::Enum8(1) => Ok(SpanKind::Internal),
+            Value::Enum8(2) => Ok(SpanKind::Server),
+            Value::Enum8(3) => Ok(SpanKind::Client),
+            Value::Enum8(4) => Ok(SpanKind::Producer),
+            Value::Enum8(5) => Ok(SpanKind::Consumer),
+            _ => Err(Error::new("invalid span kind")),
+        }
+    }
+}
+
+impl ChValue for StatusCode {
+    fn ch_type() -> Type {
+        Type::Enum8(BTreeMap::from([
+            ("unset".to_string(), 0),
+            ("ok".to_string(), 1),
+            ("error".to_string(), 2),
+        ]))
+    }
+
+    fn into_ch_value(self) -> Value {
+        Value::Enum8(match self {
+            StatusCode::Unset => 0,
+            StatusCode::Ok => 1,
+            StatusCode::Error => 2,
+        })
+    }
+
+    fn from_ch_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Enum8(0) => Ok(StatusCode::Unset),
+            Value::Enum8(1) => Ok(StatusCode::Ok),
+            Value::Enum8(2) => Ok(StatusCode::Error),
+            _ => Err(Error::new("invalid status code")),
+        }
+    }
+}
+
+/// Kinds of attribute values (Enum8)
+const ATTR_KINDS: [&str; 9] = [
+    "none", "bool", "uint", "int", "float", "string", "bytes", "array", "map",
+];
+
+/// An attribute value is stored as a tuple `(kind, bool, uint, int, float, string)`
+///
+/// Only the slot matching the kind is set, the others have their default value,
+/// so that attributes can be filtered in SQL (eg. `attrs['http.status_code'].4 = 200`).
+/// Bytes are stored as a hex string, arrays and maps are stored as JSON.
+impl ChValue for AttrValue {
+    fn ch_type() -> Type {
+        Type::Tuple(vec![
+            Type::Enum8(
+                ATTR_KINDS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, kind)| (kind.to_string(), i as i8))
+                    .collect(),
+            ),
+            Type::Bool,
+            Type::UInt64,
+            Type::Int64,
+            Type::Float64,
+            Type::String,
+        ])
+    }
+
+    fn into_ch_value(self) -> Value {
+        let (mut b, mut u, mut i, mut x, mut s) = (false, 0, 0, 0.0, String::new());
+        let kind = match self {
+            AttrValue::None => 0,
+            AttrValue::Bool(v) => {
+                b = v;
+                1
+            }
+            AttrValue::Uint(v) => {
+                u = v;
+                2
+            }
+            AttrValue::Int(v) => {
+                i = v;
+                3
+            }
+            AttrValue::Float(v) => {
+                x = v;
+                4
+            }
+            AttrValue::String(v) => {
+                s = v;
+                5
+            }
+            AttrValue::Bytes(v) => {
+                s = hex::encode(v);
+                6
+            }
+            AttrValue::Array(_) => {
+                s = attr_to_json(&self).to_string();
+                7
+            }
+            AttrValue::Map(_) => {
+                s = attr_to_json(&self).to_string();
+                8
+            }
+        };
+        Value::Tuple(vec![
+            Value::Enum8(kind),
+            Value::Bool(b),
+            Value::UInt64(u),
+            Value::Int64(i),
+            Value::Float64(x),
+            Value::String(s),
+        ])
+    }
+
+    fn from_ch_value(value: Value) -> Result<Self, Error> {
+        let values = match value {
+            Value::Tuple(values) if values.len() == 6 => values,
+            _ => return Err(Error::new("invalid attribute value: expected a 6-tuple")),
+        };
+        let mut values = values.into_iter();
+        let kind = match values.next() {
+            Some(Value::Enum8(kind)) => kind,
+            _ => return Err(Error::new("invalid attribute value kind")),
+        };
+        let b = bool::from_ch_value(values.next().unwrap())?;
+        let u = u64::from_ch_value(values.next().unwrap())?;
+        let i = i64::from_ch_value(values.next().unwrap())?;
+        let x = f64::from_ch_value(values.next().unwrap())?;
+        let s = String::from_ch_value(values.next().unwrap())?;
+        match kind {
+            0 => Ok(AttrValue::None),
+            1 => Ok(AttrValue::Bool(b)),
+            2 => Ok(AttrValue::Uint(u)),
+            3 => Ok(AttrValue::Int(i)),
+            4 => Ok(AttrValue::Float(x)),
+            5 => Ok(AttrValue::String(s)),
+            6 => hex::decode(s)
+                .map(AttrValue::Bytes)
+                .map_err(|err| Error::new(&format!("invalid attribute bytes: {err}"))),
+            7 | 8 => serde_json::from_str(&s)
+                .map_err(|err| Error::new(&format!("invalid attribute JSON: {err}")))
+                .and_then(attr_from_json),
+            _ => Err(Error::new(&format!("invalid attribute value kind: {kind}"))),
+        }
+    }
+}
+
+/// Converts an attribute value to JSON
+///
+/// NB: JSON has no NaN or infinite numbers, so the non-finite floats are encoded as strings
+fn attr_to_json(value: &AttrValue) -> serde_json::Value {
+    match value {
+        AttrValue::Float(x) if !x.is_finite() => serde_json::json!({ "Float": x.to_string() }),
+        AttrValue::Array(values) => {
+            serde_json::json!({ "Array": values.iter().map(attr_to_json).collect::<Vec<_>>() })
+        }
+        AttrValue::Map(values) => serde_json::json!({
+            "Map": values
+                .iter()
+                .map(|(k, v)| (k.clone(), attr_to_json(v)))
+                .collect::<serde_json::Map<_, _>>()
+        }),
+        value => serde_json::to_value(value).expect("attribute value is serializable"),
+    }
+}
+
+/// Converts JSON to an attribute value (see [attr_to_json])
+fn attr_from_json(value: serde_json::Value) -> Result<AttrValue, Error> {
+    use serde_json::Value as Json;
+
+    let invalid =
+        |err: &dyn std::fmt::Display| Error::new(&format!("invalid attribute JSON: {err}"));
+    match value {
+        Json::Object(obj) if obj.len() == 1 => {
+            let (kind, value) = obj.into_iter().next().expect("object has 1 entry");
+            match (kind.as_str(), value) {
+                ("Float", Json::String(x)) => {
+                    x.parse().map(AttrValue::Float).map_err(|err| invalid(&err))
+                }
+                ("Array", Json::Array(values)) => values
+                    .into_iter()
+                    .map(attr_from_json)
+                    .collect::<Result<_, _>>()
+                    .map(AttrValue::Array),
+                ("Map", Json::Object(values)) => values
+                    .into_iter()
+                    .map(|(k, v)| attr_from_json(v).map(|v| (k, v)))
+                    .collect::<Result<_, _>>()
+                    .map(AttrValue::Map),
+                (kind, value) => serde_json::from_value(Json::Object(serde_json::Map::from_iter(
+                    [(kind.to_string(), value)],
+                )))
+                .map_err(|err| invalid(&err)),
+            }
+        }
+        value => serde_json::from_value(value).map_err(|err| invalid(&err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ch_attr_value_round_trip() {
+        let values = vec![
+            AttrValue::None,
+            AttrValue::Bool(true),
+            AttrValue::Uint(u64::MAX),
+            AttrValue::Int(i64::MIN),
+            AttrValue::Float(1.5),
+            AttrValue::String("string".to_string()),
+            AttrValue::Bytes(vec![0x00, 0xff]),
+            AttrValue::Array(vec![
+                AttrValue::Uint(u64::MAX),
+                AttrValue::Bytes(vec![0x01]),
+            ]),
+            AttrValue::Map(HashMap::from([(
+                "nested".to_string(),
+                AttrValue::Map(HashMap::from([("key".to_string(), AttrValue::Int(-1))])),
+            )])),
+            AttrValue::Array(vec![
+                AttrValue::Float(f64::INFINITY),
+                AttrValue::Map(HashMap::from([(
+                    "x".to_string(),
+                    AttrValue::Float(f64::NEG_INFINITY),
+                )])),
+            ]),
+        ];
+        for value in values {
+            let ch_value = value.clone().into_ch_value();
+            assert!(ch_value.is_same_type_as(&AttrValue::ch_type()));
+            assert_eq!(AttrValue::from_ch_value(ch_value).unwrap(), value);
+        }
+
+        // NB: NaN is not equal to itself
+        let value = AttrValue::Array(vec![AttrValue::Float(f64::NAN)]);
+        match AttrValue::from_ch_value(value.into_ch_value()).unwrap() {
+            AttrValue::Array(values) => {
+                assert!(matches!(values[..], [AttrValue::Float(x)] if x.is_nan()))
+            }
+            value => panic!("invalid value: {value:?}"),
+        }
+    }
+
+    #[test]
+    fn ch_attr_value_sql_type() {
+        assert_eq!(
+            AttrValue::ch_type().to_string(),
+            "Tuple(Enum8('array' = 7, 'bool' = 1, 'bytes' = 6, 'float' = 4, 'int' = 3, \
+             'map' = 8, 'none' = 0, 'string' = 5, 'uint' = 2), Bool, UInt64, Int64, Float64, String)"
+        );
+        assert_eq!(
+            AttrValue::Int(1).into_ch_value(),
+            Value::Tuple(vec![
+                Value::Enum8(3),
+                Value::Bool(false),
+                Value::UInt64(0),
+                Value::Int64(1),
+                Value::Float64(0.0),
+                Value::String(String::new()),
+            ])
+        );
+    }
+
+    #[test]
+    fn ch_span_records_round_trip() {
+        let service = Service {
+            name: "my_service".to_string(),
+            attrs: HashMap::from([("host".to_string(), AttrValue::String("a".to_string()))]),
+        };
+        let span = Span {
+            id: 1,
+            parent_id: Some(2),
+            trace_id: 3,
+            name: "span".to_string(),
+            start: 10,
+            end: 20,
+            kind: SpanKind::Client,
+            status: SpanStatus {
+                code: StatusCode::Error,
+                message: "error".to_string(),
+            },
+            trace_state: "key=value".to_string(),
+            attrs: HashMap::new(),
+            dropped_attrs: 1,
+            events: vec![
+                SpanEvent {
+                    timestamp: 11,
+                    name: "event 1".to_string(),
+                    attrs: HashMap::new(),
+                    dropped_attrs: 0,
+                },
+                SpanEvent {
+                    timestamp: 12,
+                    name: "event 2".to_string(),
+                    attrs: HashMap::new(),
+                    dropped_attrs: 0,
+                },
+            ],
+            dropped_events: 0,
+            links: vec![SpanLink {
+                trace_id: 4,
+                span_id: 5,
+                trace_state: String::new(),
+                attrs: HashMap::new(),
+                dropped_attrs: 0,
+            }],
+            dropped_links: 2,
+        };
+
+        let (ch_span, events, links) = ChSpan::new(&service, None, span.clone());
+        assert_eq!(events.len(), 2);
+        assert_ne!(events[0].id, events[1].id);
+        assert_eq!(links[0].trace_id, 3);
+
+        // records survive the conversion to the DB format
+        let ch_span = ChSpan::from_query_data(ChSpan::to_query_data(vec![ch_span]))
+            .unwrap()
+            .remove(0);
+        let events = ChSpanEvent::from_query_data(ChSpanEvent::to_query_data(events)).unwrap();
+        let links = ChSpanLink::from_query_data(ChSpanLink::to_query_data(links)).unwrap();
+        assert_eq!(ch_span.into_span(events, links), (service, None, span));
+    }
+
+    #[test]
+    fn ch_search_sql() {
+        let query = SpanQuery::new()
+            .service("it's")
+            .time_range(1, 2)
+            .attr(AttrFilter::new("status", AttrOp::Ge, AttrValue::Int(500)))
+            .attr(AttrFilter::exists("error"))
+            .limit(10);
+        assert_eq!(
+            span_query_sql(&query).unwrap(),
+            "SELECT * FROM spans WHERE service = 'it\\'s' AND start >= 1 AND start < 2 \
+             AND (mapContains(attrs, 'status') AND attrs['status'].1 = 'int' \
+             AND attrs['status'].4 >= 500) AND mapContains(attrs, 'error') \
+             ORDER BY start DESC LIMIT 10"
+        );
+
+        let query = LogQuery::new()
+            .text("failed")
+            .min_level(17)
+            .attr(AttrFilter::new(
+                "path",
+                AttrOp::Contains,
+                AttrValue::String("api".to_string()),
+            ));
+        assert_eq!(
+            log_query_sql(&query).unwrap(),
+            "SELECT * FROM logs WHERE position(message, 'failed') > 0 AND level >= 17 \
+             AND (mapContains(attrs, 'path') AND attrs['path'].1 = 'string' \
+             AND position(attrs['path'].6, 'api') > 0) ORDER BY timestamp DESC"
+        );
+
+        let query = LogQuery::new().trace(1).span(2);
+        assert_eq!(
+            log_query_sql(&query).unwrap(),
+            "SELECT * FROM logs WHERE trace_id = 1 AND span_id = 2 ORDER BY timestamp DESC"
+        );
+
+        let query = LogQuery::new().attr(AttrFilter::eq("list", AttrValue::Array(vec![])));
+        assert!(log_query_sql(&query).is_err());
+    }
+
+    #[test]
+    fn ch_span_ids_sql() {
+        let service = Service {
+            name: "my_service".to_string(),
+            attrs: HashMap::new(),
+        };
+        let spans = [(1, 2), (3, 2)]
+            .into_iter()
+            .map(|(trace_id, id)| {
+                let span = Span {
+                    id,
+                    trace_id,
+                    ..Default::default()
+                };
+                ChSpan::new(&service, None, span).0
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            span_ids_sql(&spans),
+            "(trace_id, span_id) IN ((1, 2), (3, 2))"
+        );
+    }
+
+    #[test]
+    fn ch_metric_search_sql() {
+        let query = MetricQuery::new().name("calls").time_range(1, 2).limit(10);
+        assert_eq!(
+            metric_query_sql(&query),
+            "SELECT * FROM metrics WHERE name = 'calls' AND timestamp >= 1 AND timestamp < 2 \
+             ORDER BY timestamp DESC LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn ch_attr_filter_cases() {
+        for (filter, expected) in crate::db::query::test_filter_cases() {
+            assert_eq!(
+                attr_filter_sql("attrs", &filter).is_ok(),
+                expected.is_some(),
+                "{filter:?}"
+            );
+        }
+    }
+}
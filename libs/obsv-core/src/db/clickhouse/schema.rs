@@ -0,0 +1,315 @@
+//! Clickhouse schema
+
+use std::time::Duration;
+
+use clickhouse_client::{orm::prelude::*, schema::TableSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{ChLog, ChMetric, ChMonitor, ChMonitorCheck, ChSpan, ChSpanEvent, ChSpanLink};
+
+/// Clickhouse schema configuration
+///
+/// Each signal has its own table configuration.
+/// The span events and links share the configuration of the spans.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChSchemaConfig {
+    /// Spans
+    pub spans: ChTableConfig,
+    /// Logs
+    pub logs: ChTableConfig,
+    /// Metrics
+    pub metrics: ChTableConfig,
+    /// Monitor checks
+    pub checks: ChTableConfig,
+}
+
+impl Default for ChSchemaConfig {
+    fn default() -> Self {
+        // NB: the trace ID follows the service (low cardinality) in the sorting keys,
+        // so that the trace lookups can skip the granules of the other traces
+        Self {
+            spans: ChTableConfig::new()
+                .partition_by_day()
+                .order_by(&["service", "trace_id", "start"]),
+            logs: ChTableConfig::new().partition_by_day().order_by(&[
+                "service",
+                "trace_id",
+                "timestamp",
+            ]),
+            metrics: ChTableConfig::new()
+                .partition_by_day()
+                .order_by(&["name", "timestamp"]),
+            checks: ChTableConfig::new()
+                .partition_by_day()
+                .order_by(&["monitor_id", "timestamp"]),
+        }
+    }
+}
+
+impl ChSchemaConfig {
+    /// Returns the configuration of the span events table
+    pub fn span_events(&self) -> ChTableConfig {
+        ChTableConfig {
+            order_by: None,
+            ..self.spans.clone()
+        }
+    }
+
+    /// Returns the configuration of the span links table
+    ///
+    /// NB: the links are partitioned and expired with the start time of their span
+    pub fn span_links(&self) -> ChTableConfig {
+        ChTableConfig {
+            order_by: None,
+            ..self.spans.clone()
+        }
+    }
+}
+
+/// Clickhouse table configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChTableConfig {
+    /// Partition the data by day
+    pub partition_by_day: bool,
+    /// Sorting key (defaults to the primary key)
+    pub order_by: Option<Vec<String>>,
+    /// Data TTL
+    pub ttl: Option<Duration>,
+    /// Storage policy
+    pub storage_policy: Option<String>,
+    /// Volume which the data is moved to after a delay (tiered storage)
+    pub cold_volume: Option<(String, Duration)>,
+}
+
+impl ChTableConfig {
+    /// Creates a new table configuration, without partitioning or TTL
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Partitions the data by day
+    pub fn partition_by_day(mut self) -> Self {
+        self.partition_by_day = true;
+        self
+    }
+
+    /// Sets the sorting key
+    pub fn order_by(mut self, columns: &[&str]) -> Self {
+        self.order_by = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Sets the data TTL
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the storage policy
+    pub fn storage_policy(mut self, policy: &str) -> Self {
+        self.storage_policy = Some(policy.to_string());
+        self
+    }
+
+    /// Moves the data to a volume after a delay
+    ///
+    /// The volume must be part of the storage policy.
+    pub fn cold_volume(mut self, volume: &str, after: Duration) -> Self {
+        self.cold_volume = Some((volume.to_string(), after));
+        self
+    }
+
+    /// Returns the TTL expression, for a time column (UNIX nanoseconds)
+    pub fn ttl_sql(&self, time_col: &str) -> Option<String> {
+        let mut ttls = vec![];
+        if let Some((volume, after)) = &self.cold_volume {
+            ttls.push(format!(
+                "{} TO VOLUME '{volume}'",
+                time_interval_sql(time_col, *after)
+            ));
+        }
+        if let Some(ttl) = self.ttl {
+            ttls.push(format!("{} DELETE", time_interval_sql(time_col, ttl)));
+        }
+        if ttls.is_empty() {
+            None
+        } else {
+            Some(ttls.join(", "))
+        }
+    }
+}
+
+/// A Clickhouse table
+#[derive(Debug)]
+pub struct ChTable {
+    /// Table schema
+    pub schema: TableSchema,
+    /// Table engine
+    pub engine: String,
+    /// Time column (UNIX nanoseconds), used for partitioning and TTL
+    pub time_col: Option<String>,
+    /// Configuration
+    pub config: ChTableConfig,
+}
+
+impl ChTable {
+    /// Creates a new table for a record
+    pub fn new<T: ChRecord>(engine: &str) -> Self {
+        Self::with_schema(T::ch_schema(), engine)
+    }
+
+    /// Creates a new table with a schema
+    pub fn with_schema(schema: TableSchema, engine: &str) -> Self {
+        Self {
+            schema,
+            engine: engine.to_string(),
+            time_col: None,
+            config: ChTableConfig::default(),
+        }
+    }
+
+    /// Sets the time column and the configuration
+    pub fn with_config(mut self, time_col: &str, config: ChTableConfig) -> Self {
+        self.time_col = Some(time_col.to_string());
+        self.config = config;
+        self
+    }
+
+    /// Returns the DDL statement to create the table
+    pub fn create_sql(&self, db: &str) -> String {
+        let columns = self
+            .schema
+            .columns
+            .iter()
+            .map(|col| format!("{} {}", col.id, col.ty))
+            .collect::<Vec<_>>();
+        let mut sql = format!(
+            "CREATE TABLE IF NOT EXISTS {db}.{} ({}) ENGINE = {}",
+            self.schema.name,
+            columns.join(", "),
+            self.engine
+        );
+        if let (true, Some(time_col)) = (self.config.partition_by_day, &self.time_col) {
+            sql.push_str(&format!(" PARTITION BY toYYYYMMDD({})", time_sql(time_col)));
+        }
+        // NB: the primary key defaults to the sorting key
+        match &self.config.order_by {
+            Some(order_by) => sql.push_str(&format!(" ORDER BY ({})", order_by.join(", "))),
+            None => {
+                let primary_keys = self
+                    .schema
+                    .columns
+                    .iter()
+                    .filter(|col| col.primary)
+                    .map(|col| col.id.as_str())
+                    .collect::<Vec<_>>();
+                sql.push_str(&format!(" PRIMARY KEY ({})", primary_keys.join(", ")));
+            }
+        }
+        if let Some(ttl) = self.ttl_sql() {
+            sql.push_str(&format!(" TTL {ttl}"));
+        }
+        if let Some(policy) = &self.config.storage_policy {
+            sql.push_str(&format!(" SETTINGS storage_policy = '{policy}'"));
+        }
+        sql
+    }
+
+    /// Returns the TTL expression
+    pub fn ttl_sql(&self) -> Option<String> {
+        self.time_col
+            .as_ref()
+            .and_then(|time_col| self.config.ttl_sql(time_col))
+    }
+}
+
+/// Returns the tables of the schema
+pub fn tables(config: &ChSchemaConfig) -> Vec<ChTable> {
+    vec![
+        ChTable::new::<ChSpan>("MergeTree").with_config("start", config.spans.clone()),
+        ChTable::new::<ChSpanEvent>("MergeTree").with_config("timestamp", config.span_events()),
+        ChTable::new::<ChSpanLink>("MergeTree").with_config("start", config.span_links()),
+        ChTable::new::<ChLog>("MergeTree").with_config("timestamp", config.logs.clone()),
+        ChTable::new::<ChMetric>("MergeTree").with_config("timestamp", config.metrics.clone()),
+        // NB: monitors are replaced when inserted again
+        ChTable::new::<ChMonitor>("ReplacingMergeTree"),
+        ChTable::new::<ChMonitorCheck>("MergeTree").with_config("timestamp", config.checks.clone()),
+    ]
+}
+
+/// Returns the SQL expression of a time column (UNIX nanoseconds) as a DateTime
+fn time_sql(time_col: &str) -> String {
+    format!("toDateTime(intDiv({time_col}, 1000000000))")
+}
+
+/// Returns the SQL expression of a time column plus an interval
+fn time_interval_sql(time_col: &str, interval: Duration) -> String {
+    format!(
+        "{} + INTERVAL {} SECOND",
+        time_sql(time_col),
+        interval.as_secs()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ch_table_sql() {
+        let config = ChSchemaConfig {
+            spans: ChTableConfig::new()
+                .partition_by_day()
+                .order_by(&["service", "start"])
+                .ttl(Duration::from_secs(30 * 86400))
+                .storage_policy("tiered")
+                .cold_volume("cold", Duration::from_secs(7 * 86400)),
+            ..Default::default()
+        };
+        let tables = tables(&config);
+        let spans = tables
+            .iter()
+            .find(|t| t.schema.name == "spans")
+            .unwrap()
+            .create_sql("obsv");
+        assert!(spans.starts_with("CREATE TABLE IF NOT EXISTS obsv.spans (id UInt64, "));
+        assert!(spans.ends_with(
+            "ENGINE = MergeTree \
+             PARTITION BY toYYYYMMDD(toDateTime(intDiv(start, 1000000000))) \
+             ORDER BY (service, start) \
+             TTL toDateTime(intDiv(start, 1000000000)) + INTERVAL 604800 SECOND TO VOLUME 'cold', \
+             toDateTime(intDiv(start, 1000000000)) + INTERVAL 2592000 SECOND DELETE \
+             SETTINGS storage_policy = 'tiered'"
+        ));
+
+        let links = tables
+            .iter()
+            .find(|t| t.schema.name == "spans_links_v2")
+            .unwrap()
+            .create_sql("obsv");
+        assert!(links.ends_with(
+            "ENGINE = MergeTree \
+             PARTITION BY toYYYYMMDD(toDateTime(intDiv(start, 1000000000))) \
+             PRIMARY KEY (span_id) \
+             TTL toDateTime(intDiv(start, 1000000000)) + INTERVAL 604800 SECOND TO VOLUME 'cold', \
+             toDateTime(intDiv(start, 1000000000)) + INTERVAL 2592000 SECOND DELETE \
+             SETTINGS storage_policy = 'tiered'"
+        ));
+    }
+
+    #[test]
+    fn ch_default_sort_keys() {
+        let tables = tables(&ChSchemaConfig::default());
+        for (table, order_by) in [
+            ("spans", "ORDER BY (service, trace_id, start)"),
+            ("logs", "ORDER BY (service, trace_id, timestamp)"),
+        ] {
+            let sql = tables
+                .iter()
+                .find(|t| t.schema.name == table)
+                .unwrap()
+                .create_sql("obsv");
+            assert!(sql.contains(order_by), "{sql}");
+        }
+    }
+}
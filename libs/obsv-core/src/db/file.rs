@@ -0,0 +1,880 @@
+//! File DB client
+//!
+//! The telemetry data is stored in append-only segment files (JSON lines),
+//! partitioned by time:
+//!
+//! ```text
+//! <dir>/spans/<partition start>.jsonl
+//! <dir>/logs/<partition start>.jsonl
+//! <dir>/metrics/<partition start>.jsonl
+//! <dir>/checks/<partition start>.jsonl
+//! <dir>/monitors.json
+//! ```
+//!
+//! Each segment keeps its min/max times and the trace IDs it contains, so that queries
+//! only read the relevant segments. The segments metadata is rebuilt when the DB is opened,
+//! and a last record partially written (eg. after a crash during an append) is truncated.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    data::{
+        Log, LogData, Metric, MetricsData, Monitor, MonitorCheck, Scope, Service, Span, TraceData,
+    },
+    error::Error,
+};
+
+use super::{DbClient, LogQuery, MetricQuery, SpanQuery};
+
+/// File DB configuration
+#[derive(Debug, Clone)]
+pub struct FileDbConfig {
+    /// Root directory
+    pub dir: PathBuf,
+    /// Duration of a segment partition
+    pub partition: Duration,
+    /// Maximum age of the data
+    pub max_age: Option<Duration>,
+    /// Maximum size of the segments (bytes)
+    pub max_size: Option<u64>,
+}
+
+impl FileDbConfig {
+    /// Creates a new config, with hourly partitions and no retention
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            partition: Duration::from_secs(60 * 60),
+            max_age: None,
+            max_size: None,
+        }
+    }
+
+    /// Sets the duration of a partition
+    pub fn partition(mut self, partition: Duration) -> Self {
+        self.partition = partition;
+        self
+    }
+
+    /// Sets the maximum age of the data
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets the maximum size of the segments (bytes)
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+}
+
+/// File DB client
+///
+/// NB: the file operations are blocking, so the [DbClient] methods run them on the blocking threads
+/// of the tokio runtime.
+#[derive(Debug, Clone)]
+pub struct FileDbClient {
+    /// Config
+    config: FileDbConfig,
+    /// Segments
+    segments: Arc<Mutex<Segments>>,
+}
+
+/// Segments (by signal and partition start)
+type Segments = BTreeMap<(Signal, i128), Segment>;
+
+/// Kind of telemetry signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Signal {
+    Spans,
+    Logs,
+    Metrics,
+    Checks,
+}
+
+impl Signal {
+    /// All signals
+    const ALL: [Signal; 4] = [Signal::Spans, Signal::Logs, Signal::Metrics, Signal::Checks];
+
+    /// Returns the directory name
+    fn dir_name(&self) -> &'static str {
+        match self {
+            Signal::Spans => "spans",
+            Signal::Logs => "logs",
+            Signal::Metrics => "metrics",
+            Signal::Checks => "checks",
+        }
+    }
+}
+
+/// A segment file
+#[derive(Debug)]
+struct Segment {
+    /// Path
+    path: PathBuf,
+    /// Minimum record time (UNIX nanoseconds)
+    min_time: i128,
+    /// Maximum record time (UNIX nanoseconds)
+    max_time: i128,
+    /// Size (bytes)
+    size: u64,
+    /// Trace IDs (spans only)
+    trace_ids: HashSet<u128>,
+}
+
+impl Segment {
+    /// Creates an empty segment
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            min_time: i128::MAX,
+            max_time: i128::MIN,
+            size: 0,
+            trace_ids: HashSet::new(),
+        }
+    }
+
+    /// Updates the segment metadata with a record
+    fn index<T: SegmentRecord>(&mut self, record: &T) {
+        self.min_time = self.min_time.min(record.time());
+        self.max_time = self.max_time.max(record.time());
+        if let Some(trace_id) = record.trace_id() {
+            self.trace_ids.insert(trace_id);
+        }
+    }
+
+    /// Checks if the segment overlaps a time range
+    fn overlaps(&self, from: Option<i128>, to: Option<i128>) -> bool {
+        from.is_none_or(|from| self.max_time >= from) && to.is_none_or(|to| self.min_time < to)
+    }
+}
+
+/// A record stored in a segment
+trait SegmentRecord: Serialize + DeserializeOwned {
+    /// Signal
+    const SIGNAL: Signal;
+
+    /// Returns the record time (UNIX nanoseconds)
+    fn time(&self) -> i128;
+
+    /// Returns the trace ID
+    fn trace_id(&self) -> Option<u128> {
+        None
+    }
+}
+
+/// A record of a service
+#[derive(Debug, Serialize, Deserialize)]
+struct ServiceRecord<T> {
+    /// Service
+    service: Service,
+    /// Scope
+    scope: Option<Scope>,
+    /// Data
+    data: T,
+}
+
+impl SegmentRecord for ServiceRecord<Span> {
+    const SIGNAL: Signal = Signal::Spans;
+
+    fn time(&self) -> i128 {
+        self.data.start
+    }
+
+    fn trace_id(&self) -> Option<u128> {
+        Some(self.data.trace_id)
+    }
+}
+
+impl SegmentRecord for ServiceRecord<Log> {
+    const SIGNAL: Signal = Signal::Logs;
+
+    fn time(&self) -> i128 {
+        self.data.timestamp
+    }
+}
+
+impl SegmentRecord for ServiceRecord<Metric> {
+    const SIGNAL: Signal = Signal::Metrics;
+
+    fn time(&self) -> i128 {
+        self.data.data.last_timestamp().unwrap_or_default()
+    }
+}
+
+impl SegmentRecord for MonitorCheck {
+    const SIGNAL: Signal = Signal::Checks;
+
+    fn time(&self) -> i128 {
+        self.timestamp
+    }
+}
+
+impl FileDbClient {
+    /// Opens the file DB
+    ///
+    /// The directories are created if needed, and the existing segments are indexed.
+    pub fn open(config: FileDbConfig) -> Result<Self, Error> {
+        let mut segments = BTreeMap::new();
+        for signal in Signal::ALL {
+            let dir = config.dir.join(signal.dir_name());
+            fs::create_dir_all(&dir).map_err(|err| io_error(&dir, err))?;
+            for entry in fs::read_dir(&dir).map_err(|err| io_error(&dir, err))? {
+                let path = entry.map_err(|err| io_error(&dir, err))?.path();
+                let start = match path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<i128>().ok())
+                {
+                    Some(start) if path.extension().is_some_and(|ext| ext == "jsonl") => start,
+                    _ => continue,
+                };
+                let segment = match signal {
+                    Signal::Spans => scan_segment::<ServiceRecord<Span>>(path)?,
+                    Signal::Logs => scan_segment::<ServiceRecord<Log>>(path)?,
+                    Signal::Metrics => scan_segment::<ServiceRecord<Metric>>(path)?,
+                    Signal::Checks => scan_segment::<MonitorCheck>(path)?,
+                };
+                segments.insert((signal, start), segment);
+            }
+        }
+        Ok(Self {
+            config,
+            segments: Arc::new(Mutex::new(segments)),
+        })
+    }
+
+    /// Returns the total size of the segments (bytes)
+    pub fn size(&self) -> Result<u64, Error> {
+        Ok(self.lock()?.values().map(|s| s.size).sum())
+    }
+
+    /// Applies the retention policy (maximum age and size)
+    ///
+    /// Only the segments which are entirely older than the maximum age are deleted
+    /// (the straddling segments are rewritten by [DbClient::delete_before]).
+    /// When the maximum size is exceeded, the oldest partitions are deleted first.
+    pub fn apply_retention(&self) -> Result<(), Error> {
+        if let Some(max_age) = self.config.max_age {
+            let now = OffsetDateTime::now_utc().unix_timestamp_nanos();
+            self.delete_segments_before(now - max_age.as_nanos() as i128, false)?;
+        }
+        if let Some(max_size) = self.config.max_size {
+            let mut segments = self.lock()?;
+            let mut size: u64 = segments.values().map(|s| s.size).sum();
+            let mut keys = segments.keys().copied().collect::<Vec<_>>();
+            keys.sort_by_key(|(_, start)| *start);
+            for key in keys {
+                if size <= max_size {
+                    break;
+                }
+                let segment = segments.remove(&key).unwrap();
+                remove_file(&segment.path)?;
+                size -= segment.size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a blocking file operation on the blocking threads
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|err| Error::string(format!("file DB task error: {err}")))?
+    }
+
+    /// Locks the segments
+    fn lock(&self) -> Result<MutexGuard<'_, Segments>, Error> {
+        self.segments
+            .lock()
+            .map_err(|_| Error::new("file DB lock is poisoned"))
+    }
+
+    /// Appends records to their partition segments
+    fn append<T: SegmentRecord>(&self, records: Vec<T>) -> Result<(), Error> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let partition = self.config.partition.as_nanos().max(1) as i128;
+        let mut partitions: BTreeMap<i128, Vec<T>> = BTreeMap::new();
+        for record in records {
+            let start = record.time().div_euclid(partition) * partition;
+            partitions.entry(start).or_default().push(record);
+        }
+
+        {
+            let mut segments = self.lock()?;
+            for (start, records) in partitions {
+                let segment = segments.entry((T::SIGNAL, start)).or_insert_with(|| {
+                    Segment::new(
+                        self.config
+                            .dir
+                            .join(T::SIGNAL.dir_name())
+                            .join(format!("{start}.jsonl")),
+                    )
+                });
+                let mut buf = vec![];
+                for record in &records {
+                    serde_json::to_writer(&mut buf, record).map_err(json_error)?;
+                    buf.push(b'\n');
+                }
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&segment.path)
+                    .map_err(|err| io_error(&segment.path, err))?;
+                if let Err(err) = file.write_all(&buf) {
+                    // NB: a partial write is truncated, so that the next records are appended after the valid ones
+                    let _ = file.set_len(segment.size);
+                    return Err(io_error(&segment.path, err));
+                }
+                for record in &records {
+                    segment.index(record);
+                }
+                segment.size += buf.len() as u64;
+            }
+        }
+
+        self.apply_retention()
+    }
+
+    /// Reads the records of the segments which match a filter
+    ///
+    /// NB: the files are read without holding the segments lock, so a segment which is removed
+    /// in the meantime is skipped.
+    fn read<T: SegmentRecord>(&self, filter: impl Fn(&Segment) -> bool) -> Result<Vec<T>, Error> {
+        let paths = self
+            .lock()?
+            .iter()
+            .filter(|((signal, _), segment)| *signal == T::SIGNAL && filter(segment))
+            .map(|(_, segment)| segment.path.clone())
+            .collect::<Vec<_>>();
+        let mut records = vec![];
+        for path in paths {
+            match read_segment::<T>(&path) {
+                Ok((segment_records, _)) => records.extend(segment_records),
+                Err(_) if !path.exists() => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Deletes the records before a timestamp
+    ///
+    /// Segments which are entirely before the timestamp are removed,
+    /// and segments which straddle the timestamp are rewritten if `rewrite` is set.
+    fn delete_segments_before(&self, timestamp: i128, rewrite: bool) -> Result<(), Error> {
+        let mut segments = self.lock()?;
+        let keys = segments.keys().copied().collect::<Vec<_>>();
+        for key in keys {
+            let segment = segments.get_mut(&key).unwrap();
+            if segment.max_time < timestamp {
+                remove_file(&segment.path)?;
+                segments.remove(&key);
+            } else if rewrite && segment.min_time < timestamp {
+                let path = segment.path.clone();
+                *segment = match key.0 {
+                    Signal::Spans => retain_segment::<ServiceRecord<Span>>(path, timestamp)?,
+                    Signal::Logs => retain_segment::<ServiceRecord<Log>>(path, timestamp)?,
+                    Signal::Metrics => retain_segment::<ServiceRecord<Metric>>(path, timestamp)?,
+                    Signal::Checks => retain_segment::<MonitorCheck>(path, timestamp)?,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the monitors file
+    fn monitors_path(&self) -> PathBuf {
+        self.config.dir.join("monitors.json")
+    }
+
+    /// Reads the monitors
+    fn read_monitors(&self) -> Result<BTreeMap<u32, Monitor>, Error> {
+        let path = self.monitors_path();
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file = File::open(&path).map_err(|err| io_error(&path, err))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(json_error)
+    }
+}
+
+#[async_trait]
+impl DbClient for FileDbClient {
+    async fn init(&self) -> Result<(), Error> {
+        self.blocking(|db| db.apply_retention()).await
+    }
+
+    async fn insert_traces(&self, data: TraceData) -> Result<(), Error> {
+        let mut records = vec![];
+        for service_spans in data.spans {
+            for span in service_spans.spans {
+                records.push(ServiceRecord {
+                    service: service_spans.service.clone(),
+                    scope: service_spans.scope.clone(),
+                    data: span,
+                });
+            }
+        }
+        self.blocking(|db| db.append(records)).await
+    }
+
+    async fn insert_logs(&self, data: LogData) -> Result<(), Error> {
+        let mut records = vec![];
+        for service_logs in data.logs {
+            for log in service_logs.logs {
+                records.push(ServiceRecord {
+                    service: service_logs.service.clone(),
+                    scope: service_logs.scope.clone(),
+                    data: log,
+                });
+            }
+        }
+        self.blocking(|db| db.append(records)).await
+    }
+
+    async fn insert_metrics(&self, data: MetricsData) -> Result<(), Error> {
+        let mut records = vec![];
+        for service_metrics in data.metrics {
+            for metric in service_metrics.metrics {
+                records.push(ServiceRecord {
+                    service: service_metrics.service.clone(),
+                    scope: service_metrics.scope.clone(),
+                    data: metric,
+                });
+            }
+        }
+        self.blocking(|db| db.append(records)).await
+    }
+
+    async fn insert_monitors(&self, monitors: Vec<Monitor>) -> Result<(), Error> {
+        self.blocking(|db| {
+            // NB: the monitors file is rewritten while holding the segments lock
+            let _lock = db.lock()?;
+            let mut all_monitors = db.read_monitors()?;
+            for monitor in monitors {
+                all_monitors.insert(monitor.id, monitor);
+            }
+            let path = db.monitors_path();
+            let json = serde_json::to_vec(&all_monitors).map_err(json_error)?;
+            fs::write(&path, json).map_err(|err| io_error(&path, err))
+        })
+        .await
+    }
+
+    async fn insert_monitor_checks(&self, checks: Vec<MonitorCheck>) -> Result<(), Error> {
+        self.blocking(|db| db.append(checks)).await
+    }
+
+    async fn get_trace(&self, trace_id: u128) -> Result<TraceData, Error> {
+        let mut records = self
+            .blocking(move |db| db.read::<ServiceRecord<Span>>(|s| s.trace_ids.contains(&trace_id)))
+            .await?;
+        records.retain(|r| r.data.trace_id == trace_id);
+        records.sort_by_key(|r| r.data.start);
+        let mut data = TraceData::default();
+        for record in records {
+            data.add_span(&record.service, record.scope.as_ref(), record.data);
+        }
+        Ok(data)
+    }
+
+    async fn search_spans(&self, query: &SpanQuery) -> Result<TraceData, Error> {
+        query.check()?;
+        let (from, to) = (query.from, query.to);
+        let mut records = self
+            .blocking(move |db| db.read::<ServiceRecord<Span>>(|s| s.overlaps(from, to)))
+            .await?;
+        records.retain(|r| query.matches(&r.service, &r.data));
+        // NB: the most recent spans first
+        records.sort_by_key(|r| std::cmp::Reverse(r.data.start));
+        let mut data = TraceData::default();
+        for record in records.into_iter().take(query.limit.unwrap_or(usize::MAX)) {
+            data.add_span(&record.service, record.scope.as_ref(), record.data);
+        }
+        Ok(data)
+    }
+
+    async fn search_logs(&self, query: &LogQuery) -> Result<LogData, Error> {
+        query.check()?;
+        let (from, to) = (query.from, query.to);
+        let mut records = self
+            .blocking(move |db| db.read::<ServiceRecord<Log>>(|s| s.overlaps(from, to)))
+            .await?;
+        records.retain(|r| query.matches(&r.service, &r.data));
+        // NB: the most recent logs first
+        records.sort_by_key(|r| std::cmp::Reverse(r.data.timestamp));
+        let mut data = LogData::default();
+        for record in records.into_iter().take(query.limit.unwrap_or(usize::MAX)) {
+            data.add_log(&record.service, record.scope.as_ref(), record.data);
+        }
+        Ok(data)
+    }
+
+    async fn search_metrics(&self, query: &MetricQuery) -> Result<MetricsData, Error> {
+        let (from, to) = (query.from, query.to);
+        let mut records = self
+            .blocking(move |db| db.read::<ServiceRecord<Metric>>(|s| s.overlaps(from, to)))
+            .await?;
+        records.retain(|r| query.matches(&r.service, &r.data));
+        // NB: the most recent metrics first
+        records.sort_by_key(|r| std::cmp::Reverse(r.time()));
+        let mut data = MetricsData::default();
+        for record in records.into_iter().take(query.limit.unwrap_or(usize::MAX)) {
+            data.add_metric(&record.service, record.scope.as_ref(), record.data);
+        }
+        Ok(data)
+    }
+
+    async fn get_monitors(&self) -> Result<Vec<Monitor>, Error> {
+        self.blocking(|db| {
+            let _lock = db.lock()?;
+            Ok(db.read_monitors()?.into_values().collect())
+        })
+        .await
+    }
+
+    async fn delete_before(&self, timestamp: i128) -> Result<(), Error> {
+        self.blocking(move |db| db.delete_segments_before(timestamp, true))
+            .await
+    }
+}
+
+/// Reads the records of a segment file, and returns them with the size of the valid records (bytes)
+///
+/// NB: the last line may have been partially written (eg. after a crash during an append),
+/// so it is ignored if it is incomplete or invalid.
+fn read_segment<T: SegmentRecord>(path: &Path) -> Result<(Vec<T>, u64), Error> {
+    let file = File::open(path).map_err(|err| io_error(path, err))?;
+    let mut reader = BufReader::new(file);
+    let mut records = vec![];
+    let mut size = 0;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        let n = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|err| io_error(path, err))?;
+        if n == 0 {
+            break;
+        }
+        let is_last = reader
+            .fill_buf()
+            .map_err(|err| io_error(path, err))?
+            .is_empty();
+        if is_last && line.last() != Some(&b'\n') {
+            break;
+        }
+        let content = line.trim_ascii();
+        if !content.is_empty() {
+            match serde_json::from_slice(content) {
+                Ok(record) => records.push(record),
+                Err(_) if is_last => break,
+                Err(err) => return Err(json_error(err)),
+            }
+        }
+        size += n as u64;
+    }
+    Ok((records, size))
+}
+
+/// Scans a segment file to rebuild its metadata
+///
+/// A partially written last record is truncated, so that the next records are appended after the valid ones.
+fn scan_segment<T: SegmentRecord>(path: PathBuf) -> Result<Segment, Error> {
+    let mut segment = Segment::new(path);
+    let (records, size) = read_segment::<T>(&segment.path)?;
+    for record in records {
+        segment.index(&record);
+    }
+    let file_size = fs::metadata(&segment.path)
+        .map_err(|err| io_error(&segment.path, err))?
+        .len();
+    if size < file_size {
+        OpenOptions::new()
+            .write(true)
+            .open(&segment.path)
+            .and_then(|file| file.set_len(size))
+            .map_err(|err| io_error(&segment.path, err))?;
+    }
+    segment.size = size;
+    Ok(segment)
+}
+
+/// Rewrites a segment file with the records after a timestamp
+fn retain_segment<T: SegmentRecord>(path: PathBuf, timestamp: i128) -> Result<Segment, Error> {
+    let mut buf = vec![];
+    for record in read_segment::<T>(&path)?.0 {
+        if record.time() >= timestamp {
+            serde_json::to_writer(&mut buf, &record).map_err(json_error)?;
+            buf.push(b'\n');
+        }
+    }
+    // NB: the segment is written to a temporary file first, to avoid losing data
+    let tmp_path = path.with_extension("jsonl.tmp");
+    fs::write(&tmp_path, buf).map_err(|err| io_error(&tmp_path, err))?;
+    fs::rename(&tmp_path, &path).map_err(|err| io_error(&path, err))?;
+    scan_segment::<T>(path)
+}
+
+/// Removes a file
+fn remove_file(path: &Path) -> Result<(), Error> {
+    fs::remove_file(path).map_err(|err| io_error(path, err))
+}
+
+/// Returns an IO error for a path
+fn io_error(path: &Path, err: std::io::Error) -> Error {
+    Error::string(format!("file DB error ({}): {err}", path.display()))
+}
+
+/// Returns a JSON error
+fn json_error(err: serde_json::Error) -> Error {
+    Error::string(format!("file DB JSON error: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{data::ServiceSpans, db::AttrFilter};
+
+    use super::*;
+
+    /// Returns an empty test directory
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obsv-file-db-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Returns trace data with 1 span per start time
+    fn trace_data(spans: &[(u128, u64, i128)]) -> TraceData {
+        TraceData {
+            spans: vec![ServiceSpans {
+                service: Service {
+                    name: "my_service".to_string(),
+                    attrs: HashMap::new(),
+                },
+                scope: None,
+                spans: spans
+                    .iter()
+                    .map(|(trace_id, id, start)| Span {
+                        id: *id,
+                        trace_id: *trace_id,
+                        name: "span".to_string(),
+                        start: *start,
+                        end: start + 1,
+                        attrs: HashMap::from([(
+                            "id".to_string(),
+                            crate::data::AttrValue::Uint(*id),
+                        )]),
+                        ..Default::default()
+                    })
+                    .collect(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn file_db_traces() {
+        let dir = test_dir("traces");
+        let config = FileDbConfig::new(&dir).partition(Duration::from_nanos(100));
+        let db = FileDbClient::open(config.clone()).unwrap();
+        db.init().await.unwrap();
+        db.insert_traces(trace_data(&[(1, 1, 10), (1, 2, 150), (2, 3, 120)]))
+            .await
+            .unwrap();
+        db.insert_traces(trace_data(&[(2, 4, 250)])).await.unwrap();
+
+        // 1 segment per partition
+        assert_eq!(fs::read_dir(dir.join("spans")).unwrap().count(), 3);
+        assert_eq!(db.get_trace(1).await.unwrap().len(), 2);
+        let data = db
+            .search_spans(&SpanQuery::new().time_range(100, 200))
+            .await
+            .unwrap();
+        assert_eq!(
+            data.spans[0].spans.iter().map(|s| s.id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        let data = db
+            .search_spans(
+                &SpanQuery::new().attr(AttrFilter::eq("id", crate::data::AttrValue::Uint(4))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(data.len(), 1);
+
+        // the index is rebuilt when the DB is reopened
+        drop(db);
+        let db = FileDbClient::open(config).unwrap();
+        let trace = db.get_trace(2).await.unwrap();
+        assert_eq!(
+            trace.spans[0]
+                .spans
+                .iter()
+                .map(|s| s.id)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+
+        // retention by age (the straddling segment is rewritten)
+        db.delete_before(130).await.unwrap();
+        assert_eq!(db.get_trace(1).await.unwrap().len(), 1);
+        assert_eq!(db.get_trace(2).await.unwrap().len(), 1);
+        assert_eq!(fs::read_dir(dir.join("spans")).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_db_attr_filters() {
+        let dir = test_dir("attr_filters");
+        let db = FileDbClient::open(FileDbConfig::new(&dir)).unwrap();
+        let mut data = trace_data(&[(1, 1, 10)]);
+        data.spans[0].spans[0].attrs = crate::db::query::test_filter_attrs();
+        db.insert_traces(data).await.unwrap();
+        for (filter, expected) in crate::db::query::test_filter_cases() {
+            let res = db
+                .search_spans(&SpanQuery::new().attr(filter.clone()))
+                .await;
+            assert_eq!(res.ok().map(|data| data.len() == 1), expected, "{filter:?}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_db_partial_record() {
+        let dir = test_dir("partial_record");
+        let config = FileDbConfig::new(&dir);
+        let db = FileDbClient::open(config.clone()).unwrap();
+        db.insert_traces(trace_data(&[(1, 1, 10), (1, 2, 20)]))
+            .await
+            .unwrap();
+
+        // crash during an append
+        let path = dir.join("spans").join("0.jsonl");
+        let size = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"service":{"name":"my_se"#).unwrap();
+        drop(file);
+
+        // the partial record is truncated
+        drop(db);
+        let db = FileDbClient::open(config.clone()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), size);
+        assert_eq!(db.get_trace(1).await.unwrap().len(), 2);
+        db.insert_traces(trace_data(&[(1, 3, 30)])).await.unwrap();
+        drop(db);
+        let db = FileDbClient::open(config).unwrap();
+        assert_eq!(db.get_trace(1).await.unwrap().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_db_max_age() {
+        let dir = test_dir("max_age");
+        let now = OffsetDateTime::now_utc().unix_timestamp_nanos();
+        let hour = 60 * 60 * 1_000_000_000;
+        let db = FileDbClient::open(
+            FileDbConfig::new(&dir)
+                .partition(Duration::from_secs(1_000 * 24 * 60 * 60))
+                .max_age(Duration::from_secs(60 * 60)),
+        )
+        .unwrap();
+        db.insert_traces(trace_data(&[
+            (1, 1, 10),
+            (2, 2, now - 2 * hour),
+            (2, 3, now),
+        ]))
+        .await
+        .unwrap();
+
+        // the expired partition is deleted on insert, the straddling one is kept as is
+        assert!(db.get_trace(1).await.unwrap().is_empty());
+        assert_eq!(db.get_trace(2).await.unwrap().len(), 2);
+        assert_eq!(fs::read_dir(dir.join("spans")).unwrap().count(), 1);
+
+        db.delete_before(now - hour).await.unwrap();
+        assert_eq!(db.get_trace(2).await.unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_db_max_size() {
+        let dir = test_dir("max_size");
+        let db = FileDbClient::open(FileDbConfig::new(&dir).partition(Duration::from_nanos(100)))
+            .unwrap();
+        db.insert_traces(trace_data(&[(1, 1, 10), (1, 2, 110), (1, 3, 210)]))
+            .await
+            .unwrap();
+        let size = db.size().unwrap();
+
+        // the oldest partition is deleted
+        let db = FileDbClient::open(
+            FileDbConfig::new(&dir)
+                .partition(Duration::from_nanos(100))
+                .max_size(size - 1),
+        )
+        .unwrap();
+        db.init().await.unwrap();
+        let trace = db.get_trace(1).await.unwrap();
+        assert_eq!(
+            trace.spans[0]
+                .spans
+                .iter()
+                .map(|s| s.id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_db_monitors() {
+        let dir = test_dir("monitors");
+        let db = FileDbClient::open(FileDbConfig::new(&dir)).unwrap();
+        db.insert_monitors(vec![
+            Monitor::http(1, "a".to_string(), "http://a".to_string()),
+            Monitor::http(2, "b".to_string(), "http://b".to_string()),
+        ])
+        .await
+        .unwrap();
+        db.insert_monitors(vec![Monitor::http(
+            1,
+            "a2".to_string(),
+            "http://a".to_string(),
+        )])
+        .await
+        .unwrap();
+        let monitors = db.get_monitors().await.unwrap();
+        assert_eq!(
+            monitors.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(),
+            vec!["a2", "b"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,448 @@
+//! In-memory DB client
+
+use std::{
+    collections::BTreeMap,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    data::{
+        Log, LogData, Metric, MetricsData, Monitor, MonitorCheck, Scope, Service, Span, TraceData,
+    },
+    error::Error,
+};
+
+use super::{DbClient, LogQuery, MetricQuery, SpanQuery};
+
+/// In-memory DB client
+///
+/// The data is kept in memory and lost when the client is dropped.
+/// The query semantics are the same as the other DB clients.
+#[derive(Debug, Default)]
+pub struct MemDbClient {
+    /// Store
+    store: RwLock<MemStore>,
+}
+
+/// In-memory store
+#[derive(Debug, Default)]
+struct MemStore {
+    /// Spans
+    spans: Vec<(Service, Option<Scope>, Span)>,
+    /// Logs
+    logs: Vec<(Service, Option<Scope>, Log)>,
+    /// Metrics
+    metrics: Vec<(Service, Option<Scope>, Metric)>,
+    /// Monitors (by ID)
+    monitors: BTreeMap<u32, Monitor>,
+    /// Monitor checks
+    checks: Vec<MonitorCheck>,
+}
+
+impl MemDbClient {
+    /// Creates a new in-memory DB client
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the monitor checks of a monitor
+    pub fn monitor_checks(&self, monitor_id: u32) -> Result<Vec<MonitorCheck>, Error> {
+        Ok(self
+            .read()?
+            .checks
+            .iter()
+            .filter(|c| c.monitor_id == monitor_id)
+            .cloned()
+            .collect())
+    }
+
+    /// Returns all the metrics
+    pub fn metrics(&self) -> Result<MetricsData, Error> {
+        let mut data = MetricsData::default();
+        for (service, scope, metric) in &self.read()?.metrics {
+            data.add_metric(service, scope.as_ref(), metric.clone());
+        }
+        Ok(data)
+    }
+
+    /// Locks the store for reading
+    fn read(&self) -> Result<RwLockReadGuard<'_, MemStore>, Error> {
+        self.store
+            .read()
+            .map_err(|_| Error::new("memory DB lock is poisoned"))
+    }
+
+    /// Locks the store for writing
+    fn write(&self) -> Result<RwLockWriteGuard<'_, MemStore>, Error> {
+        self.store
+            .write()
+            .map_err(|_| Error::new("memory DB lock is poisoned"))
+    }
+}
+
+#[async_trait]
+impl DbClient for MemDbClient {
+    async fn init(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn insert_traces(&self, data: TraceData) -> Result<(), Error> {
+        let mut store = self.write()?;
+        for service_spans in data.spans {
+            for span in service_spans.spans {
+                store.spans.push((
+                    service_spans.service.clone(),
+                    service_spans.scope.clone(),
+                    span,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_logs(&self, data: LogData) -> Result<(), Error> {
+        let mut store = self.write()?;
+        for service_logs in data.logs {
+            for log in service_logs.logs {
+                store.logs.push((
+                    service_logs.service.clone(),
+                    service_logs.scope.clone(),
+                    log,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_metrics(&self, data: MetricsData) -> Result<(), Error> {
+        let mut store = self.write()?;
+        for service_metrics in data.metrics {
+            for metric in service_metrics.metrics {
+                store.metrics.push((
+                    service_metrics.service.clone(),
+                    service_metrics.scope.clone(),
+                    metric,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_monitors(&self, monitors: Vec<Monitor>) -> Result<(), Error> {
+        let mut store = self.write()?;
+        for monitor in monitors {
+            store.monitors.insert(monitor.id, monitor);
+        }
+        Ok(())
+    }
+
+    async fn insert_monitor_checks(&self, checks: Vec<MonitorCheck>) -> Result<(), Error> {
+        self.write()?.checks.extend(checks);
+        Ok(())
+    }
+
+    async fn get_trace(&self, trace_id: u128) -> Result<TraceData, Error> {
+        let store = self.read()?;
+        let mut spans = store
+            .spans
+            .iter()
+            .filter(|(_, _, span)| span.trace_id == trace_id)
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|(_, _, span)| span.start);
+        let mut data = TraceData::default();
+        for (service, scope, span) in spans {
+            data.add_span(service, scope.as_ref(), span.clone());
+        }
+        Ok(data)
+    }
+
+    async fn search_spans(&self, query: &SpanQuery) -> Result<TraceData, Error> {
+        query.check()?;
+        let store = self.read()?;
+        let mut spans = store
+            .spans
+            .iter()
+            .filter(|(service, _, span)| query.matches(service, span))
+            .collect::<Vec<_>>();
+        // NB: the most recent spans first
+        spans.sort_by_key(|(_, _, span)| std::cmp::Reverse(span.start));
+        let mut data = TraceData::default();
+        for (service, scope, span) in spans.into_iter().take(query.limit.unwrap_or(usize::MAX)) {
+            data.add_span(service, scope.as_ref(), span.clone());
+        }
+        Ok(data)
+    }
+
+    async fn search_logs(&self, query: &LogQuery) -> Result<LogData, Error> {
+        query.check()?;
+        let store = self.read()?;
+        let mut logs = store
+            .logs
+            .iter()
+            .filter(|(service, _, log)| query.matches(service, log))
+            .collect::<Vec<_>>();
+        // NB: the most recent logs first
+        logs.sort_by_key(|(_, _, log)| std::cmp::Reverse(log.timestamp));
+        let mut data = LogData::default();
+        for (service, scope, log) in logs.into_iter().take(query.limit.unwrap_or(usize::MAX)) {
+            data.add_log(service, scope.as_ref(), log.clone());
+        }
+        Ok(data)
+    }
+
+    async fn search_metrics(&self, query: &MetricQuery) -> Result<MetricsData, Error> {
+        let store = self.read()?;
+        let mut metrics = store
+            .metrics
+            .iter()
+            .filter(|(service, _, metric)| query.matches(service, metric))
+            .collect::<Vec<_>>();
+        // NB: the most recent metrics first
+        metrics.sort_by_key(|(_, _, metric)| {
+            std::cmp::Reverse(metric.data.last_timestamp().unwrap_or_default())
+        });
+        let mut data = MetricsData::default();
+        for (service, scope, metric) in metrics.into_iter().take(query.limit.unwrap_or(usize::MAX))
+        {
+            data.add_metric(service, scope.as_ref(), metric.clone());
+        }
+        Ok(data)
+    }
+
+    async fn get_monitors(&self) -> Result<Vec<Monitor>, Error> {
+        Ok(self.read()?.monitors.values().cloned().collect())
+    }
+
+    async fn delete_before(&self, timestamp: i128) -> Result<(), Error> {
+        let mut store = self.write()?;
+        store.spans.retain(|(_, _, span)| span.start >= timestamp);
+        store.logs.retain(|(_, _, log)| log.timestamp >= timestamp);
+        store
+            .metrics
+            .retain(|(_, _, metric)| metric.data.last_timestamp().unwrap_or_default() >= timestamp);
+        store.checks.retain(|check| check.timestamp >= timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        data::{AttrValue, ServiceLogs, ServiceSpans},
+        db::AttrFilter,
+    };
+
+    use super::*;
+
+    /// Returns a service
+    fn service(name: &str) -> Service {
+        Service {
+            name: name.to_string(),
+            attrs: HashMap::new(),
+        }
+    }
+
+    /// Returns a span
+    fn span(trace_id: u128, id: u64, start: i128, status: i64) -> Span {
+        Span {
+            id,
+            trace_id,
+            name: format!("span {id}"),
+            start,
+            end: start + 10,
+            attrs: HashMap::from([("status".to_string(), AttrValue::Int(status))]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn mem_db_traces() {
+        let db = MemDbClient::new();
+        db.init().await.unwrap();
+        db.insert_traces(TraceData {
+            spans: vec![
+                ServiceSpans {
+                    service: service("a"),
+                    scope: None,
+                    spans: vec![span(1, 2, 20, 200), span(2, 3, 30, 500)],
+                },
+                ServiceSpans {
+                    service: service("b"),
+                    scope: None,
+                    spans: vec![span(1, 1, 10, 200)],
+                },
+            ],
+        })
+        .await
+        .unwrap();
+
+        // trace lookup, ordered by start time
+        let trace = db.get_trace(1).await.unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace.spans[0].service.name, "b");
+        assert_eq!(trace.spans[1].spans[0].id, 2);
+
+        // search
+        let data = db
+            .search_spans(&SpanQuery::new().service("a"))
+            .await
+            .unwrap();
+        assert_eq!(data.spans[0].spans[0].id, 3);
+        assert_eq!(data.len(), 2);
+        let data = db
+            .search_spans(&SpanQuery::new().time_range(0, 25).limit(1))
+            .await
+            .unwrap();
+        assert_eq!(data.spans[0].spans[0].id, 2);
+        assert_eq!(data.len(), 1);
+        let data = db
+            .search_spans(&SpanQuery::new().attr(AttrFilter::eq("status", AttrValue::Int(500))))
+            .await
+            .unwrap();
+        assert_eq!(data.spans[0].spans[0].id, 3);
+        assert_eq!(data.len(), 1);
+
+        // retention
+        db.delete_before(20).await.unwrap();
+        assert_eq!(db.get_trace(1).await.unwrap().spans[0].spans[0].id, 2);
+        assert_eq!(db.search_spans(&SpanQuery::new()).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn mem_db_attr_filters() {
+        let db = MemDbClient::new();
+        let mut data = TraceData::default();
+        data.add_span(
+            &service("a"),
+            None,
+            Span {
+                attrs: crate::db::query::test_filter_attrs(),
+                ..span(1, 1, 10, 0)
+            },
+        );
+        db.insert_traces(data).await.unwrap();
+        for (filter, expected) in crate::db::query::test_filter_cases() {
+            let res = db
+                .search_spans(&SpanQuery::new().attr(filter.clone()))
+                .await;
+            assert_eq!(res.ok().map(|data| data.len() == 1), expected, "{filter:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn mem_db_logs() {
+        let db = MemDbClient::new();
+        let log = |timestamp: i128, level: i16, message: &str| Log {
+            trace_id: 0,
+            span_id: 0,
+            timestamp,
+            level,
+            message: message.to_string(),
+            attrs: HashMap::new(),
+        };
+        db.insert_logs(LogData {
+            logs: vec![ServiceLogs {
+                service: service("a"),
+                scope: None,
+                logs: vec![
+                    log(1, 9, "started"),
+                    log(2, 17, "request failed"),
+                    log(3, 9, "request done"),
+                ],
+            }],
+        })
+        .await
+        .unwrap();
+
+        let data = db
+            .search_logs(&LogQuery::new().text("request"))
+            .await
+            .unwrap();
+        assert_eq!(
+            data.logs[0]
+                .logs
+                .iter()
+                .map(|l| l.timestamp)
+                .collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        let data = db
+            .search_logs(&LogQuery::new().min_level(17))
+            .await
+            .unwrap();
+        assert_eq!(data.logs[0].logs[0].message, "request failed");
+
+        db.delete_before(3).await.unwrap();
+        assert_eq!(db.search_logs(&LogQuery::new()).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mem_db_trace_logs() {
+        let db = MemDbClient::new();
+        let mut data = LogData::default();
+        for (trace_id, message) in [(1, "started"), (2, "request failed"), (1, "done")] {
+            data.add_log(
+                &service("a"),
+                None,
+                Log {
+                    trace_id,
+                    span_id: 1,
+                    timestamp: 0,
+                    level: 9,
+                    message: message.to_string(),
+                    attrs: HashMap::new(),
+                },
+            );
+        }
+        db.insert_logs(data).await.unwrap();
+
+        let data = db.get_trace_logs(2).await.unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.logs[0].logs[0].message, "request failed");
+        assert_eq!(db.get_trace_logs(1).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn mem_db_service_graph() {
+        let db = MemDbClient::new();
+        let mut data = TraceData::default();
+        data.add_span(&service("web"), None, span(1, 1, 10, 0));
+        data.add_span(
+            &service("api"),
+            None,
+            Span {
+                parent_id: Some(1),
+                ..span(1, 2, 20, 0)
+            },
+        );
+        let graph = crate::data::ServiceGraph::from_traces(&data);
+        db.insert_metrics(graph.to_metrics(0, 100)).await.unwrap();
+        db.insert_metrics(graph.to_metrics(100, 200)).await.unwrap();
+
+        let metrics = db
+            .search_metrics(&MetricQuery::new().service("api").limit(1))
+            .await
+            .unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(
+            metrics.metrics[0].metrics[0].data.last_timestamp(),
+            Some(200)
+        );
+
+        let graph = db.get_service_graph(0, 150).await.unwrap();
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].requests, 1);
+        let graph = db.get_service_graph(0, 250).await.unwrap();
+        assert_eq!(graph.edges[0].requests, 2);
+        assert!(db
+            .get_service_graph(300, 400)
+            .await
+            .unwrap()
+            .edges
+            .is_empty());
+    }
+}
@@ -1,11 +1,84 @@
 //! Databases
 
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use crate::{
+    data::{
+        LogData, MetricsData, Monitor, MonitorCheck, ServiceGraph, TraceData,
+        SERVICE_GRAPH_DURATION_METRIC, SERVICE_GRAPH_FAILED_METRIC,
+    },
+    error::Error,
+};
+
 #[cfg(feature = "clickhouse")]
 pub mod clickhouse;
+#[cfg(feature = "file")]
+pub mod file;
+pub mod memory;
+mod query;
+
+pub use query::*;
 
 /// Database client
 ///
 /// A DB client can store and retrieve the telemetry data
-pub trait DbClient {
-    // TODO: add methods
+#[async_trait]
+pub trait DbClient: Debug + Send + Sync {
+    /// Initializes the DB (creates or migrates the schema)
+    async fn init(&self) -> Result<(), Error>;
+
+    /// Inserts traces
+    async fn insert_traces(&self, data: TraceData) -> Result<(), Error>;
+
+    /// Inserts logs
+    async fn insert_logs(&self, data: LogData) -> Result<(), Error>;
+
+    /// Inserts metrics
+    async fn insert_metrics(&self, data: MetricsData) -> Result<(), Error>;
+
+    /// Inserts (or replaces) monitors
+    async fn insert_monitors(&self, monitors: Vec<Monitor>) -> Result<(), Error>;
+
+    /// Inserts monitor checks
+    async fn insert_monitor_checks(&self, checks: Vec<MonitorCheck>) -> Result<(), Error>;
+
+    /// Returns all the spans of a trace
+    async fn get_trace(&self, trace_id: u128) -> Result<TraceData, Error>;
+
+    /// Searches spans
+    async fn search_spans(&self, query: &SpanQuery) -> Result<TraceData, Error>;
+
+    /// Returns the service graph of a time range (UNIX nanoseconds)
+    ///
+    /// The graph is built from its metrics (see [ServiceGraph::to_metrics]).
+    async fn get_service_graph(&self, from: i128, to: i128) -> Result<ServiceGraph, Error> {
+        let mut data = MetricsData::default();
+        for name in [SERVICE_GRAPH_DURATION_METRIC, SERVICE_GRAPH_FAILED_METRIC] {
+            let query = MetricQuery::new().name(name).time_range(from, to);
+            data.metrics
+                .extend(self.search_metrics(&query).await?.metrics);
+        }
+        Ok(ServiceGraph::from_metrics(&data))
+    }
+
+    /// Searches logs
+    async fn search_logs(&self, query: &LogQuery) -> Result<LogData, Error>;
+
+    /// Searches metrics
+    async fn search_metrics(&self, query: &MetricQuery) -> Result<MetricsData, Error>;
+
+    /// Returns all the logs of a trace
+    async fn get_trace_logs(&self, trace_id: u128) -> Result<LogData, Error> {
+        self.search_logs(&LogQuery::new().trace(trace_id)).await
+    }
+
+    /// Returns all the monitors
+    async fn get_monitors(&self) -> Result<Vec<Monitor>, Error>;
+
+    /// Deletes the telemetry data older than a timestamp (UNIX nanoseconds)
+    ///
+    /// Monitors are kept, but their checks are deleted.
+    async fn delete_before(&self, timestamp: i128) -> Result<(), Error>;
 }
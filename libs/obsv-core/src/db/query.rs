@@ -0,0 +1,515 @@
+//! DB queries
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{AttrValue, Log, Metric, Service, Span},
+    error::Error,
+};
+
+/// A query to search spans
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpanQuery {
+    /// Service name
+    pub service: Option<String>,
+    /// Span name
+    pub name: Option<String>,
+    /// Start of the time range (UNIX nanoseconds, inclusive)
+    pub from: Option<i128>,
+    /// End of the time range (UNIX nanoseconds, exclusive)
+    pub to: Option<i128>,
+    /// Attribute filters
+    pub attrs: Vec<AttrFilter>,
+    /// Maximum number of spans
+    pub limit: Option<usize>,
+}
+
+impl SpanQuery {
+    /// Creates a new query, matching all spans
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the service name
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = Some(service.to_string());
+        self
+    }
+
+    /// Sets the span name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the time range (UNIX nanoseconds)
+    pub fn time_range(mut self, from: i128, to: i128) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// Adds an attribute filter
+    pub fn attr(mut self, filter: AttrFilter) -> Self {
+        self.attrs.push(filter);
+        self
+    }
+
+    /// Sets the maximum number of spans
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Checks that the query is supported by the DBs (see [AttrFilter::check])
+    pub fn check(&self) -> Result<(), Error> {
+        self.attrs.iter().try_for_each(AttrFilter::check)
+    }
+
+    /// Checks if a span matches the query
+    ///
+    /// The time range applies to the span start time.
+    pub fn matches(&self, service: &Service, span: &Span) -> bool {
+        self.service.as_ref().is_none_or(|s| *s == service.name)
+            && self.name.as_ref().is_none_or(|n| *n == span.name)
+            && in_range(span.start, self.from, self.to)
+            && self.attrs.iter().all(|f| f.matches(&span.attrs))
+    }
+}
+
+/// A query to search logs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogQuery {
+    /// Service name
+    pub service: Option<String>,
+    /// Trace ID
+    pub trace_id: Option<u128>,
+    /// Span ID
+    pub span_id: Option<u64>,
+    /// Text contained in the message
+    pub text: Option<String>,
+    /// Minimum level (severity)
+    pub min_level: Option<i16>,
+    /// Start of the time range (UNIX nanoseconds, inclusive)
+    pub from: Option<i128>,
+    /// End of the time range (UNIX nanoseconds, exclusive)
+    pub to: Option<i128>,
+    /// Attribute filters
+    pub attrs: Vec<AttrFilter>,
+    /// Maximum number of logs
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// Creates a new query, matching all logs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the service name
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = Some(service.to_string());
+        self
+    }
+
+    /// Sets the trace ID
+    pub fn trace(mut self, trace_id: u128) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    /// Sets the span ID
+    pub fn span(mut self, span_id: u64) -> Self {
+        self.span_id = Some(span_id);
+        self
+    }
+
+    /// Sets the text contained in the message
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Sets the minimum level
+    pub fn min_level(mut self, level: i16) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// Sets the time range (UNIX nanoseconds)
+    pub fn time_range(mut self, from: i128, to: i128) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// Adds an attribute filter
+    pub fn attr(mut self, filter: AttrFilter) -> Self {
+        self.attrs.push(filter);
+        self
+    }
+
+    /// Sets the maximum number of logs
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Checks that the query is supported by the DBs (see [AttrFilter::check])
+    pub fn check(&self) -> Result<(), Error> {
+        self.attrs.iter().try_for_each(AttrFilter::check)
+    }
+
+    /// Checks if a log matches the query
+    pub fn matches(&self, service: &Service, log: &Log) -> bool {
+        self.service.as_ref().is_none_or(|s| *s == service.name)
+            && self.trace_id.is_none_or(|t| t == log.trace_id)
+            && self.span_id.is_none_or(|s| s == log.span_id)
+            && self
+                .text
+                .as_ref()
+                .is_none_or(|t| log.message.contains(t.as_str()))
+            && self.min_level.is_none_or(|l| log.level >= l)
+            && in_range(log.timestamp, self.from, self.to)
+            && self.attrs.iter().all(|f| f.matches(&log.attrs))
+    }
+}
+
+/// A query to search metrics
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricQuery {
+    /// Service name
+    pub service: Option<String>,
+    /// Metric name
+    pub name: Option<String>,
+    /// Start of the time range (UNIX nanoseconds, inclusive)
+    pub from: Option<i128>,
+    /// End of the time range (UNIX nanoseconds, exclusive)
+    pub to: Option<i128>,
+    /// Maximum number of metrics
+    pub limit: Option<usize>,
+}
+
+impl MetricQuery {
+    /// Creates a new query, matching all metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the service name
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = Some(service.to_string());
+        self
+    }
+
+    /// Sets the metric name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the time range (UNIX nanoseconds)
+    pub fn time_range(mut self, from: i128, to: i128) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the maximum number of metrics
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Checks if a metric matches the query
+    ///
+    /// The time range applies to the timestamp of the last data point.
+    pub fn matches(&self, service: &Service, metric: &Metric) -> bool {
+        self.service.as_ref().is_none_or(|s| *s == service.name)
+            && self.name.as_ref().is_none_or(|n| *n == metric.name)
+            && in_range(
+                metric.data.last_timestamp().unwrap_or_default(),
+                self.from,
+                self.to,
+            )
+    }
+}
+
+/// An attribute filter
+///
+/// Values are only compared with attributes of the same kind, for all the operators
+/// (eg. an `Int` filter never matches a `Float` attribute, even with [AttrOp::Ne]).
+/// The filter values cannot be arrays or maps (see [AttrFilter::check]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttrFilter {
+    /// Attribute key
+    pub key: String,
+    /// Operator
+    pub op: AttrOp,
+    /// Value
+    pub value: AttrValue,
+}
+
+/// An attribute filter operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttrOp {
+    /// Attribute exists (the value is ignored)
+    Exists,
+    /// Equal
+    Eq,
+    /// Not equal
+    Ne,
+    /// Less than
+    Lt,
+    /// Less than or equal
+    Le,
+    /// Greater than
+    Gt,
+    /// Greater than or equal
+    Ge,
+    /// String attribute contains the value
+    Contains,
+}
+
+impl AttrFilter {
+    /// Creates a new attribute filter
+    pub fn new(key: &str, op: AttrOp, value: AttrValue) -> Self {
+        Self {
+            key: key.to_string(),
+            op,
+            value,
+        }
+    }
+
+    /// Creates a filter on the existence of an attribute
+    pub fn exists(key: &str) -> Self {
+        Self::new(key, AttrOp::Exists, AttrValue::None)
+    }
+
+    /// Creates a filter on the equality of an attribute
+    pub fn eq(key: &str, value: AttrValue) -> Self {
+        Self::new(key, AttrOp::Eq, value)
+    }
+
+    /// Checks that the filter is supported
+    ///
+    /// Except for [AttrOp::Exists], the value must be a scalar (not `None`, an array or a map),
+    /// and [AttrOp::Contains] requires a string.
+    pub fn check(&self) -> Result<(), Error> {
+        match (self.op, &self.value) {
+            (AttrOp::Exists, _) => Ok(()),
+            (_, AttrValue::None | AttrValue::Array(_) | AttrValue::Map(_)) => Err(Error::string(
+                format!("unsupported filter value for attribute '{}'", self.key),
+            )),
+            (AttrOp::Contains, value) if !matches!(value, AttrValue::String(_)) => {
+                Err(Error::string(format!(
+                    "'contains' filter on attribute '{}' requires a string",
+                    self.key
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks if attributes match the filter
+    ///
+    /// NB: an unsupported filter (see [AttrFilter::check]) never matches
+    pub fn matches(&self, attrs: &HashMap<String, AttrValue>) -> bool {
+        let value = match attrs.get(&self.key) {
+            Some(value) => value,
+            None => return false,
+        };
+        let ord = match (value, &self.value) {
+            (AttrValue::Bool(a), AttrValue::Bool(b)) => a.partial_cmp(b),
+            (AttrValue::Uint(a), AttrValue::Uint(b)) => a.partial_cmp(b),
+            (AttrValue::Int(a), AttrValue::Int(b)) => a.partial_cmp(b),
+            (AttrValue::Float(a), AttrValue::Float(b)) => a.partial_cmp(b),
+            (AttrValue::String(a), AttrValue::String(b)) => {
+                if self.op == AttrOp::Contains {
+                    return a.contains(b.as_str());
+                }
+                a.partial_cmp(b)
+            }
+            (AttrValue::Bytes(a), AttrValue::Bytes(b)) => a.partial_cmp(b),
+            _ => None,
+        };
+        match self.op {
+            AttrOp::Exists => true,
+            AttrOp::Eq => ord.is_some_and(|o| o.is_eq()),
+            AttrOp::Ne => ord.is_some_and(|o| o.is_ne()),
+            AttrOp::Lt => ord.is_some_and(|o| o.is_lt()),
+            AttrOp::Le => ord.is_some_and(|o| o.is_le()),
+            AttrOp::Gt => ord.is_some_and(|o| o.is_gt()),
+            AttrOp::Ge => ord.is_some_and(|o| o.is_ge()),
+            AttrOp::Contains => false,
+        }
+    }
+}
+
+/// Checks if a timestamp is in a time range
+fn in_range(timestamp: i128, from: Option<i128>, to: Option<i128>) -> bool {
+    from.is_none_or(|from| timestamp >= from) && to.is_none_or(|to| timestamp < to)
+}
+
+/// Attribute filter cases shared by the DB tests
+///
+/// The filters apply to the attributes of [test_filter_attrs].
+/// The expected result is the match, or `None` if the filter is not supported.
+#[cfg(test)]
+pub(crate) fn test_filter_cases() -> Vec<(AttrFilter, Option<bool>)> {
+    let string = |s: &str| AttrValue::String(s.to_string());
+    vec![
+        (AttrFilter::exists("status"), Some(true)),
+        (AttrFilter::exists("missing"), Some(false)),
+        (AttrFilter::exists("tags"), Some(true)),
+        (AttrFilter::eq("status", AttrValue::Int(500)), Some(true)),
+        (AttrFilter::eq("missing", AttrValue::Int(500)), Some(false)),
+        (
+            AttrFilter::new("status", AttrOp::Ne, AttrValue::Int(500)),
+            Some(false),
+        ),
+        (
+            AttrFilter::new("status", AttrOp::Ne, AttrValue::Int(404)),
+            Some(true),
+        ),
+        (
+            AttrFilter::new("missing", AttrOp::Ne, AttrValue::Int(404)),
+            Some(false),
+        ),
+        (
+            AttrFilter::eq("status", AttrValue::Float(500.0)),
+            Some(false),
+        ),
+        (
+            AttrFilter::new("status", AttrOp::Ne, AttrValue::Float(1.0)),
+            Some(false),
+        ),
+        (
+            AttrFilter::new("status", AttrOp::Ne, string("500")),
+            Some(false),
+        ),
+        (
+            AttrFilter::new("status", AttrOp::Ge, AttrValue::Int(500)),
+            Some(true),
+        ),
+        (
+            AttrFilter::new("status", AttrOp::Lt, AttrValue::Int(500)),
+            Some(false),
+        ),
+        (
+            AttrFilter::new("ratio", AttrOp::Lt, AttrValue::Float(1.0)),
+            Some(true),
+        ),
+        (
+            AttrFilter::new("path", AttrOp::Contains, string("users")),
+            Some(true),
+        ),
+        (
+            AttrFilter::new("path", AttrOp::Gt, string("/api")),
+            Some(true),
+        ),
+        (
+            AttrFilter::new("path", AttrOp::Contains, AttrValue::Int(1)),
+            None,
+        ),
+        (
+            AttrFilter::eq("tags", AttrValue::Array(vec![string("a")])),
+            None,
+        ),
+        (
+            AttrFilter::new("tags", AttrOp::Ne, AttrValue::Map(HashMap::new())),
+            None,
+        ),
+        (AttrFilter::eq("status", AttrValue::None), None),
+    ]
+}
+
+/// Returns the attributes of the filter cases (see [test_filter_cases])
+#[cfg(test)]
+pub(crate) fn test_filter_attrs() -> HashMap<String, AttrValue> {
+    HashMap::from([
+        ("status".to_string(), AttrValue::Int(500)),
+        ("ratio".to_string(), AttrValue::Float(0.5)),
+        (
+            "path".to_string(),
+            AttrValue::String("/api/users".to_string()),
+        ),
+        (
+            "tags".to_string(),
+            AttrValue::Array(vec![AttrValue::String("a".to_string())]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attr_filter_cases() {
+        let attrs = test_filter_attrs();
+        for (filter, expected) in test_filter_cases() {
+            assert_eq!(filter.check().is_ok(), expected.is_some(), "{filter:?}");
+            assert_eq!(
+                filter.matches(&attrs),
+                expected.unwrap_or_default(),
+                "{filter:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn attr_filter_matches() {
+        let attrs = HashMap::from([
+            ("status".to_string(), AttrValue::Int(500)),
+            (
+                "path".to_string(),
+                AttrValue::String("/api/users".to_string()),
+            ),
+        ]);
+
+        assert!(AttrFilter::exists("status").matches(&attrs));
+        assert!(!AttrFilter::exists("missing").matches(&attrs));
+        assert!(AttrFilter::eq("status", AttrValue::Int(500)).matches(&attrs));
+        assert!(AttrFilter::new("status", AttrOp::Ge, AttrValue::Int(500)).matches(&attrs));
+        assert!(!AttrFilter::new("status", AttrOp::Lt, AttrValue::Int(500)).matches(&attrs));
+        // NB: values of different kinds are not compared
+        assert!(!AttrFilter::eq("status", AttrValue::Float(500.0)).matches(&attrs));
+        assert!(!AttrFilter::new("status", AttrOp::Ne, AttrValue::Int(500)).matches(&attrs));
+        assert!(AttrFilter::new(
+            "path",
+            AttrOp::Contains,
+            AttrValue::String("users".to_string())
+        )
+        .matches(&attrs));
+    }
+
+    #[test]
+    fn span_query_matches() {
+        let service = Service {
+            name: "my_service".to_string(),
+            attrs: HashMap::new(),
+        };
+        let span = Span {
+            id: 1,
+            trace_id: 1,
+            name: "GET /".to_string(),
+            start: 10,
+            end: 20,
+            attrs: HashMap::from([("status".to_string(), AttrValue::Int(200))]),
+            ..Default::default()
+        };
+
+        assert!(SpanQuery::new().matches(&service, &span));
+        assert!(SpanQuery::new()
+            .service("my_service")
+            .name("GET /")
+            .time_range(10, 11)
+            .attr(AttrFilter::eq("status", AttrValue::Int(200)))
+            .matches(&service, &span));
+        assert!(!SpanQuery::new().service("other").matches(&service, &span));
+        assert!(!SpanQuery::new().time_range(0, 10).matches(&service, &span));
+    }
+}
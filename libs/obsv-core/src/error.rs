@@ -27,3 +27,10 @@ impl From<hex::FromHexError> for Error {
         Self::new(&value.to_string())
     }
 }
+
+#[cfg(feature = "clickhouse")]
+impl From<clickhouse_client::error::Error> for Error {
+    fn from(value: clickhouse_client::error::Error) -> Self {
+        Self::string(format!("Clickhouse error: {value}"))
+    }
+}
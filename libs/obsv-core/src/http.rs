@@ -20,6 +20,14 @@ pub struct TraceParentHeader {
 impl TraceParentHeader {
     /// Header name
     pub const NAME: &str = "traceparent";
+
+    /// Sampled flag
+    pub const SAMPLED: u8 = 0x01;
+
+    /// Checks if the sampled flag is set
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
 }
 
 impl std::fmt::Display for TraceParentHeader {
@@ -144,6 +152,18 @@ mod tests {
         assert_eq!(header_parsed, header);
     }
 
+    #[test]
+    fn http_trace_parent_sampled() {
+        let header = "00-00000000000000000000000000000001-0000000000000001-01"
+            .parse::<TraceParentHeader>()
+            .unwrap();
+        assert!(header.is_sampled());
+        let header = "00-00000000000000000000000000000001-0000000000000001-00"
+            .parse::<TraceParentHeader>()
+            .unwrap();
+        assert!(!header.is_sampled());
+    }
+
     #[test]
     fn http_trace_state_header() {
         let header = TraceStateHeader {
@@ -153,6 +153,21 @@ impl SemConv {
     /// Cloud platform (string)
     pub const CLOUD_PLATFORM: &str = "cloud.platform";
 
+    /// K8s cluster name (string)
+    pub const K8S_CLUSTER_NAME: &str = "k8s.cluster.name";
+
+    /// K8s node name (string)
+    pub const K8S_NODE_NAME: &str = "k8s.node.name";
+
+    /// K8s namespace name (string)
+    pub const K8S_NAMESPACE_NAME: &str = "k8s.namespace.name";
+
+    /// K8s pod name (string)
+    pub const K8S_POD_NAME: &str = "k8s.pod.name";
+
+    /// K8s pod uid (string)
+    pub const K8S_POD_UID: &str = "k8s.pod.uid";
+
     /// Browser brands (string[])
     pub const BROWSER_BRANDS: &str = "browser.brands";
 